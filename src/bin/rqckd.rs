@@ -0,0 +1,180 @@
+//! `rqckd` opens the pads once and serves the JSON-RPC API described in
+//! [`rgame_linux::rpc`] on a Unix socket.
+
+use std::fs::{self, Permissions};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use clap::{clap_app, value_t};
+use rusb::Context;
+
+use rgame_linux::cli::Backend;
+use rgame_linux::daemon::{self, Daemon};
+use rgame_linux::dbus;
+use rgame_linux::device::{self, PadInfo};
+use rgame_linux::openrgb;
+use rgame_linux::registry::Registry;
+use rgame_linux::state::StateCache;
+use rgame_linux::transport::Transport;
+use rgame_linux::{rpc, QckPad, Result};
+
+fn main() -> Result<()> {
+    let matches = clap_app!(rqckd =>
+        (about: "Keeps the SteelSeries QCK pads open and controls them through a JSON-RPC API on a Unix socket")
+        (@arg BACKEND: --backend +takes_value possible_values(&Backend::variants()) case_insensitive(true) default_value("libusb") "Talk to the pads through hidraw or by detaching the kernel driver with libusb")
+        (@arg SOCKET: --socket +takes_value "Path of the socket, defaults to $XDG_RUNTIME_DIR/rqck/rqckd.sock")
+        (@arg DBUS: --dbus "Also serves the pads on the D-Bus session bus as org.rqck")
+        (@arg OPENRGB: --openrgb value_name("ADDRESS") min_values(0) max_values(1) "Also serves the pads to OpenRGB SDK clients, on 127.0.0.1:6742 if no ADDRESS is given")
+    )
+    .get_matches();
+
+    let backend = value_t!(matches, "BACKEND", Backend).unwrap_or_else(|e| e.exit());
+    let socket = matches
+        .value_of("SOCKET")
+        .map_or_else(rpc::socket_path, PathBuf::from);
+    let frontends = Frontends {
+        session_bus: matches.is_present("DBUS"),
+        openrgb: if matches.is_present("OPENRGB") {
+            let default = SocketAddr::from((Ipv4Addr::LOCALHOST, openrgb::DEFAULT_PORT));
+            Some(
+                matches
+                    .value_of("OPENRGB")
+                    .map_or(Ok(default), str::parse)
+                    .unwrap_or_else(|e| {
+                        clap::Error::with_description(
+                            &format!("invalid OpenRGB address: {}", e),
+                            clap::ErrorKind::InvalidValue,
+                        )
+                        .exit()
+                    }),
+            )
+        } else {
+            None
+        },
+    };
+
+    let (registry, errors) = Registry::load();
+    for e in errors {
+        eprintln!("ignoring device definition {}", e);
+    }
+
+    match backend {
+        Backend::Hidraw => {
+            let pads = device::list_pads(None, registry.models())?;
+            let pads = open(pads, &registry, |info| {
+                let path = info.hidraw.as_ref().expect("hidraw pads have a node");
+                Ok(QckPad::open_hidraw_path(path)?)
+            });
+
+            serve(pads, &socket, &frontends)
+        }
+        Backend::Libusb => {
+            let context = Context::new()?;
+            let pads = device::list_pads(Some(&context), registry.models())?;
+            let pads = open(pads, &registry, |info| {
+                let (bus, address) = (info.bus.unwrap_or(0), info.address.unwrap_or(0));
+                Ok(QckPad::open_at(&context, bus, address)?)
+            });
+
+            serve(pads, &socket, &frontends)
+        }
+    }
+}
+
+/// Opens the pads with their capabilities and state cache, the ones that
+/// cannot be opened are skipped.
+fn open<T, F>(pads: Vec<PadInfo>, registry: &Registry, open: F) -> Vec<(PadInfo, QckPad<T>)>
+where
+    T: Transport,
+    F: Fn(&PadInfo) -> Result<QckPad<T>>,
+{
+    let mut opened = vec![];
+
+    for info in pads {
+        match open(&info) {
+            Ok(mut pad) => {
+                pad.use_capabilities(registry.model(info.vendor_id, info.product_id).capabilities);
+                pad.use_state_cache(StateCache::load(info.id()));
+                println!("opened {}", info.selector());
+                opened.push((info, pad));
+            }
+            Err(e) => eprintln!("could not open {}: {}", info.selector(), e),
+        }
+    }
+
+    opened
+}
+
+/// What serves the pads besides the socket.
+struct Frontends {
+    session_bus: bool,
+    openrgb: Option<SocketAddr>,
+}
+
+fn serve<T: Transport + Send + 'static>(
+    pads: Vec<(PadInfo, QckPad<T>)>,
+    socket: &Path,
+    frontends: &Frontends,
+) -> Result<()> {
+    if pads.is_empty() {
+        println!("could not find any pad");
+        return Ok(());
+    }
+
+    let daemon = Arc::new(Daemon::new(pads));
+    let listener = bind(socket)?;
+    println!("listening on {}", socket.display());
+
+    {
+        let daemon = daemon.clone();
+        thread::spawn(move || daemon::expire_layers(daemon));
+    }
+
+    let _bus = if frontends.session_bus {
+        let connection = dbus::serve(daemon.clone(), None)?;
+        println!("serving the pads on the session bus as {}", dbus::BUS_NAME);
+        Some(connection)
+    } else {
+        None
+    };
+
+    if let Some(address) = frontends.openrgb {
+        let openrgb_listener = TcpListener::bind(address)?;
+        println!("serving the pads to OpenRGB on {}", address);
+        let daemon = daemon.clone();
+        thread::spawn(move || openrgb::serve(daemon, openrgb_listener));
+    }
+
+    Ok(daemon::serve(daemon, listener)?)
+}
+
+/// Binds the socket, replacing the socket of a daemon that is not running
+/// anymore. Only the user may connect.
+fn bind(socket: &Path) -> io::Result<UnixListener> {
+    if UnixStream::connect(socket).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("rqckd is already running on {}", socket.display()),
+        ));
+    }
+
+    if let Ok(metadata) = fs::symlink_metadata(socket) {
+        if metadata.file_type().is_socket() {
+            fs::remove_file(socket)?;
+        }
+    }
+
+    if let Some(dir) = socket.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let listener = UnixListener::bind(socket)?;
+    fs::set_permissions(socket, Permissions::from_mode(0o600))?;
+
+    Ok(listener)
+}
@@ -0,0 +1,644 @@
+//! Reads control transfers from USB captures.
+//!
+//! Supported are the text interface of usbmon
+//! (`/sys/kernel/debug/usb/usbmon/*u`) as well as pcap and pcapng files
+//! written by Wireshark or tcpdump with the Linux usbmon link types or the
+//! USBPcap link type used on Windows. The URB headers are stripped, OUT
+//! transfers are taken from the submission and IN transfers from the
+//! completion.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::time::Duration;
+
+const LINKTYPE_USB_LINUX: u32 = 189;
+const LINKTYPE_USB_LINUX_MMAPPED: u32 = 220;
+const LINKTYPE_USBPCAP: u32 = 249;
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const PCAPNG_SIMPLE_PACKET: u32 = 0x0000_0003;
+const PCAPNG_ENHANCED_PACKET: u32 = 0x0000_0006;
+
+const XFER_CONTROL: u8 = 2;
+
+#[derive(Debug, PartialEq)]
+pub enum CaptureError {
+    Truncated(usize),
+    UnsupportedLinkType(u32),
+    InvalidPcapng(usize),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CaptureError::Truncated(offset) => {
+                write!(f, "capture is truncated at byte {}", offset)
+            }
+            CaptureError::UnsupportedLinkType(link_type) => write!(
+                f,
+                "link type {} is not supported, expected a usbmon or USBPcap capture",
+                link_type
+            ),
+            CaptureError::InvalidPcapng(offset) => {
+                write!(f, "invalid pcapng block at byte {}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+/// The setup packet of a control transfer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Setup {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl Setup {
+    fn from_bytes(data: &[u8]) -> Setup {
+        Setup {
+            request_type: data[0],
+            request: data[1],
+            value: u16::from_le_bytes([data[2], data[3]]),
+            index: u16::from_le_bytes([data[4], data[5]]),
+            length: u16::from_le_bytes([data[6], data[7]]),
+        }
+    }
+
+    pub fn is_in(&self) -> bool {
+        self.request_type & 0x80 != 0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlTransfer {
+    /// As recorded in the capture, usually since the epoch or since boot
+    pub timestamp: Duration,
+    pub bus: u16,
+    pub device: u8,
+    pub setup: Setup,
+    /// The captured data, may be shorter than `setup.length`
+    pub data: Vec<u8>,
+}
+
+impl ControlTransfer {
+    /// The capture holds less data than the transfer announced.
+    pub fn is_truncated(&self) -> bool {
+        self.data.len() < usize::from(self.setup.length)
+    }
+}
+
+/// Parses a capture, the format is detected from the first bytes.
+pub fn parse(data: &[u8]) -> Result<Vec<ControlTransfer>, CaptureError> {
+    match data.get(0..4).map(|magic| read_u32(magic, true)) {
+        Some(0xa1b2_c3d4) | Some(0xd4c3_b2a1) | Some(0xa1b2_3c4d) | Some(0x4d3c_b2a1) => {
+            parse_pcap(data)
+        }
+        Some(PCAPNG_SECTION_HEADER) => parse_pcapng(data),
+        _ => Ok(parse_usbmon_text(&String::from_utf8_lossy(data))),
+    }
+}
+
+enum UrbEvent {
+    Submit {
+        id: u64,
+        timestamp: Duration,
+        bus: u16,
+        device: u8,
+        setup: Setup,
+        data: Vec<u8>,
+    },
+    Complete {
+        id: u64,
+        data: Vec<u8>,
+    },
+}
+
+/// Pairs IN submissions with their completions.
+#[derive(Default)]
+struct Collector {
+    pending: HashMap<u64, ControlTransfer>,
+    transfers: Vec<ControlTransfer>,
+}
+
+impl Collector {
+    fn push(&mut self, event: UrbEvent) {
+        match event {
+            UrbEvent::Submit {
+                id,
+                timestamp,
+                bus,
+                device,
+                setup,
+                data,
+            } => {
+                let transfer = ControlTransfer {
+                    timestamp,
+                    bus,
+                    device,
+                    setup,
+                    data,
+                };
+
+                if setup.is_in() {
+                    self.pending.insert(id, transfer);
+                } else {
+                    self.transfers.push(transfer);
+                }
+            }
+            UrbEvent::Complete { id, data } => {
+                if let Some(mut transfer) = self.pending.remove(&id) {
+                    transfer.data = data;
+                    self.transfers.push(transfer);
+                }
+            }
+        }
+    }
+}
+
+/// Parses the text interface of usbmon. Lines that are not control transfers
+/// are skipped. Note that usbmon only prints the first 32 bytes of data.
+pub fn parse_usbmon_text(text: &str) -> Vec<ControlTransfer> {
+    let mut collector = Collector::default();
+
+    for line in text.lines() {
+        if let Some(event) = parse_usbmon_line(line) {
+            collector.push(event);
+        }
+    }
+
+    collector.transfers
+}
+
+// ffff8d6ac3c9e6c0 3791843910 S Co:1:003:0 s 21 09 0300 0000 020c 524 = 0e000200 ff0000ff ...
+fn parse_usbmon_line(line: &str) -> Option<UrbEvent> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let id = u64::from_str_radix(tokens.first()?, 16).ok()?;
+    let timestamp = Duration::from_micros(tokens.get(1)?.parse().ok()?);
+
+    let address: Vec<&str> = tokens.get(3)?.split(':').collect();
+    if !address[0].starts_with('C') {
+        return None;
+    }
+    // the bus is missing in the address of older kernels
+    let (bus, device) = match address.len() {
+        4 => (address[1].parse().ok()?, address[2].parse().ok()?),
+        3 => (0, address[1].parse().ok()?),
+        _ => return None,
+    };
+
+    let data = match tokens.iter().position(|token| *token == "=") {
+        Some(i) => decode_hex(&tokens[i + 1..].concat())?,
+        None => vec![],
+    };
+
+    match *tokens.get(2)? {
+        "S" if tokens.get(4) == Some(&"s") => {
+            let field = |i: usize| u16::from_str_radix(tokens.get(i)?, 16).ok();
+            let setup = Setup {
+                request_type: field(5)? as u8,
+                request: field(6)? as u8,
+                value: field(7)?,
+                index: field(8)?,
+                length: field(9)?,
+            };
+
+            Some(UrbEvent::Submit {
+                id,
+                timestamp,
+                bus,
+                device,
+                setup,
+                data,
+            })
+        }
+        "C" => Some(UrbEvent::Complete { id, data }),
+        _ => None,
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn read_u16(data: &[u8], little_endian: bool) -> u16 {
+    let bytes = data[..2].try_into().unwrap();
+    if little_endian {
+        u16::from_le_bytes(bytes)
+    } else {
+        u16::from_be_bytes(bytes)
+    }
+}
+
+fn read_u32(data: &[u8], little_endian: bool) -> u32 {
+    let bytes = data[..4].try_into().unwrap();
+    if little_endian {
+        u32::from_le_bytes(bytes)
+    } else {
+        u32::from_be_bytes(bytes)
+    }
+}
+
+fn read_u64(data: &[u8], little_endian: bool) -> u64 {
+    let bytes = data[..8].try_into().unwrap();
+    if little_endian {
+        u64::from_le_bytes(bytes)
+    } else {
+        u64::from_be_bytes(bytes)
+    }
+}
+
+fn slice(data: &[u8], offset: usize, len: usize) -> Result<&[u8], CaptureError> {
+    data.get(offset..offset + len)
+        .ok_or(CaptureError::Truncated(data.len()))
+}
+
+fn parse_pcap(data: &[u8]) -> Result<Vec<ControlTransfer>, CaptureError> {
+    let header = slice(data, 0, 24)?;
+    let little_endian = matches!(read_u32(header, true), 0xa1b2_c3d4 | 0xa1b2_3c4d);
+    let nanoseconds = matches!(read_u32(header, little_endian), 0xa1b2_3c4d);
+    let link_type = read_u32(&header[20..], little_endian);
+
+    let mut collector = Collector::default();
+    let mut offset = 24;
+    while offset < data.len() {
+        let record = slice(data, offset, 16)?;
+        let seconds = u64::from(read_u32(record, little_endian));
+        let fraction = u64::from(read_u32(&record[4..], little_endian));
+        let captured = read_u32(&record[8..], little_endian) as usize;
+
+        let timestamp = if nanoseconds {
+            Duration::from_secs(seconds) + Duration::from_nanos(fraction)
+        } else {
+            Duration::from_secs(seconds) + Duration::from_micros(fraction)
+        };
+
+        let packet = slice(data, offset + 16, captured)?;
+        if let Some(event) = parse_packet(link_type, packet, timestamp, little_endian)? {
+            collector.push(event);
+        }
+
+        offset += 16 + captured;
+    }
+
+    Ok(collector.transfers)
+}
+
+/// Timestamps of enhanced packet blocks are assumed to be in microseconds,
+/// the default resolution.
+fn parse_pcapng(data: &[u8]) -> Result<Vec<ControlTransfer>, CaptureError> {
+    let mut collector = Collector::default();
+    let mut link_types: Vec<u32> = vec![];
+    let mut little_endian = true;
+
+    let mut offset = 0;
+    while offset < data.len() {
+        let header = slice(data, offset, 12)?;
+
+        // the type of the section header reads the same in both byte orders,
+        // the types of the blocks in the section are in its byte order
+        let block_type = if read_u32(header, true) == PCAPNG_SECTION_HEADER {
+            little_endian = read_u32(&header[8..], true) == 0x1a2b_3c4d;
+            link_types.clear();
+            PCAPNG_SECTION_HEADER
+        } else {
+            read_u32(header, little_endian)
+        };
+
+        let len = read_u32(&header[4..], little_endian) as usize;
+        if len < 12 || !len.is_multiple_of(4) {
+            return Err(CaptureError::InvalidPcapng(offset));
+        }
+        let body = slice(data, offset + 8, len - 12)?;
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => {
+                let body = slice(body, 0, 2)?;
+                link_types.push(u32::from(read_u16(body, little_endian)));
+            }
+            PCAPNG_ENHANCED_PACKET => {
+                slice(body, 0, 20)?;
+                let interface = read_u32(body, little_endian) as usize;
+                let high = u64::from(read_u32(&body[4..], little_endian));
+                let low = u64::from(read_u32(&body[8..], little_endian));
+                let captured = read_u32(&body[12..], little_endian) as usize;
+
+                let link_type = *link_types
+                    .get(interface)
+                    .ok_or(CaptureError::InvalidPcapng(offset))?;
+                let timestamp = Duration::from_micros((high << 32) | low);
+                let packet = slice(body, 20, captured)?;
+
+                if let Some(event) = parse_packet(link_type, packet, timestamp, little_endian)? {
+                    collector.push(event);
+                }
+            }
+            PCAPNG_SIMPLE_PACKET => {
+                let link_type = *link_types
+                    .first()
+                    .ok_or(CaptureError::InvalidPcapng(offset))?;
+                let captured = read_u32(slice(body, 0, 4)?, little_endian) as usize;
+                let packet = &body[4..];
+                let packet = &packet[..captured.min(packet.len())];
+
+                if let Some(event) =
+                    parse_packet(link_type, packet, Duration::default(), little_endian)?
+                {
+                    collector.push(event);
+                }
+            }
+            _ => {}
+        }
+
+        offset += len;
+    }
+
+    Ok(collector.transfers)
+}
+
+fn parse_packet(
+    link_type: u32,
+    packet: &[u8],
+    timestamp: Duration,
+    little_endian: bool,
+) -> Result<Option<UrbEvent>, CaptureError> {
+    match link_type {
+        LINKTYPE_USB_LINUX => Ok(parse_usbmon_packet(packet, 48, little_endian)),
+        LINKTYPE_USB_LINUX_MMAPPED => Ok(parse_usbmon_packet(packet, 64, little_endian)),
+        LINKTYPE_USBPCAP => Ok(parse_usbpcap_packet(packet, timestamp)),
+        _ => Err(CaptureError::UnsupportedLinkType(link_type)),
+    }
+}
+
+/// The binary header of usbmon, `header_len` is 64 for the mmapped variant
+/// which appends isochronous fields.
+fn parse_usbmon_packet(packet: &[u8], header_len: usize, little_endian: bool) -> Option<UrbEvent> {
+    let header = packet.get(..header_len)?;
+    if header[9] != XFER_CONTROL {
+        return None;
+    }
+
+    let id = read_u64(header, little_endian);
+    let device = header[11];
+    let bus = read_u16(&header[12..], little_endian);
+    let seconds = read_u64(&header[16..], little_endian);
+    let micros = read_u32(&header[24..], little_endian);
+    let captured = read_u32(&header[36..], little_endian) as usize;
+
+    let data = &packet[header_len..];
+    let data = data[..captured.min(data.len())].to_vec();
+
+    match header[8] {
+        // flag_setup is 0 if the setup packet is present
+        b'S' if header[14] == 0 => Some(UrbEvent::Submit {
+            id,
+            timestamp: Duration::from_secs(seconds) + Duration::from_micros(u64::from(micros)),
+            bus,
+            device,
+            setup: Setup::from_bytes(&header[40..48]),
+            data,
+        }),
+        b'C' => Some(UrbEvent::Complete { id, data }),
+        _ => None,
+    }
+}
+
+/// The pseudo header of USBPcap. The setup stage holds the setup packet
+/// followed by the data of OUT transfers, IN data arrives with the completion
+/// of the data stage.
+fn parse_usbpcap_packet(packet: &[u8], timestamp: Duration) -> Option<UrbEvent> {
+    let header_len = usize::from(read_u16(packet.get(..2)?, true));
+    let header = packet.get(..header_len.max(28))?;
+    if header[22] != XFER_CONTROL {
+        return None;
+    }
+
+    let id = read_u64(&header[2..], true);
+    let completion = header[16] & 0x01 != 0;
+    let bus = read_u16(&header[17..], true);
+    let device = read_u16(&header[19..], true) as u8;
+    let stage = header[27];
+    let data = packet.get(header_len..)?;
+
+    match (stage, completion) {
+        (0, false) if data.len() >= 8 => Some(UrbEvent::Submit {
+            id,
+            timestamp,
+            bus,
+            device,
+            setup: Setup::from_bytes(&data[..8]),
+            data: data[8..].to_vec(),
+        }),
+        (1, true) => Some(UrbEvent::Complete {
+            id,
+            data: data.to_vec(),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The usbmon mmapped header and the beginning of the zone color report
+    /// that used to be sent by `set_solid_color`.
+    const SOLID_URB: [u8; 92] = [
+        0x80, 0xb6, 0xdc, 0x42, 0x9f, 0x89, 0xff, 0xff, 0x53, 0x02, 0x00, 0x03, 0x01, 0x00, 0x00,
+        0x00, 0xee, 0xfe, 0x9e, 0x5f, 0x00, 0x00, 0x00, 0x00, 0x8d, 0x18, 0x08, 0x00, 0x8d, 0xff,
+        0xff, 0xff, 0x0c, 0x02, 0x00, 0x00, 0x0c, 0x02, 0x00, 0x00, 0x21, 0x09, 0x00, 0x03, 0x00,
+        0x00, 0x0c, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x0e, 0x00, 0x02, 0x00, 0xff, 0x00, 0x00, 0xff, 0x32, 0xc8, 0x00,
+        0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x32, 0xc8, 0x00, 0x00, 0x01, 0x01,
+        0x00, 0x01,
+    ];
+
+    fn pcap(link_type: u32, packets: &[&[u8]]) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+        data.extend_from_slice(&[2, 0, 4, 0]);
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&65535u32.to_le_bytes());
+        data.extend_from_slice(&link_type.to_le_bytes());
+
+        for packet in packets {
+            data.extend_from_slice(&[0; 8]);
+            data.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            data.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            data.extend_from_slice(packet);
+        }
+        data
+    }
+
+    fn to_bytes(value: u32, little_endian: bool) -> [u8; 4] {
+        if little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        }
+    }
+
+    fn pcapng_block(block_type: u32, body: &[u8], little_endian: bool) -> Vec<u8> {
+        let mut body = body.to_vec();
+        body.resize(body.len().div_ceil(4) * 4, 0);
+        let len = (body.len() + 12) as u32;
+
+        let mut block = vec![];
+        block.extend_from_slice(&to_bytes(block_type, little_endian));
+        block.extend_from_slice(&to_bytes(len, little_endian));
+        block.extend_from_slice(&body);
+        block.extend_from_slice(&to_bytes(len, little_endian));
+        block
+    }
+
+    /// A section with an interface and an enhanced packet block of `urb`.
+    fn pcapng(urb: &[u8], little_endian: bool) -> Vec<u8> {
+        let mut section = vec![];
+        section.extend_from_slice(&to_bytes(0x1a2b_3c4d, little_endian));
+        section.extend_from_slice(if little_endian {
+            &[1, 0, 0, 0]
+        } else {
+            &[0, 1, 0, 0]
+        });
+        section.extend_from_slice(&[0xff; 8]);
+
+        let link_type = LINKTYPE_USB_LINUX_MMAPPED as u16;
+        let mut interface = vec![];
+        if little_endian {
+            interface.extend_from_slice(&link_type.to_le_bytes());
+        } else {
+            interface.extend_from_slice(&link_type.to_be_bytes());
+        }
+        interface.extend_from_slice(&[0, 0]);
+        interface.extend_from_slice(&to_bytes(0, little_endian));
+
+        let mut packet = vec![0; 12];
+        packet.extend_from_slice(&to_bytes(urb.len() as u32, little_endian));
+        packet.extend_from_slice(&to_bytes(urb.len() as u32, little_endian));
+        packet.extend_from_slice(urb);
+
+        let mut data = pcapng_block(PCAPNG_SECTION_HEADER, &section, little_endian);
+        data.extend(pcapng_block(
+            PCAPNG_INTERFACE_DESCRIPTION,
+            &interface,
+            little_endian,
+        ));
+        data.extend(pcapng_block(PCAPNG_ENHANCED_PACKET, &packet, little_endian));
+        data
+    }
+
+    #[test]
+    fn strips_usbmon_header_in_pcap() {
+        let transfers = parse(&pcap(LINKTYPE_USB_LINUX_MMAPPED, &[&SOLID_URB])).unwrap();
+
+        assert_eq!(transfers.len(), 1);
+        let transfer = &transfers[0];
+        assert_eq!((transfer.bus, transfer.device), (1, 3));
+        assert_eq!(
+            transfer.setup,
+            Setup {
+                request_type: 0x21,
+                request: 0x09,
+                value: 0x0300,
+                index: 0,
+                length: 524,
+            }
+        );
+        assert_eq!(transfer.data, SOLID_URB[64..]);
+        assert!(transfer.is_truncated());
+    }
+
+    #[test]
+    fn reads_pcapng() {
+        let transfers = parse(&pcapng(&SOLID_URB, true)).unwrap();
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].data, SOLID_URB[64..]);
+    }
+
+    #[test]
+    fn reads_big_endian_pcapng() {
+        // usbmon writes its header in the byte order of the host, the setup
+        // packet and the data are the same on every host
+        let mut urb = SOLID_URB;
+        for (start, len) in [(0, 8), (12, 2), (16, 8), (24, 4), (28, 4), (32, 4), (36, 4)] {
+            urb[start..start + len].reverse();
+        }
+
+        let transfers = parse(&pcapng(&urb, false)).unwrap();
+        assert_eq!(transfers.len(), 1);
+        assert_eq!((transfers[0].bus, transfers[0].device), (1, 3));
+        assert_eq!(transfers[0].setup.length, 524);
+        assert_eq!(transfers[0].data, SOLID_URB[64..]);
+    }
+
+    #[test]
+    fn reads_usbmon_text() {
+        let text = "\
+ffff9f8942dcb680 3791843910 S Co:1:003:0 s 21 09 0300 0000 020c 524 = 0e000200 ff0000ff 32c80000 00010000 000000ff 32c80000 01010001 00000000
+ffff9f8942dcb680 3791845012 C Co:1:003:0 0 524 >
+ffff9f8942dcb6c0 3791846001 S Ii:1:003:1 -115:1 64 <
+ffff9f8942dcb700 3791850000 S Ci:1:003:0 s a1 01 0200 0000 0040 64 <
+ffff9f8942dcb700 3791850400 C Ci:1:003:0 0 64 = 0c00ff00 00000000
+";
+        let transfers = parse(text.as_bytes()).unwrap();
+
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(transfers[0].setup.value, 0x0300);
+        assert_eq!(transfers[0].data[..28], SOLID_URB[64..]);
+        assert_eq!(transfers[0].timestamp, Duration::from_micros(3791843910));
+        assert_eq!(transfers[1].setup.request_type, 0xa1);
+        assert_eq!(transfers[1].data, vec![0x0c, 0x00, 0xff, 0x00, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn reads_usbpcap() {
+        let header = |irp: u64, info: u8, stage: u8| {
+            let mut header = vec![];
+            header.extend_from_slice(&28u16.to_le_bytes());
+            header.extend_from_slice(&irp.to_le_bytes());
+            header.extend_from_slice(&[0; 4]); // status
+            header.extend_from_slice(&[0x08, 0x00]); // function
+            header.push(info);
+            header.extend_from_slice(&1u16.to_le_bytes());
+            header.extend_from_slice(&5u16.to_le_bytes());
+            header.push(0x80);
+            header.push(XFER_CONTROL);
+            header.extend_from_slice(&[0; 4]); // data length
+            header.push(stage);
+            header
+        };
+
+        let mut setup = header(7, 0, 0);
+        setup.extend_from_slice(&[0xa1, 0x01, 0x00, 0x02, 0x00, 0x00, 0x40, 0x00]);
+        let mut data = header(7, 1, 1);
+        data.extend_from_slice(&[0x0c, 0x00, 0x80]);
+
+        let transfers = parse(&pcap(LINKTYPE_USBPCAP, &[&setup, &data])).unwrap();
+
+        assert_eq!(transfers.len(), 1);
+        assert_eq!((transfers[0].bus, transfers[0].device), (1, 5));
+        assert_eq!(transfers[0].setup.value, 0x0200);
+        assert_eq!(transfers[0].data, vec![0x0c, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn rejects_other_link_types() {
+        assert_eq!(
+            parse(&pcap(1, &[&[0u8; 14]])),
+            Err(CaptureError::UnsupportedLinkType(1))
+        );
+    }
+}
@@ -0,0 +1,541 @@
+//! The command line interface of `rgame_linux`.
+//!
+//! [`run`] executes the parsed arguments against any [`Pad`]: a [`crate::QckPad`],
+//! which can also be tested with a [`crate::transport::MockTransport`], or a
+//! pad opened by `rqckd`.
+
+use clap::{arg_enum, clap_app, value_t, values_t, App, ArgMatches};
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::config::{self, Config, ProfileConfig};
+use crate::device::DeviceSelector;
+use crate::effects::{self, EffectName, Playback, MAX_FPS};
+use crate::report::{ZoneColorReport, ZoneEntry};
+use crate::timeline::{Easing, Plan, Step, Timeline};
+use crate::transition::{self, Fade};
+use crate::units::{parse_duration, Brightness, Curve, Intensity, Speed};
+use crate::{capture, decode};
+use crate::{
+    ColorRGB, ColorShift, EmulatedBreathe, Error, KeyFrame, Pad, Result, Zone, BLACK_COLOR,
+};
+
+arg_enum! {
+    /// How the pad is accessed
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub enum Backend {
+        Hidraw,
+        Libusb
+    }
+}
+
+fn is_device(value: String) -> std::result::Result<(), String> {
+    value
+        .parse::<DeviceSelector>()
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn parse_usb_id(value: &str) -> Option<u16> {
+    u16::from_str_radix(value.trim_start_matches("0x"), 16).ok()
+}
+
+fn is_usb_id(value: String) -> std::result::Result<(), String> {
+    match parse_usb_id(&value) {
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "'{}' is not a USB id, expected hex like 150d",
+            value
+        )),
+    }
+}
+
+fn is_fps(value: String) -> std::result::Result<(), String> {
+    match value.parse::<u32>() {
+        Ok(fps) if (1..=MAX_FPS).contains(&fps) => Ok(()),
+        _ => Err(format!(
+            "'{}' is not a frame rate between 1 and {}",
+            value, MAX_FPS
+        )),
+    }
+}
+
+pub fn app() -> App<'static, 'static> {
+    clap_app!(myapp =>
+        (about: "Controls your Steelseries QCK Cloth mousepad")
+        (@arg BACKEND: --backend +takes_value +global possible_values(&Backend::variants()) case_insensitive(true) default_value("libusb") "Talk to the pad through hidraw or by detaching the kernel driver with libusb")
+        (@arg DEVICE: -D --device +takes_value +global {is_device} "Pad to use as serial, BUS:ADDRESS or hidraw path, see list")
+        (@arg ALL: --all +global "Runs the command on all pads, or all pads matching --device")
+        (@arg VID: --vid +takes_value +global requires[PID] {is_usb_id} "Vendor id in hex of a pad that is not supported yet, used together with --pid")
+        (@arg PID: --pid +takes_value +global requires[VID] {is_usb_id} "Product id in hex of a pad that is not supported yet")
+        (@arg SOCKET: --socket +takes_value +global "Socket of rqckd, defaults to $XDG_RUNTIME_DIR/rqck/rqckd.sock")
+        (@arg NO_DAEMON: --("no-daemon") +global "Accesses the pad directly even if rqckd is running")
+        (@arg CONFIG: --config +takes_value +global "Configuration file with the profiles, defaults to ~/.config/rqck/config.toml")
+        (@subcommand set_intensity =>
+            (about: "Sets the intensity of the LEDs")
+            (@arg INTENSITY: +required +takes_value -i --intensity "value between 0 and 100 for the intensity, between 0 and 255 with --raw")
+            (@arg CURVE: -c --curve +takes_value possible_values(&Curve::variants()) case_insensitive(true) conflicts_with[RAW] "Maps the percentage linearly, with gamma 2.2 or as CIE L* lightness, defaults to linear")
+            (@arg RAW: --raw "Sends INTENSITY to the pad as it is")
+            (@arg FADE: --fade +takes_value "Fades from the current intensity over this time, e.g. 500ms")
+            (@arg EASING: --easing +takes_value requires[FADE] possible_values(&["linear", "ease-in", "ease-out", "ease-in-out"]) "Easing of the fade, defaults to ease-in-out")
+        )
+
+        (@subcommand disable =>
+            (about: "Disables one of the two zones of the LEDs on your pad")
+            (@arg ZONE: +required ... "Zones you want to disable")
+        )
+
+        (@subcommand solid =>
+            (about: "Sets a single color per zone")
+            (@arg COLORS: +required ... "RED GREEN BLUE [RED2 GREEN2 BLUE2] or one to two colors like ff5200, #f50, rgb(255, 82, 0), hsl(19, 100%, 50%), orange or 3200K, the first one is used for the upper zone")
+            (@arg ZONE: -z --zone +takes_value possible_values(&Zone::variants()) case_insensitive(true) "Only changes this zone and keeps the other one")
+            (@arg BRIGHTNESS: -b --brightness +takes_value default_value("100%") "Brightness of the colors in percent")
+            (@arg SPEED: -s --speed +takes_value default_value("50") "Speed between 0 and 255")
+            (@arg DURATION: -d --duration +takes_value default_value("200ms") "Duration of the color, e.g. 200ms or 1.5s")
+            (@arg FADE: --fade +takes_value "Fades from the current colors over this time, e.g. 500ms")
+            (@arg EASING: --easing +takes_value requires[FADE] possible_values(&["linear", "ease-in", "ease-out", "ease-in-out"]) "Easing of the fade, defaults to ease-in-out")
+        )
+
+        (@subcommand colorshift =>
+            (about: "Lets each zone cycle through a list of colors")
+            (@arg SPEED: -s --speed +takes_value default_value("50") "Speed of the cycle between 0 and 255")
+            (@arg UPPER: -u --upper +takes_value use_delimiter(false) required_unless("LOWER") "Keyframes of the upper zone as COLOR:DURATION, e.g. ff0000:500ms,blue:1.5s")
+            (@arg LOWER: -l --lower +takes_value use_delimiter(false) "Keyframes of the lower zone, defaults to the ones of the upper zone")
+        )
+
+        (@subcommand breathe =>
+            (about: "Lets each zone breathe in a list of colors, emulated with ColorShift keyframes")
+            (@arg PERIOD: -p --period +takes_value default_value("4s") "Length of a single breath, e.g. 4s or 2500ms")
+            (@arg UPPER: -u --upper +takes_value use_delimiter(false) required_unless("LOWER") "Colors of the upper zone, e.g. ff0000,blue")
+            (@arg LOWER: -l --lower +takes_value use_delimiter(false) "Colors of the lower zone, defaults to the ones of the upper zone")
+        )
+
+        (@subcommand effect =>
+            (about: "Plays an effect computed on this computer until it is stopped with Ctrl-C")
+            (@arg NAME: +required possible_values(&EffectName::variants()) case_insensitive(true) "Effect to play")
+            (@arg COLOR: -c --color +takes_value "Color of the effect, rainbow and police have their own colors")
+            (@arg PERIOD: -p --period +takes_value "Length of one cycle, e.g. 2s, the default depends on the effect")
+            (@arg FPS: --fps +takes_value {is_fps} default_value("30") "Frames per second, between 1 and 60")
+            (@arg STOP_AFTER: --("stop-after") +takes_value "Stops the effect after this time, e.g. 10s")
+        )
+
+        (@subcommand timeline =>
+            (about: "Fades each zone through a list of colors, played by the pad itself if it can, otherwise until it is stopped with Ctrl-C")
+            (@arg UPPER: -u --upper +takes_value use_delimiter(false) required_unless("LOWER") "Steps of the upper zone as COLOR:DURATION[:EASING], e.g. red:1s:ease-in-out,blue:500ms")
+            (@arg LOWER: -l --lower +takes_value use_delimiter(false) "Steps of the lower zone, defaults to the ones of the upper zone")
+            (@arg STREAM: --stream "Computes the frames on this computer even if the pad could play the timeline")
+            (@arg FPS: --fps +takes_value {is_fps} default_value("30") "Frames per second when streaming, between 1 and 60")
+            (@arg STOP_AFTER: --("stop-after") +takes_value "Stops streaming after this time, e.g. 10s")
+        )
+
+        (@subcommand status =>
+            (about: "Shows the current mode and colors of the pad")
+            (@arg JSON: --json "Prints the status as JSON")
+        )
+
+        (@subcommand profile =>
+            (about: "Applies, lists and saves the profiles in the configuration file")
+            (@setting SubcommandRequiredElseHelp)
+            (@subcommand apply =>
+                (about: "Applies a profile")
+                (@arg NAME: +required "Name of the profile")
+                (@arg FADE: --fade +takes_value "Fades from the current colors over this time, e.g. 500ms")
+                (@arg EASING: --easing +takes_value requires[FADE] possible_values(&["linear", "ease-in", "ease-out", "ease-in-out"]) "Easing of the fade, defaults to ease-in-out")
+            )
+            (@subcommand list =>
+                (about: "Lists the profiles, no pad needed")
+            )
+            (@subcommand save =>
+                (about: "Saves the current state of the pad as a profile, replacing a profile with the same name")
+                (@arg NAME: +required "Name of the profile")
+            )
+        )
+
+        (@subcommand watch =>
+            (about: "Waits for pads to be plugged in and reapplies their last configuration")
+        )
+
+        (@subcommand list =>
+            (about: "Lists all connected pads")
+        )
+
+        (@subcommand decode =>
+            (about: "Prints the reports sent to the pad in a usbmon log or a pcap/pcapng capture, no pad needed")
+            (@arg FILE: +required "usbmon text log or pcap/pcapng file")
+        )
+    )
+}
+
+/// The ids given with `--vid` and `--pid`.
+pub fn usb_ids(matches: &ArgMatches) -> Option<(u16, u16)> {
+    let vid = parse_usb_id(matches.value_of("VID")?)?;
+    let pid = parse_usb_id(matches.value_of("PID")?)?;
+
+    Some((vid, pid))
+}
+
+/// Runs the subcommand in `matches`. Invalid values are reported as
+/// [`Error::Cli`].
+pub fn run<P: Pad>(pad: &mut P, matches: &ArgMatches) -> Result<()> {
+    if let Some(matches) = matches.subcommand_matches("set_intensity") {
+        let value = matches.value_of("INTENSITY").unwrap();
+        let intensity = if matches.is_present("RAW") {
+            value.parse::<Intensity>().map_err(usage)?
+        } else {
+            let curve = value_t!(matches, "CURVE", Curve).unwrap_or(Curve::Linear);
+            Intensity::parse_percent(value, curve).map_err(usage)?
+        };
+
+        match parse_fade(matches)? {
+            Some(fade) => {
+                transition::fade(pad, &ZoneColorReport::new(), Some(intensity.raw()), &fade)?
+            }
+            None => pad.set_intensity(intensity.raw())?,
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("disable") {
+        let zones = values_t!(matches, "ZONE", Zone).map_err(Error::Cli)?;
+
+        for zone in zones {
+            let mut update = ZoneColorReport::new();
+            update.push(ZoneEntry::new(zone.index(), BLACK_COLOR))?;
+            pad.update_zones(&update)?;
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("solid") {
+        let colors = parse_colors(matches)?;
+        let upper_zone_color = colors[0];
+
+        let builder = ZoneColorReport::builder()
+            .brightness(parse_value::<Brightness>(matches, "BRIGHTNESS")?)
+            .speed(parse_value::<Speed>(matches, "SPEED")?)
+            .duration(parse_duration(matches.value_of("DURATION").unwrap()).map_err(usage)?);
+
+        let builder = match (value_t!(matches, "ZONE", Zone), colors.get(1)) {
+            (Ok(_), Some(_)) => return Err(usage("--zone takes a single color")),
+            (Ok(zone), None) => builder.entry(zone.index(), upper_zone_color),
+            // without a second color the lower zone keeps its configuration
+            (Err(_), None) => builder.entry(Zone::Upper.index(), upper_zone_color),
+            (Err(_), Some(lower_zone_color)) => builder
+                .entry(Zone::Lower.index(), *lower_zone_color)
+                .entry(Zone::Upper.index(), upper_zone_color),
+        };
+
+        let report = builder.build().map_err(usage)?;
+
+        match parse_fade(matches)? {
+            Some(fade) => transition::fade(pad, &report, None, &fade)?,
+            None => pad.update_zones(&report)?,
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("colorshift") {
+        let speed = parse_value::<Speed>(matches, "SPEED")?;
+        let upper = parse_list::<KeyFrame>(matches, "UPPER")?;
+        let lower = parse_list::<KeyFrame>(matches, "LOWER")?;
+
+        let (upper, lower) = match (upper, lower) {
+            (Some(upper), Some(lower)) => (upper, lower),
+            (Some(upper), None) => (upper.clone(), upper),
+            (None, Some(lower)) => (lower.clone(), lower),
+            (None, None) => unreachable!("clap requires UPPER or LOWER"),
+        };
+
+        match ColorShift::new(speed, lower, upper) {
+            Ok(color_shift) => pad.set_zone_colors(&color_shift.to_report())?,
+            Err(e) => return Err(usage(e)),
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("breathe") {
+        let period = parse_duration(matches.value_of("PERIOD").unwrap()).map_err(usage)?;
+        let upper = parse_list::<ColorRGB>(matches, "UPPER")?;
+        let lower = parse_list::<ColorRGB>(matches, "LOWER")?;
+
+        let (upper, lower) = match (upper, lower) {
+            (Some(upper), Some(lower)) => (upper, lower),
+            (Some(upper), None) => (upper.clone(), upper),
+            (None, Some(lower)) => (lower.clone(), lower),
+            (None, None) => unreachable!("clap requires UPPER or LOWER"),
+        };
+
+        match EmulatedBreathe::new(period, lower, upper) {
+            Ok(breathe) => pad.set_zone_colors(&breathe.to_report())?,
+            Err(e) => return Err(usage(e)),
+        }
+    }
+
+    if let Some(profile) = matches.subcommand_matches("profile") {
+        if let Some(apply) = profile.subcommand_matches("apply") {
+            let name = apply.value_of("NAME").unwrap();
+            let config = Config::load(&config_path(matches)?)?;
+
+            let profile = config.profile(name)?.to_profile(name)?;
+
+            match parse_fade(apply)? {
+                Some(fade) => {
+                    let update = profile.zone_colors()?.unwrap_or_default();
+                    transition::fade(pad, &update, profile.intensity, &fade)?
+                }
+                None => pad.apply_profile(&profile)?,
+            }
+        }
+
+        if let Some(save) = profile.subcommand_matches("save") {
+            let name = save.value_of("NAME").unwrap();
+            let path = config_path(matches)?;
+            let profile = ProfileConfig::from(pad.status()?);
+
+            if config::save_profile(&path, name, &profile)? {
+                println!("replaced profile {} in {}", name, path.display());
+            } else {
+                println!("saved profile {} to {}", name, path.display());
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("effect") {
+        let name = value_t!(matches, "NAME", EffectName).map_err(Error::Cli)?;
+        let color = match matches.value_of("COLOR") {
+            Some(color) => Some(color.parse::<ColorRGB>().map_err(usage)?),
+            None => None,
+        };
+        let period = parse_optional_duration(matches, "PERIOD")?;
+        if period == Some(Duration::ZERO) {
+            return Err(usage("the period must be longer than 0ms"));
+        }
+
+        let playback = Playback {
+            fps: parse_value(matches, "FPS")?,
+            zones: pad.capabilities().zones,
+            length: parse_optional_duration(matches, "STOP_AFTER")?,
+        };
+
+        if playback.length.is_none() {
+            println!(
+                "playing {}, stop with Ctrl-C",
+                name.to_string().to_lowercase()
+            );
+        }
+        effects::play_until_interrupted(pad, &mut *name.build(color, period), &playback)?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("timeline") {
+        let upper = parse_list::<Step>(matches, "UPPER")?;
+        let lower = parse_list::<Step>(matches, "LOWER")?;
+
+        let (upper, lower) = match (upper, lower) {
+            (Some(upper), Some(lower)) => (upper, lower),
+            (Some(upper), None) => (upper.clone(), upper),
+            (None, Some(lower)) => (lower.clone(), lower),
+            (None, None) => unreachable!("clap requires UPPER or LOWER"),
+        };
+
+        let mut timeline = Timeline::new(vec![lower, upper]).map_err(usage)?;
+        let plan = if matches.is_present("STREAM") {
+            Plan::Stream {
+                reason: "as asked with --stream".to_string(),
+            }
+        } else {
+            timeline.compile(&pad.capabilities())
+        };
+
+        let playback = Playback {
+            fps: parse_value(matches, "FPS")?,
+            zones: pad.capabilities().zones,
+            length: parse_optional_duration(matches, "STOP_AFTER")?,
+        };
+
+        match plan {
+            Plan::Stream { .. } if playback.length.is_none() => {
+                println!("{}, stop with Ctrl-C", plan)
+            }
+            _ => println!("{}", plan),
+        }
+        timeline.play(pad, &plan, &playback)?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("status") {
+        let status = pad.status()?;
+
+        if matches.is_present("JSON") {
+            println!("{}", serde_json::to_string_pretty(&status).unwrap());
+        } else {
+            print!("{}", status);
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the `decode` subcommand, which works without a pad.
+pub fn decode(matches: &ArgMatches) -> Result<()> {
+    let data = fs::read(matches.value_of("FILE").unwrap())?;
+    let transfers = capture::parse(&data)?;
+
+    let mut found = false;
+    for decoded in transfers.iter().filter_map(decode::decode) {
+        println!("{}", decoded);
+        found = true;
+    }
+
+    if !found {
+        println!("no reports found in {} control transfers", transfers.len());
+    }
+
+    Ok(())
+}
+
+/// Runs `profile list`, which works without a pad.
+pub fn list_profiles(matches: &ArgMatches) -> Result<()> {
+    let path = config_path(matches)?;
+    let config = Config::load(&path)?;
+
+    if config.profiles.is_empty() {
+        println!("no profiles in {}", path.display());
+    }
+
+    for (name, profile) in &config.profiles {
+        match profile.mode {
+            Some(mode) => println!("{} ({})", name, mode),
+            None => println!("{}", name),
+        }
+    }
+
+    Ok(())
+}
+
+/// The file given with `--config` or the default one.
+fn config_path(matches: &ArgMatches) -> Result<PathBuf> {
+    match matches.value_of("CONFIG") {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => Config::path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no configuration directory").into()
+        }),
+    }
+}
+
+fn usage<E: Display>(e: E) -> Error {
+    Error::Cli(clap::Error::value_validation_auto(e.to_string()))
+}
+
+/// Colors are either given as decimal RED GREEN BLUE triples or in any syntax
+/// of [`crate::color`].
+fn parse_colors(matches: &ArgMatches) -> Result<Vec<ColorRGB>> {
+    let values: Vec<&str> = matches.values_of("COLORS").unwrap().collect();
+
+    let decimal = values[0].bytes().all(|b| b.is_ascii_digit());
+    match values.len() {
+        3 | 6 if decimal => values.chunks(3).map(parse_decimal_color).collect(),
+        1 | 2 => Ok(parse_values::<ColorRGB>(matches, "COLORS")?.unwrap()),
+        _ => Err(usage(
+            "expected RED GREEN BLUE [RED2 GREEN2 BLUE2] or one to two colors",
+        )),
+    }
+}
+
+fn parse_decimal_color(rgb: &[&str]) -> Result<ColorRGB> {
+    let component = |value: &str, name: &str| {
+        value.parse::<u8>().map_err(|_| {
+            usage(format!(
+                "'{}' is not a number between 0 and 255 for {}",
+                value, name
+            ))
+        })
+    };
+
+    Ok(ColorRGB::new(
+        component(rgb[0], "RED")?,
+        component(rgb[1], "GREEN")?,
+        component(rgb[2], "BLUE")?,
+    ))
+}
+
+fn parse_value<T>(matches: &ArgMatches, arg: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    matches.value_of(arg).unwrap().parse::<T>().map_err(usage)
+}
+
+fn parse_optional_duration(matches: &ArgMatches, arg: &str) -> Result<Option<Duration>> {
+    match matches.value_of(arg) {
+        Some(duration) => Ok(Some(parse_duration(duration).map_err(usage)?)),
+        None => Ok(None),
+    }
+}
+
+/// `None` without `--fade` or with a fade of 0ms.
+fn parse_fade(matches: &ArgMatches) -> Result<Option<Fade>> {
+    let duration = match parse_optional_duration(matches, "FADE")? {
+        Some(duration) if duration > Duration::ZERO => duration,
+        _ => return Ok(None),
+    };
+    let easing = match matches.value_of("EASING") {
+        Some(easing) => easing.parse::<Easing>().map_err(usage)?,
+        None => Easing::EaseInOut,
+    };
+
+    Ok(Some(Fade { duration, easing }))
+}
+
+fn parse_values<T>(matches: &ArgMatches, arg: &str) -> Result<Option<Vec<T>>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let values = match matches.values_of(arg) {
+        Some(values) => values,
+        None => return Ok(None),
+    };
+
+    let parsed = values
+        .map(|value| value.parse::<T>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(usage)?;
+
+    Ok(Some(parsed))
+}
+
+/// A comma separated list, commas within parentheses like in
+/// `rgb(255, 0, 0):500ms,blue:1s` do not separate the values.
+fn parse_list<T>(matches: &ArgMatches, arg: &str) -> Result<Option<Vec<T>>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let list = match matches.value_of(arg) {
+        Some(list) => list,
+        None => return Ok(None),
+    };
+
+    let mut values = vec![];
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in list.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                values.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    values.push(&list[start..]);
+
+    let parsed = values
+        .into_iter()
+        .map(|value| value.trim().parse::<T>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(usage)?;
+
+    Ok(Some(parsed))
+}
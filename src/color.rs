@@ -0,0 +1,717 @@
+//! The syntax of colors, wherever a color is given: on the command line, in
+//! profiles and through `rqckd`.
+//!
+//! - hex colors: `#ff5200`, `ff5200` and `#f50`
+//! - `rgb(255, 82, 0)`, also with percentages or separated by spaces like
+//!   `rgb(100% 32% 0%)`
+//! - `hsl(19, 100%, 50%)` and `hsv(19, 100%, 100%)`, the hue in degrees
+//! - CSS color names like `orange` or `rebeccapurple` and X11 color names
+//!   like `navy blue` or `gray50`
+//! - color temperatures between 1000K and 40000K like `3200K`
+//!
+//! Errors name the part of the color that could not be parsed.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::commands::ColorRGB;
+
+const MIN_KELVIN: u32 = 1000;
+const MAX_KELVIN: u32 = 40000;
+
+#[derive(Debug, PartialEq)]
+pub struct ParseColorError {
+    input: String,
+    /// The bad part of `input`, all of it if the color is not recognized
+    span: Range<usize>,
+    reason: String,
+}
+
+impl ParseColorError {
+    fn new<R: ToString>(input: &str, span: Range<usize>, reason: R) -> Self {
+        ParseColorError {
+            input: input.to_string(),
+            span,
+            reason: reason.to_string(),
+        }
+    }
+
+    /// The color that was given
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Where in [`ParseColorError::input`] the error is
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let token = &self.input[self.span.clone()];
+
+        if token == self.input.trim() {
+            write!(f, "'{}' {}", self.input, self.reason)
+        } else {
+            write!(
+                f,
+                "'{}' is not a color, '{}' {}",
+                self.input, token, self.reason
+            )
+        }
+    }
+}
+
+impl std::error::Error for ParseColorError {}
+
+/// Parses a color in any of the forms of the module documentation.
+pub fn parse(input: &str) -> Result<ColorRGB, ParseColorError> {
+    let start = input.len() - input.trim_start().len();
+    let s = input.trim();
+    let span = start..start + s.len();
+
+    if s.is_empty() {
+        return Err(ParseColorError::new(input, span, "is not a color"));
+    }
+
+    if let Some(hex) = s.strip_prefix('#') {
+        return parse_hex(hex).ok_or_else(|| {
+            ParseColorError::new(input, span, "is not a hex color, expected #RRGGBB or #RGB")
+        });
+    }
+
+    if let Some(open) = s.find('(') {
+        return parse_function(input, start, open);
+    }
+
+    if let Some(kelvin) = s.strip_suffix(['K', 'k']) {
+        if !kelvin.is_empty() && kelvin.bytes().all(|b| b.is_ascii_digit()) {
+            return match kelvin.parse::<u32>() {
+                Ok(kelvin) if (MIN_KELVIN..=MAX_KELVIN).contains(&kelvin) => {
+                    Ok(from_kelvin(kelvin))
+                }
+                _ => Err(ParseColorError::new(
+                    input,
+                    span,
+                    format!(
+                        "is not a color temperature between {}K and {}K",
+                        MIN_KELVIN, MAX_KELVIN
+                    ),
+                )),
+            };
+        }
+    }
+
+    if s.len() == 6 {
+        if let Some(color) = parse_hex(s) {
+            return Ok(color);
+        }
+    }
+
+    if let Some(color) = named(s) {
+        return Ok(color);
+    }
+
+    let reason = match suggest(s) {
+        Some(name) => format!("is not a known color name, did you mean '{}'?", name),
+        None => "is not a color, expected a hex color, rgb(), hsl(), hsv(), a color name or a temperature like 3200K"
+            .to_string(),
+    };
+    Err(ParseColorError::new(input, span, reason))
+}
+
+/// `RRGGBB` or `RGB`, without the `#`.
+fn parse_hex(hex: &str) -> Option<ColorRGB> {
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok();
+    let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+
+    match hex.len() {
+        // #f50 is #ff5500
+        3 => Some(ColorRGB::new(
+            digit(0)? * 0x11,
+            digit(1)? * 0x11,
+            digit(2)? * 0x11,
+        )),
+        6 => Some(ColorRGB::new(component(0)?, component(2)?, component(4)?)),
+        _ => None,
+    }
+}
+
+/// `rgb(...)`, `hsl(...)` or `hsv(...)`, `open` is the index of the
+/// parenthesis in the trimmed input.
+fn parse_function(input: &str, start: usize, open: usize) -> Result<ColorRGB, ParseColorError> {
+    let s = input.trim();
+    let name = s[..open].trim();
+    let name_span = start..start + s[..open].trim_end().len();
+
+    let close = match s.strip_suffix(')') {
+        Some(inner) if !inner[open + 1..].contains(')') => s.len() - 1,
+        _ => {
+            let end = start + s.len();
+            return Err(ParseColorError::new(
+                input,
+                start + open..end,
+                "is missing the closing parenthesis",
+            ));
+        }
+    };
+
+    let args = arguments(&s[open + 1..close], start + open + 1);
+    let bad = |span: &Range<usize>, reason: &str| ParseColorError::new(input, span.clone(), reason);
+
+    let expected = |args: &[(Range<usize>, &str)], what: &str| {
+        if args.len() > 3 {
+            Err(ParseColorError::new(
+                input,
+                args[3].0.start..args[args.len() - 1].0.end,
+                format!("is too much, expected only {}", what),
+            ))
+        } else if args.len() < 3 {
+            Err(ParseColorError::new(
+                input,
+                start + open..start + close + 1,
+                format!("is missing values, expected {}", what),
+            ))
+        } else {
+            Ok(())
+        }
+    };
+
+    match name.to_ascii_lowercase().as_str() {
+        "rgb" => {
+            expected(&args, "red, green and blue")?;
+
+            let mut components = [0u8; 3];
+            for (component, (span, arg)) in components.iter_mut().zip(&args) {
+                *component = match arg.strip_suffix('%') {
+                    Some(percent) => percentage(percent)
+                        .map(|percent| (percent * 2.55).round() as u8)
+                        .ok_or_else(|| bad(span, "is not a percentage between 0% and 100%"))?,
+                    None => arg
+                        .parse::<u8>()
+                        .map_err(|_| bad(span, "is not a number between 0 and 255"))?,
+                };
+            }
+
+            Ok(ColorRGB::new(components[0], components[1], components[2]))
+        }
+        function @ ("hsl" | "hsv") => {
+            let last = if function == "hsl" {
+                "lightness"
+            } else {
+                "value"
+            };
+            expected(&args, &format!("hue, saturation and {}", last))?;
+
+            let (hue_span, hue) = &args[0];
+            let hue = hue.strip_suffix("deg").unwrap_or(hue);
+            let hue = hue
+                .parse::<f64>()
+                .ok()
+                .filter(|hue| hue.is_finite())
+                .ok_or_else(|| bad(hue_span, "is not a hue in degrees"))?;
+
+            let mut percents = [0.0; 2];
+            for (percent, (span, arg)) in percents.iter_mut().zip(&args[1..]) {
+                *percent = percentage(arg.strip_suffix('%').unwrap_or(arg))
+                    .ok_or_else(|| bad(span, "is not a percentage between 0% and 100%"))?
+                    / 100.0;
+            }
+
+            Ok(if function == "hsl" {
+                from_hsl(hue, percents[0], percents[1])
+            } else {
+                from_hsv(hue, percents[0], percents[1])
+            })
+        }
+        _ => Err(ParseColorError::new(
+            input,
+            name_span,
+            "is not a color function, expected rgb, hsl or hsv",
+        )),
+    }
+}
+
+/// The arguments separated by commas or spaces, with their position in the
+/// input.
+fn arguments(s: &str, offset: usize) -> Vec<(Range<usize>, &str)> {
+    let mut args = vec![];
+    let mut start = None;
+
+    for (i, c) in s.char_indices().chain(std::iter::once((s.len(), ' '))) {
+        let separator = c == ',' || c.is_whitespace();
+
+        match (start, separator) {
+            (None, false) => start = Some(i),
+            (Some(first), true) => {
+                args.push((offset + first..offset + i, &s[first..i]));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+
+    args
+}
+
+fn percentage(s: &str) -> Option<f64> {
+    s.parse::<f64>()
+        .ok()
+        .filter(|percent| (0.0..=100.0).contains(percent))
+}
+
+fn to_u8(component: f64) -> u8 {
+    (component * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// `red`, `green` and `blue` between 0 and 1 for a hue in degrees and the
+/// chroma and offset of HSL and HSV.
+fn from_hue(hue: f64, chroma: f64, offset: f64) -> ColorRGB {
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+
+    let (red, green, blue) = match sector as u8 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    ColorRGB::new(
+        to_u8(red + offset),
+        to_u8(green + offset),
+        to_u8(blue + offset),
+    )
+}
+
+fn from_hsl(hue: f64, saturation: f64, lightness: f64) -> ColorRGB {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    from_hue(hue, chroma, lightness - chroma / 2.0)
+}
+
+pub(crate) fn from_hsv(hue: f64, saturation: f64, value: f64) -> ColorRGB {
+    let chroma = value * saturation;
+    from_hue(hue, chroma, value - chroma)
+}
+
+/// The color of a black body at the temperature, after the approximation of
+/// Tanner Helland.
+pub(crate) fn from_kelvin(kelvin: u32) -> ColorRGB {
+    let temperature = f64::from(kelvin) / 100.0;
+
+    let red = if temperature <= 66.0 {
+        255.0
+    } else {
+        329.698727446 * (temperature - 60.0).powf(-0.1332047592)
+    };
+    let green = if temperature <= 66.0 {
+        99.4708025861 * temperature.ln() - 161.1195681661
+    } else {
+        288.1221695283 * (temperature - 60.0).powf(-0.0755148492)
+    };
+    let blue = if temperature >= 66.0 {
+        255.0
+    } else if temperature <= 19.0 {
+        0.0
+    } else {
+        138.5177312231 * (temperature - 10.0).ln() - 305.0447927307
+    };
+
+    let component = |value: f64| value.round().clamp(0.0, 255.0) as u8;
+    ColorRGB::new(component(red), component(green), component(blue))
+}
+
+/// The color in OKLab as `[L, a, b]`, where equal distances look about
+/// equally different.
+pub(crate) fn to_oklab(color: ColorRGB) -> [f64; 3] {
+    let linear = |component: u8| {
+        let c = f64::from(component) / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let (r, g, b) = (linear(color.red), linear(color.green), linear(color.blue));
+
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+/// The inverse of [`to_oklab`], colors outside of sRGB are clipped.
+pub(crate) fn from_oklab([lightness, a, b]: [f64; 3]) -> ColorRGB {
+    let l = (lightness + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m = (lightness - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s = (lightness - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+
+    let encode = |c: f64| {
+        let c = c.clamp(0.0, 1.0);
+        if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
+    };
+
+    ColorRGB::new(
+        to_u8(encode(
+            4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        )),
+        to_u8(encode(
+            -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        )),
+        to_u8(encode(
+            -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+        )),
+    )
+}
+
+/// Names are compared without case, spaces, dashes and underscores, so
+/// `Light Sea Green` is `lightseagreen`.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '_'))
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn named(name: &str) -> Option<ColorRGB> {
+    let name = normalize(name);
+
+    // X11 gray0 to gray100
+    if let Some(level) = name
+        .strip_prefix("gray")
+        .or_else(|| name.strip_prefix("grey"))
+        .and_then(|level| level.parse::<u8>().ok())
+        .filter(|level| *level <= 100)
+    {
+        let gray = (f64::from(level) * 2.55).round() as u8;
+        return Some(ColorRGB::new(gray, gray, gray));
+    }
+
+    let index = NAMES
+        .binary_search_by(|(known, _)| known.cmp(&name.as_str()))
+        .ok()?;
+    let rgb = NAMES[index].1;
+
+    Some(ColorRGB::new(
+        (rgb >> 16) as u8,
+        (rgb >> 8) as u8,
+        rgb as u8,
+    ))
+}
+
+/// The known name closest to a misspelled one, if there is one close enough.
+fn suggest(name: &str) -> Option<&'static str> {
+    let name = normalize(name);
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    NAMES
+        .iter()
+        .map(|(known, _)| (distance(&name, known), *known))
+        .filter(|(distance, known)| *distance <= 2 && *distance < known.len() / 2)
+        .min()
+        .map(|(_, known)| known)
+}
+
+/// The Levenshtein distance of two ASCII strings.
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.as_bytes();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.bytes().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// The CSS color names and the X11 names that are not CSS names, sorted.
+/// `gray`, `green`, `maroon` and `purple` are the CSS colors, the X11 ones
+/// are `x11gray`, `x11green`, `x11maroon` and `x11purple`.
+const NAMES: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrod", 0xeedd82),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslateblue", 0x8470ff),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("navyblue", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("violetred", 0xd02090),
+    ("webgray", 0x808080),
+    ("webgreen", 0x008000),
+    ("webgrey", 0x808080),
+    ("webmaroon", 0x800000),
+    ("webpurple", 0x800080),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("x11gray", 0xbebebe),
+    ("x11green", 0x00ff00),
+    ("x11grey", 0xbebebe),
+    ("x11maroon", 0xb03060),
+    ("x11purple", 0xa020f0),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color(s: &str) -> ColorRGB {
+        parse(s).unwrap()
+    }
+
+    fn error(s: &str) -> String {
+        parse(s).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn oklab_round_trips() {
+        for color in [
+            ColorRGB::new(0x00, 0x00, 0x00),
+            ColorRGB::new(0xff, 0xff, 0xff),
+            ColorRGB::new(0xff, 0x55, 0x00),
+            ColorRGB::new(0x20, 0xb2, 0xaa),
+        ] {
+            assert_eq!(from_oklab(to_oklab(color)), color);
+        }
+
+        let [lightness, a, b] = to_oklab(ColorRGB::new(0xff, 0xff, 0xff));
+        assert!((lightness - 1.0).abs() < 1e-3 && a.abs() < 1e-3 && b.abs() < 1e-3);
+    }
+
+    #[test]
+    fn names_are_sorted() {
+        assert!(NAMES.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn parses_every_syntax() {
+        let orange = ColorRGB::new(0xff, 0x55, 0x00);
+        assert_eq!(color("#ff5500"), orange);
+        assert_eq!(color("FF5500"), orange);
+        assert_eq!(color("#f50"), orange);
+        assert_eq!(color("rgb(255, 85, 0)"), orange);
+        assert_eq!(color(" RGB(100% 33.3% 0%) "), orange);
+        assert_eq!(color("hsl(20, 100%, 50%)"), orange);
+        assert_eq!(color("hsv(20deg 100 100)"), orange);
+        assert_eq!(color("hsl(-340, 100%, 50%)"), orange);
+
+        assert_eq!(color("orange"), ColorRGB::new(0xff, 0xa5, 0x00));
+        assert_eq!(color("Light Sea Green"), ColorRGB::new(0x20, 0xb2, 0xaa));
+        assert_eq!(color("navy_blue"), ColorRGB::new(0x00, 0x00, 0x80));
+        assert_eq!(color("gray50"), ColorRGB::new(0x7f, 0x7f, 0x7f));
+        assert_eq!(color("grey100"), ColorRGB::new(0xff, 0xff, 0xff));
+        // the hex color, not a name
+        assert_eq!(color("beaded"), ColorRGB::new(0xbe, 0xad, 0xed));
+
+        assert_eq!(color("6600K"), ColorRGB::new(0xff, 0xff, 0xff));
+        assert_eq!(color("3200k"), ColorRGB::new(0xff, 0xb8, 0x7b));
+        assert_eq!(color("1000K"), ColorRGB::new(0xff, 0x44, 0x00));
+    }
+
+    #[test]
+    fn errors_point_at_the_bad_part() {
+        assert_eq!(
+            error("rgb(255, 3OO, 0)"),
+            "'rgb(255, 3OO, 0)' is not a color, '3OO' is not a number between 0 and 255"
+        );
+        assert_eq!(parse("rgb(255, 3OO, 0)").unwrap_err().span(), 9..12);
+        assert_eq!(
+            error("rgb(255, 0)"),
+            "'rgb(255, 0)' is not a color, '(255, 0)' is missing values, expected red, green and blue"
+        );
+        assert_eq!(
+            error("rgb(1, 2, 3, 4, 5)"),
+            "'rgb(1, 2, 3, 4, 5)' is not a color, '4, 5' is too much, expected only red, green and blue"
+        );
+        assert_eq!(
+            error("hsl(20, 100%, 150%)"),
+            "'hsl(20, 100%, 150%)' is not a color, '150%' is not a percentage between 0% and 100%"
+        );
+        assert_eq!(
+            error("hsx(1, 2, 3)"),
+            "'hsx(1, 2, 3)' is not a color, 'hsx' is not a color function, expected rgb, hsl or hsv"
+        );
+        assert_eq!(
+            error("rgb(1, 2, 3"),
+            "'rgb(1, 2, 3' is not a color, '(1, 2, 3' is missing the closing parenthesis"
+        );
+        assert_eq!(
+            error("#ff550"),
+            "'#ff550' is not a hex color, expected #RRGGBB or #RGB"
+        );
+        assert_eq!(
+            error("500K"),
+            "'500K' is not a color temperature between 1000K and 40000K"
+        );
+        assert_eq!(
+            error("ornage"),
+            "'ornage' is not a known color name, did you mean 'orange'?"
+        );
+        assert!(error("12345").ends_with("or a temperature like 3200K"));
+        assert_eq!(error(""), "'' is not a color");
+    }
+}
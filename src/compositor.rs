@@ -0,0 +1,226 @@
+//! Layers of color that `rqckd` shows on top of the configuration of a pad.
+//!
+//! Every source, e.g. a notification or a CPU alert, sets its own named
+//! layer. The layers are blended over the colors of the configuration, the
+//! base scene, in the order of their priority. The result is written as one
+//! steady color per zone. Once the last layer is removed or has expired the
+//! configuration is shown again, also if it was changed meanwhile.
+
+use std::time::Instant;
+
+use serde::Deserialize;
+
+use crate::report::ZoneColorReport;
+use crate::{ColorRGB, BLACK_COLOR};
+
+/// How a layer is combined with the colors below it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Blend {
+    /// The color of the layer replaces the one below
+    #[default]
+    Normal,
+    /// The colors are added, e.g. to brighten the base scene
+    Add,
+    /// The colors are multiplied, e.g. to tint or darken the base scene
+    Multiply,
+}
+
+/// A color over one or all zones.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub color: ColorRGB,
+    /// All zones if `None`
+    pub zone: Option<u8>,
+    /// Layers with a higher priority are above the others
+    pub priority: i32,
+    /// Between 0 (invisible) and 1
+    pub opacity: f64,
+    pub blend: Blend,
+    /// The layer is kept until it is removed if `None`
+    pub expires: Option<Instant>,
+}
+
+impl Layer {
+    /// An opaque layer over all zones that is kept until it is removed.
+    pub fn new(color: ColorRGB) -> Self {
+        Layer {
+            color,
+            zone: None,
+            priority: 0,
+            opacity: 1.0,
+            blend: Blend::Normal,
+            expires: None,
+        }
+    }
+
+    fn covers(&self, zone: u8) -> bool {
+        self.zone.is_none_or(|z| z == zone)
+    }
+
+    fn blend_over(&self, below: ColorRGB) -> ColorRGB {
+        let opacity = self.opacity.clamp(0.0, 1.0);
+        let component = |below: u8, above: u8| {
+            let (below, above) = (f64::from(below) / 255.0, f64::from(above) / 255.0);
+            let blended = match self.blend {
+                Blend::Normal => above,
+                Blend::Add => (below + above).min(1.0),
+                Blend::Multiply => below * above,
+            };
+
+            ((below + (blended - below) * opacity) * 255.0).round() as u8
+        };
+
+        ColorRGB::new(
+            component(below.red, self.color.red),
+            component(below.green, self.color.green),
+            component(below.blue, self.color.blue),
+        )
+    }
+}
+
+/// The layers of one pad.
+#[derive(Clone, Debug, Default)]
+pub struct Compositor {
+    /// In the order they were first set, which decides between layers of
+    /// the same priority
+    layers: Vec<(String, Layer)>,
+}
+
+impl Compositor {
+    pub fn new() -> Self {
+        Compositor::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Sets the layer, a layer with the same name is replaced.
+    pub fn set(&mut self, name: &str, layer: Layer) {
+        match self.layers.iter_mut().find(|(n, _)| n == name) {
+            Some((_, existing)) => *existing = layer,
+            None => self.layers.push((name.to_string(), layer)),
+        }
+    }
+
+    /// Returns `false` if there is no layer with the name.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.layers.len();
+        self.layers.retain(|(n, _)| n != name);
+        self.layers.len() != count
+    }
+
+    /// Removes the layers that expired before `now`, returns `false` if
+    /// none did.
+    pub fn expire(&mut self, now: Instant) -> bool {
+        let count = self.layers.len();
+        self.layers
+            .retain(|(_, layer)| layer.expires.is_none_or(|expires| expires > now));
+        self.layers.len() != count
+    }
+
+    /// The layers blended over the first color of every zone of `base`, one
+    /// color per zone starting with zone 0.
+    pub fn compose(&self, base: Option<&ZoneColorReport>, zones: u8) -> Vec<ColorRGB> {
+        let mut layers: Vec<&Layer> = self.layers.iter().map(|(_, layer)| layer).collect();
+        layers.sort_by_key(|layer| layer.priority);
+
+        (0..zones)
+            .map(|zone| {
+                let below = base
+                    .and_then(|base| base.entries().iter().find(|entry| entry.zone == zone))
+                    .map_or(BLACK_COLOR, |entry| entry.color);
+
+                layers
+                    .iter()
+                    .filter(|layer| layer.covers(zone))
+                    .fold(below, |below, layer| layer.blend_over(below))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn layers_are_blended_in_the_order_of_their_priority() {
+        let base = ZoneColorReport::steady(
+            ColorRGB::new(0x00, 0x00, 0xff),
+            ColorRGB::new(0x80, 0x80, 0x80),
+        );
+        let mut compositor = Compositor::new();
+        assert_eq!(
+            compositor.compose(Some(&base), 2),
+            vec![
+                ColorRGB::new(0x00, 0x00, 0xff),
+                ColorRGB::new(0x80, 0x80, 0x80)
+            ]
+        );
+
+        compositor.set(
+            "alert",
+            Layer {
+                zone: Some(1),
+                priority: 10,
+                opacity: 0.5,
+                ..Layer::new(ColorRGB::new(0xff, 0x00, 0x00))
+            },
+        );
+        compositor.set(
+            "tint",
+            Layer {
+                blend: Blend::Multiply,
+                ..Layer::new(ColorRGB::new(0xff, 0x80, 0xff))
+            },
+        );
+        // the tint is applied first, then half of the alert over the upper zone
+        assert_eq!(
+            compositor.compose(Some(&base), 2),
+            vec![
+                ColorRGB::new(0x00, 0x00, 0xff),
+                ColorRGB::new(0xc0, 0x20, 0x40)
+            ]
+        );
+
+        compositor.set(
+            "tint",
+            Layer {
+                blend: Blend::Add,
+                ..Layer::new(ColorRGB::new(0x00, 0x80, 0x00))
+            },
+        );
+        assert_eq!(
+            compositor.compose(Some(&base), 2)[0],
+            ColorRGB::new(0x00, 0x80, 0xff)
+        );
+
+        assert!(compositor.remove("tint"));
+        assert!(!compositor.remove("tint"));
+        assert_eq!(
+            compositor.compose(None, 2),
+            vec![BLACK_COLOR, ColorRGB::new(0x80, 0x00, 0x00)]
+        );
+    }
+
+    #[test]
+    fn expired_layers_are_removed() {
+        let now = Instant::now();
+        let mut compositor = Compositor::new();
+        compositor.set(
+            "flash",
+            Layer {
+                expires: Some(now + Duration::from_secs(2)),
+                ..Layer::new(ColorRGB::new(0xff, 0xff, 0xff))
+            },
+        );
+        compositor.set("cpu", Layer::new(ColorRGB::new(0xff, 0x00, 0x00)));
+
+        assert!(!compositor.expire(now + Duration::from_secs(1)));
+        assert!(compositor.expire(now + Duration::from_secs(2)));
+        assert!(!compositor.is_empty());
+    }
+}
@@ -0,0 +1,549 @@
+//! Named profiles in `~/.config/rqck/config.toml` (or
+//! `$XDG_CONFIG_HOME/rqck/config.toml`).
+//!
+//! Every profile sets a mode and optionally the intensity. The values are
+//! written like the arguments of the subcommands:
+//!
+//! ```toml
+//! [profiles.work]
+//! mode = "steady"
+//! upper = "ff5200"
+//! lower = "000000"
+//! intensity = 60
+//!
+//! [profiles.gaming]
+//! mode = "colorshift"
+//! speed = 100
+//! upper = ["ff0000:500ms", "00ff00:500ms", "0000ff:500ms"]
+//!
+//! [profiles.chill]
+//! mode = "breathe"
+//! period = "6s"
+//! upper = ["ff0000", "0000ff"]
+//! lower = ["00ff00"]
+//!
+//! [profiles.off]
+//! mode = "off"
+//! ```
+//!
+//! `mode = "keyframes"` takes the `zones` in the format of `status --json`
+//! instead, `profile save` writes profiles in this form. A profile without
+//! a mode only sets the intensity.
+//!
+//! `raw_intensity` sets the raw value between 0 and 255 instead of
+//! `intensity`. `profile save` writes it, a percentage could not hold every
+//! raw value.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use toml_edit::{DocumentMut, Item};
+
+use crate::modes::{ColorShift, EmulatedBreathe, KeyFrame};
+use crate::paths::config_dir;
+use crate::profile::Profile;
+use crate::report::ZoneColorReport;
+use crate::status::{zone_name, DeviceState, ZoneState};
+use crate::units::{parse_duration, Brightness, Curve, Intensity, Speed};
+use crate::{ColorRGB, Zone, BLACK_COLOR};
+
+/// Period of `breathe` if none is given, like the subcommand
+const DEFAULT_PERIOD: &str = "4s";
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    /// The file is not valid TOML or has unknown keys
+    Parse { path: PathBuf, message: String },
+    /// The profile is not in the file, with the names of the profiles that are
+    UnknownProfile { name: String, known: Vec<String> },
+    /// The profile cannot be applied
+    Invalid { name: String, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Parse { path, message } => write!(f, "{}: {}", path.display(), message),
+            ConfigError::UnknownProfile { name, known } if known.is_empty() => {
+                write!(
+                    f,
+                    "there is no profile {}, no profiles are configured",
+                    name
+                )
+            }
+            ConfigError::UnknownProfile { name, known } => write!(
+                f,
+                "there is no profile {}, expected one of {}",
+                name,
+                known.join(", ")
+            ),
+            ConfigError::Invalid { name, message } => write!(f, "profile {}: {}", name, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileMode {
+    Steady,
+    ColorShift,
+    Breathe,
+    /// Turns off all zones
+    Off,
+    /// The keyframes of every zone, as shown by `status --json`
+    Keyframes,
+}
+
+impl fmt::Display for ProfileMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProfileMode::Steady => write!(f, "steady"),
+            ProfileMode::ColorShift => write!(f, "colorshift"),
+            ProfileMode::Breathe => write!(f, "breathe"),
+            ProfileMode::Off => write!(f, "off"),
+            ProfileMode::Keyframes => write!(f, "keyframes"),
+        }
+    }
+}
+
+/// A single value or a list, e.g. one color for `steady` and a list of
+/// keyframes for `colorshift`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Values {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Values {
+    fn to_vec(&self) -> Vec<&str> {
+        match self {
+            Values::One(value) => vec![value.as_str()],
+            Values::Many(values) => values.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+/// A profile as written in the configuration file.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<ProfileMode>,
+    /// Between 0 and 100 like `set_intensity`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intensity: Option<u8>,
+    /// Between 0 and 255 like `set_intensity --raw`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_intensity: Option<u8>,
+    /// A color for `steady`, keyframes for `colorshift` and colors for
+    /// `breathe`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upper: Option<Values>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lower: Option<Values>,
+    /// `steady` only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<String>,
+    /// `steady` and `colorshift`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<u8>,
+    /// `steady` only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<String>,
+    /// `breathe` only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub period: Option<String>,
+    /// `keyframes` only
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub zones: Vec<ZoneState>,
+}
+
+impl ProfileConfig {
+    /// The profile to apply, `name` is only used for the errors.
+    pub fn to_profile(&self, name: &str) -> Result<Profile, ConfigError> {
+        let invalid = |message: String| ConfigError::Invalid {
+            name: name.to_string(),
+            message,
+        };
+
+        let intensity = match (self.intensity, self.raw_intensity) {
+            (Some(_), Some(_)) => {
+                return Err(invalid(
+                    "intensity and raw_intensity cannot both be given".to_string(),
+                ))
+            }
+            (Some(percent), None) => match Intensity::from_percent(percent, Curve::Linear) {
+                Some(intensity) => Some(intensity.raw()),
+                None => {
+                    return Err(invalid(format!(
+                        "intensity {} is not between 0 and 100",
+                        percent
+                    )))
+                }
+            },
+            (None, raw) => raw,
+        };
+
+        let report = match self.mode {
+            None if self.upper.is_some() || self.lower.is_some() || !self.zones.is_empty() => {
+                return Err(invalid("mode is missing".to_string()))
+            }
+            None => None,
+            Some(ProfileMode::Steady) => Some(self.steady().map_err(invalid)?),
+            Some(ProfileMode::ColorShift) => Some(self.color_shift().map_err(invalid)?),
+            Some(ProfileMode::Breathe) => Some(self.breathe().map_err(invalid)?),
+            Some(ProfileMode::Off) => Some(ZoneColorReport::steady(BLACK_COLOR, BLACK_COLOR)),
+            Some(ProfileMode::Keyframes) => {
+                let profile = Profile {
+                    intensity: None,
+                    zones: self.zones.clone(),
+                };
+                let report = profile.zone_colors().map_err(|e| invalid(e.to_string()))?;
+                Some(report.ok_or_else(|| invalid("keyframes needs zones".to_string()))?)
+            }
+        };
+
+        Ok(match report {
+            Some(report) => Profile::new(&report, intensity),
+            None => Profile {
+                intensity,
+                zones: vec![],
+            },
+        })
+    }
+
+    /// Zones that are not given keep their configuration.
+    fn steady(&self) -> Result<ZoneColorReport, String> {
+        let mut builder = ZoneColorReport::builder();
+        if let Some(speed) = self.speed {
+            builder = builder.speed(Speed::new(speed));
+        }
+        if let Some(brightness) = &self.brightness {
+            builder = builder.brightness(
+                brightness
+                    .parse::<Brightness>()
+                    .map_err(|e| e.to_string())?,
+            );
+        }
+        if let Some(duration) = &self.duration {
+            builder = builder.duration(parse_duration(duration).map_err(|e| e.to_string())?);
+        }
+
+        for (zone, values) in [(Zone::Lower, &self.lower), (Zone::Upper, &self.upper)] {
+            let color = match values.as_ref().map(Values::to_vec).as_deref() {
+                Some([color]) => parse::<ColorRGB>(color)?,
+                Some(_) => {
+                    return Err(format!(
+                        "steady takes a single color for {}",
+                        zone_name(zone.index())
+                    ))
+                }
+                None => continue,
+            };
+            builder = builder.entry(zone.index(), color);
+        }
+
+        let report = builder.build().map_err(|e| e.to_string())?;
+        if report.entries().is_empty() {
+            return Err("steady needs a color for upper or lower".to_string());
+        }
+
+        Ok(report)
+    }
+
+    fn color_shift(&self) -> Result<ZoneColorReport, String> {
+        let speed = self.speed.map_or(Speed::DEFAULT, Speed::new);
+        let (lower, upper) = self.zone_values::<KeyFrame>()?;
+
+        let color_shift = ColorShift::new(speed, lower, upper).map_err(|e| e.to_string())?;
+        Ok(color_shift.to_report())
+    }
+
+    fn breathe(&self) -> Result<ZoneColorReport, String> {
+        let period = self.period.as_deref().unwrap_or(DEFAULT_PERIOD);
+        let period = parse_duration(period).map_err(|e| e.to_string())?;
+        let (lower, upper) = self.zone_values::<ColorRGB>()?;
+
+        let breathe = EmulatedBreathe::new(period, lower, upper).map_err(|e| e.to_string())?;
+        Ok(breathe.to_report())
+    }
+
+    /// The values of the lower and the upper zone. If only one zone is
+    /// given, both use its values like on the command line.
+    fn zone_values<T>(&self) -> Result<(Vec<T>, Vec<T>), String>
+    where
+        T: std::str::FromStr + Clone,
+        T::Err: fmt::Display,
+    {
+        let values = |values: &Option<Values>| -> Result<Option<Vec<T>>, String> {
+            values
+                .as_ref()
+                .map(|values| values.to_vec().into_iter().map(parse).collect())
+                .transpose()
+        };
+
+        match (values(&self.lower)?, values(&self.upper)?) {
+            (Some(lower), Some(upper)) => Ok((lower, upper)),
+            (Some(lower), None) => Ok((lower.clone(), lower)),
+            (None, Some(upper)) => Ok((upper.clone(), upper)),
+            (None, None) => Err("upper or lower is missing".to_string()),
+        }
+    }
+}
+
+fn parse<T>(value: &str) -> Result<T, String>
+where
+    T: std::str::FromStr,
+    T::Err: fmt::Display,
+{
+    value.parse().map_err(|e: T::Err| e.to_string())
+}
+
+/// Captures every keyframe and the raw intensity.
+impl From<DeviceState> for ProfileConfig {
+    fn from(state: DeviceState) -> Self {
+        ProfileConfig {
+            mode: Some(ProfileMode::Keyframes),
+            raw_intensity: state.intensity,
+            zones: state.zones,
+            ..ProfileConfig::default()
+        }
+    }
+}
+
+/// The contents of `config.toml`.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileConfig>,
+}
+
+impl Config {
+    /// `~/.config/rqck/config.toml`
+    pub fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    /// A missing file is an empty configuration.
+    pub fn load(path: &Path) -> crate::Result<Config> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(Config::parse(path, &text)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// `path` is only used for the errors.
+    pub fn parse(path: &Path, text: &str) -> Result<Config, ConfigError> {
+        toml::from_str(text).map_err(|e| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })
+    }
+
+    pub fn profile(&self, name: &str) -> Result<&ProfileConfig, ConfigError> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| ConfigError::UnknownProfile {
+                name: name.to_string(),
+                known: self.profiles.keys().cloned().collect(),
+            })
+    }
+}
+
+/// Adds the profile to the file at `path` or replaces the profile with the
+/// same name. The rest of the file, including its comments, is kept. Returns
+/// whether a profile was replaced.
+pub fn save_profile(path: &Path, name: &str, profile: &ProfileConfig) -> crate::Result<bool> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let parse_error = |message: String| ConfigError::Parse {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    let mut document: DocumentMut = text
+        .parse()
+        .map_err(|e: toml_edit::TomlError| parse_error(e.to_string()))?;
+    let mut table = toml_edit::ser::to_document(profile)
+        .map_err(|e| parse_error(e.to_string()))?
+        .as_table()
+        .clone();
+    format_zones(&mut table);
+
+    let profiles = document
+        .entry("profiles")
+        .or_insert_with(toml_edit::table)
+        .as_table_mut()
+        .ok_or_else(|| parse_error("profiles is not a table".to_string()))?;
+    profiles.set_implicit(true);
+    let replaced = profiles.insert(name, Item::Table(table)).is_some();
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, document.to_string())?;
+
+    Ok(replaced)
+}
+
+/// Writes every zone as its own table and every keyframe on its own line,
+/// instead of everything on a single line.
+fn format_zones(table: &mut toml_edit::Table) {
+    let zones = match table.remove("zones").map(Item::into_array_of_tables) {
+        Some(Ok(zones)) => zones,
+        _ => return,
+    };
+
+    let mut formatted = toml_edit::ArrayOfTables::new();
+    for mut zone in zones {
+        if let Some(key_frames) = zone.get_mut("key_frames").and_then(Item::as_array_mut) {
+            for key_frame in key_frames.iter_mut() {
+                key_frame.decor_mut().set_prefix("\n    ");
+            }
+            key_frames.set_trailing_comma(true);
+            key_frames.set_trailing("\n");
+        }
+        formatted.push(zone);
+    }
+
+    table.insert("zones", Item::ArrayOfTables(formatted));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modes::Mode;
+
+    fn profile(text: &str) -> Result<Profile, ConfigError> {
+        let config = Config::parse(Path::new("config.toml"), text).unwrap();
+        config.profile("test")?.to_profile("test")
+    }
+
+    fn mode(profile: &Profile) -> Mode {
+        Mode::of(&profile.zone_colors().unwrap().unwrap())
+    }
+
+    #[test]
+    fn parses_every_mode() {
+        let work = profile(
+            "[profiles.test]\nmode = \"steady\"\nupper = \"ff5200\"\nbrightness = \"50%\"\nintensity = 60",
+        )
+        .unwrap();
+        assert_eq!(work.intensity, Some(153));
+        assert_eq!(work.zones.len(), 1);
+        assert_eq!(work.zones[0].zone, "upper");
+        assert_eq!(work.zones[0].key_frames[0].brightness, 50);
+
+        let gaming = profile(
+            "[profiles.test]\nmode = \"colorshift\"\nupper = [\"ff0000:500ms\", \"0000ff:1s\"]",
+        )
+        .unwrap();
+        assert_eq!(mode(&gaming), Mode::ColorShift);
+        assert_eq!(gaming.zones.len(), 2);
+
+        let chill =
+            profile("[profiles.test]\nmode = \"breathe\"\nlower = [\"00ff00\"]\nperiod = \"6s\"")
+                .unwrap();
+        assert_eq!(mode(&chill), Mode::Breathe);
+
+        let off = profile("[profiles.test]\nmode = \"off\"").unwrap();
+        assert_eq!(
+            off.zone_colors().unwrap(),
+            Some(ZoneColorReport::steady(BLACK_COLOR, BLACK_COLOR))
+        );
+
+        let dim = profile("[profiles.test]\nintensity = 10").unwrap();
+        assert_eq!(dim.intensity, Some(25));
+        assert!(dim.zones.is_empty());
+    }
+
+    #[test]
+    fn reports_invalid_profiles() {
+        let invalid = |text: &str| match profile(text) {
+            Err(ConfigError::Invalid { message, .. }) => message,
+            result => panic!("{:?}", result),
+        };
+
+        assert_eq!(
+            invalid("[profiles.test]\nupper = \"ff0000\""),
+            "mode is missing"
+        );
+        assert_eq!(
+            invalid("[profiles.test]\nmode = \"steady\"\nintensity = 101"),
+            "intensity 101 is not between 0 and 100"
+        );
+        assert_eq!(
+            invalid("[profiles.test]\nintensity = 50\nraw_intensity = 128"),
+            "intensity and raw_intensity cannot both be given"
+        );
+        assert_eq!(
+            invalid("[profiles.test]\nmode = \"steady\"\nupper = [\"ff0000\", \"00ff00\"]"),
+            "steady takes a single color for upper"
+        );
+        assert_eq!(
+            invalid("[profiles.test]\nmode = \"breathe\""),
+            "upper or lower is missing"
+        );
+        assert!(
+            invalid("[profiles.test]\nmode = \"colorshift\"\nupper = [\"ff0000\"]")
+                .contains("ff0000")
+        );
+
+        assert_eq!(
+            profile("[profiles.work]\nmode = \"off\"")
+                .unwrap_err()
+                .to_string(),
+            "there is no profile test, expected one of work"
+        );
+        assert!(matches!(
+            Config::parse(
+                Path::new("config.toml"),
+                "[profiles.test]\ncolour = \"red\""
+            ),
+            Err(ConfigError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn saved_profiles_keep_the_rest_of_the_file() {
+        let dir = std::env::temp_dir().join(format!("rqck-config-{}", std::process::id()));
+        let path = dir.join("config.toml");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            &path,
+            "# my profiles\n[profiles.off]\nmode = \"off\" # dark\n",
+        )
+        .unwrap();
+
+        let report = ZoneColorReport::steady(BLACK_COLOR, ColorRGB::new(0xff, 0x52, 0x00));
+        // 50% would be applied as 127
+        let saved = ProfileConfig::from(DeviceState::new(&report, Some(128)));
+
+        assert!(!save_profile(&path, "work", &saved).unwrap());
+        assert!(save_profile(&path, "work", &saved).unwrap());
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("# my profiles\n[profiles.off]\nmode = \"off\" # dark\n"));
+        assert!(text.contains("raw_intensity = 128\n"));
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.profiles["work"], saved);
+        let profile = config.profile("work").unwrap().to_profile("work").unwrap();
+        assert_eq!(profile, Profile::new(&report, Some(128)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
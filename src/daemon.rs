@@ -0,0 +1,854 @@
+//! `rqckd`, which keeps the pads open and serves the API of [`crate::rpc`].
+//!
+//! Every client is handled in its own thread. The pads are shared behind a
+//! mutex, so the reports of concurrent requests do not interleave.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::compositor::{Blend, Compositor, Layer};
+use crate::device::{DeviceSelector, PadInfo};
+use crate::profile::Profile;
+use crate::report::{ZoneColorReport, ZoneEntry};
+use crate::rpc::{
+    PadState, Request, Response, RpcError, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND,
+    NO_PAD, PAD_ERROR, PARSE_ERROR, STATE_CHANGED,
+};
+use crate::status::zone_index;
+use crate::transport::Transport;
+use crate::{ColorRGB, QckPad, Result};
+
+/// Where responses and notifications for a client are written to.
+pub type Connection = Arc<Mutex<dyn Write + Send>>;
+
+/// Calls a method of [`crate::rpc`] on the daemon, used by the front ends
+/// that do not speak JSON-RPC.
+pub type Call = Arc<dyn Fn(&str, Value) -> std::result::Result<Value, RpcError> + Send + Sync>;
+
+/// How long a write to a client may block before the client is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Longest `ttl` of a layer, a day.
+const MAX_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How often [`expire_layers`] looks for expired layers.
+const EXPIRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The pads `device` and `all` select.
+#[derive(Deserialize)]
+struct Target {
+    device: Option<String>,
+    #[serde(default)]
+    all: bool,
+}
+
+#[derive(Deserialize)]
+struct SetColor {
+    #[serde(flatten)]
+    target: Target,
+    color: ColorRGB,
+    /// All zones if `None`
+    zone: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SetIntensity {
+    #[serde(flatten)]
+    target: Target,
+    intensity: u8,
+}
+
+#[derive(Deserialize)]
+struct ApplyProfile {
+    #[serde(flatten)]
+    target: Target,
+    profile: Profile,
+}
+
+#[derive(Deserialize)]
+struct ShowFrame {
+    #[serde(flatten)]
+    target: Target,
+    colors: Vec<ColorRGB>,
+}
+
+#[derive(Deserialize)]
+struct SetLayer {
+    #[serde(flatten)]
+    target: Target,
+    name: String,
+    color: ColorRGB,
+    /// All zones if `None`
+    zone: Option<String>,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default = "opaque")]
+    opacity: f64,
+    #[serde(default)]
+    blend: Blend,
+    /// Milliseconds until the layer is removed, kept if `None`
+    ttl: Option<u64>,
+}
+
+fn opaque() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+struct RemoveLayer {
+    #[serde(flatten)]
+    target: Target,
+    name: String,
+}
+
+/// The opened pads and the clients that subscribed to their changes.
+pub struct Daemon<T: Transport> {
+    pads: Mutex<Vec<(PadInfo, QckPad<T>)>>,
+    /// The layers of every pad, in the order of `pads`
+    layers: Mutex<Vec<Compositor>>,
+    subscribers: Mutex<Vec<Connection>>,
+    listeners: Mutex<Vec<Sender<PadState>>>,
+}
+
+impl<T: Transport> Daemon<T> {
+    /// The pads are used in the given order, the first one is the default.
+    pub fn new(pads: Vec<(PadInfo, QckPad<T>)>) -> Self {
+        Daemon {
+            layers: Mutex::new(vec![Compositor::new(); pads.len()]),
+            pads: Mutex::new(pads),
+            subscribers: Mutex::new(vec![]),
+            listeners: Mutex::new(vec![]),
+        }
+    }
+
+    /// The `device` and the number of zones of every pad.
+    pub fn pads(&self) -> Vec<(String, u8)> {
+        self.pads
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(info, pad)| (info.selector(), pad.capabilities().zones))
+            .collect()
+    }
+
+    /// Receives the state of every pad that was changed, like a client that
+    /// subscribed.
+    pub fn listen(&self) -> Receiver<PadState> {
+        let (sender, receiver) = mpsc::channel();
+        self.listeners.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Handles one line sent by a client. Returns `None` for notifications,
+    /// they get no response.
+    pub fn handle(&self, line: &str, connection: &Connection) -> Option<Response> {
+        let value: Value = match serde_json::from_str(line) {
+            Ok(value) => value,
+            Err(e) => {
+                return Some(Response::new(
+                    Value::Null,
+                    Err(RpcError::new(PARSE_ERROR, e)),
+                ))
+            }
+        };
+
+        let request: Request = match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(e) => {
+                return Some(Response::new(
+                    Value::Null,
+                    Err(RpcError::new(INVALID_REQUEST, e)),
+                ))
+            }
+        };
+
+        let result = if request.jsonrpc != "2.0" {
+            Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""))
+        } else if request.method == "subscribe" {
+            self.subscribers.lock().unwrap().push(connection.clone());
+            Ok(Value::Bool(true))
+        } else {
+            self.call(&request.method, request.params)
+        };
+
+        request.id.map(|id| Response::new(id, result))
+    }
+
+    /// Calls a method other than `subscribe`, see [`crate::rpc`].
+    pub fn call(&self, method: &str, params: Value) -> std::result::Result<Value, RpcError> {
+        match method {
+            "set_color" => {
+                let params: SetColor = parse_params(params)?;
+                let zone = match &params.zone {
+                    Some(name) => Some(zone_index(name).ok_or_else(|| {
+                        RpcError::new(INVALID_PARAMS, format!("'{}' is not a zone", name))
+                    })?),
+                    None => None,
+                };
+
+                self.change(&params.target, |pad| match zone {
+                    Some(zone) => {
+                        let mut update = ZoneColorReport::new();
+                        update.push(ZoneEntry::new(zone, params.color))?;
+                        pad.update_zones(&update)
+                    }
+                    None => pad.set_solid_color(params.color),
+                })
+            }
+            "set_intensity" => {
+                let params: SetIntensity = parse_params(params)?;
+                self.change(&params.target, |pad| pad.set_intensity(params.intensity))
+            }
+            "apply_profile" => {
+                let params: ApplyProfile = parse_params(params)?;
+                params
+                    .profile
+                    .zone_colors()
+                    .map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+
+                self.change(&params.target, |pad| {
+                    crate::Pad::apply_profile(pad, &params.profile)
+                })
+            }
+            "show_frame" => {
+                let params: ShowFrame = parse_params(params)?;
+                let report = ZoneColorReport::per_zone(&params.colors);
+                let mut pads = self.pads.lock().unwrap();
+
+                // frames come too often to tell the subscribers about each
+                for index in select(&pads, &params.target)? {
+                    pads[index]
+                        .1
+                        .show_frame(&report)
+                        .map_err(|e| RpcError::new(PAD_ERROR, e))?;
+                }
+
+                Ok(Value::Null)
+            }
+            "set_layer" => {
+                let params: SetLayer = parse_params(params)?;
+                let zone = match &params.zone {
+                    Some(name) => Some(zone_index(name).ok_or_else(|| {
+                        RpcError::new(INVALID_PARAMS, format!("'{}' is not a zone", name))
+                    })?),
+                    None => None,
+                };
+                if !(0.0..=1.0).contains(&params.opacity) {
+                    return Err(RpcError::new(
+                        INVALID_PARAMS,
+                        format!("opacity {} is not between 0 and 1", params.opacity),
+                    ));
+                }
+
+                let too_long = |ttl: u64| {
+                    RpcError::new(INVALID_PARAMS, format!("ttl {} is longer than a day", ttl))
+                };
+                let expires = match params.ttl {
+                    Some(ttl) if Duration::from_millis(ttl) > MAX_TTL => return Err(too_long(ttl)),
+                    Some(ttl) => Some(
+                        Instant::now()
+                            .checked_add(Duration::from_millis(ttl))
+                            .ok_or_else(|| too_long(ttl))?,
+                    ),
+                    None => None,
+                };
+
+                let layer = Layer {
+                    color: params.color,
+                    zone,
+                    priority: params.priority,
+                    opacity: params.opacity,
+                    blend: params.blend,
+                    expires,
+                };
+
+                self.change_layers(&params.target, |layers| {
+                    layers.set(&params.name, layer.clone());
+                    true
+                })
+            }
+            "remove_layer" => {
+                let params: RemoveLayer = parse_params(params)?;
+                self.change_layers(&params.target, |layers| layers.remove(&params.name))
+            }
+            "get_state" => {
+                let target: Target = parse_params(params)?;
+                let mut pads = self.pads.lock().unwrap();
+
+                let states: Vec<PadState> = select(&pads, &target)?
+                    .into_iter()
+                    .map(|index| {
+                        let (info, pad) = &mut pads[index];
+                        PadState {
+                            device: info.selector(),
+                            state: pad.current_state(),
+                        }
+                    })
+                    .collect();
+
+                Ok(serde_json::to_value(states).unwrap())
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method {}", method),
+            )),
+        }
+    }
+
+    /// Removes the layers that expired before `now` and shows what is left.
+    pub fn expire_layers(&self, now: Instant) -> Result<()> {
+        let mut pads = self.pads.lock().unwrap();
+        let mut layers = self.layers.lock().unwrap();
+
+        for (index, layers) in layers.iter_mut().enumerate() {
+            if layers.expire(now) {
+                show_layers(&mut pads[index].1, layers)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs `change` on the layers of the selected pads and shows the pads
+    /// whose layers were changed.
+    fn change_layers<F>(
+        &self,
+        target: &Target,
+        mut change: F,
+    ) -> std::result::Result<Value, RpcError>
+    where
+        F: FnMut(&mut Compositor) -> bool,
+    {
+        let mut pads = self.pads.lock().unwrap();
+        let mut layers = self.layers.lock().unwrap();
+
+        for index in select(&pads, target)? {
+            if change(&mut layers[index]) {
+                show_layers(&mut pads[index].1, &layers[index])
+                    .map_err(|e| RpcError::new(PAD_ERROR, e))?;
+            }
+        }
+
+        Ok(Value::Null)
+    }
+
+    /// Runs `change` on the selected pads and tells the subscribers about
+    /// their new state. The layers of the pads are shown over the new
+    /// configuration.
+    ///
+    /// The subscribers are told once the pads are unlocked, so a subscriber
+    /// that does not read its notifications does not hold up the pads.
+    fn change<F>(&self, target: &Target, change: F) -> std::result::Result<Value, RpcError>
+    where
+        F: FnMut(&mut QckPad<T>) -> Result<()>,
+    {
+        let mut changed = vec![];
+        let result = self.change_pads(target, change, &mut changed);
+
+        for state in &changed {
+            self.notify(state);
+        }
+        result
+    }
+
+    /// Runs `change` on the selected pads and collects the states of the
+    /// pads that were changed until one fails.
+    fn change_pads<F>(
+        &self,
+        target: &Target,
+        mut change: F,
+        changed: &mut Vec<PadState>,
+    ) -> std::result::Result<Value, RpcError>
+    where
+        F: FnMut(&mut QckPad<T>) -> Result<()>,
+    {
+        let mut pads = self.pads.lock().unwrap();
+        let layers = self.layers.lock().unwrap();
+
+        for index in select(&pads, target)? {
+            let (info, pad) = &mut pads[index];
+            // the new configuration is only shown below the layers
+            let covered = !layers[index].is_empty();
+            pad.set_covered(covered);
+            let result = change(pad);
+            pad.set_covered(false);
+
+            result.map_err(|e| RpcError::new(PAD_ERROR, e))?;
+            if covered {
+                show_layers(pad, &layers[index]).map_err(|e| RpcError::new(PAD_ERROR, e))?;
+            }
+
+            changed.push(PadState {
+                device: info.selector(),
+                state: pad.current_state(),
+            });
+        }
+
+        Ok(Value::Null)
+    }
+
+    /// Sends a notification to every subscriber and listener, the ones that
+    /// are gone are dropped.
+    fn notify(&self, state: &PadState) {
+        self.listeners
+            .lock()
+            .unwrap()
+            .retain(|listener| listener.send(state.clone()).is_ok());
+
+        let params = serde_json::to_value(state).unwrap();
+        let notification = Request::new(None, STATE_CHANGED, params);
+        let line = serde_json::to_string(&notification).unwrap();
+
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|connection| writeln!(connection.lock().unwrap(), "{}", line).is_ok());
+    }
+
+    /// Answers the requests of a client until it disconnects.
+    pub fn serve_client(&self, stream: UnixStream) -> io::Result<()> {
+        let writer = stream.try_clone()?;
+        writer.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let connection: Connection = Arc::new(Mutex::new(writer));
+
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(response) = self.handle(&line, &connection) {
+                let response = serde_json::to_string(&response).unwrap();
+                writeln!(connection.lock().unwrap(), "{}", response)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Accepts clients until `listener` fails.
+pub fn serve<T>(daemon: Arc<Daemon<T>>, listener: UnixListener) -> io::Result<()>
+where
+    T: Transport + Send + 'static,
+{
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let daemon = daemon.clone();
+
+        thread::spawn(move || {
+            let _ = daemon.serve_client(stream);
+        });
+    }
+
+    Ok(())
+}
+
+/// Removes expired layers until the process exits.
+pub fn expire_layers<T: Transport>(daemon: Arc<Daemon<T>>) {
+    loop {
+        thread::sleep(EXPIRY_INTERVAL);
+        if let Err(e) = daemon.expire_layers(Instant::now()) {
+            eprintln!("could not remove expired layers: {}", e);
+        }
+    }
+}
+
+/// Shows the layers over the configuration of the pad, without layers the
+/// configuration itself.
+fn show_layers<T: Transport>(pad: &mut QckPad<T>, layers: &Compositor) -> Result<()> {
+    let base = pad.configuration();
+
+    if layers.is_empty() {
+        return match base {
+            Some(base) => pad.set_zone_colors(&base),
+            None => Ok(()),
+        };
+    }
+
+    let colors = layers.compose(base.as_ref(), pad.capabilities().zones);
+    pad.show_frame(&ZoneColorReport::per_zone(&colors))
+}
+
+fn parse_params<P: DeserializeOwned>(params: Value) -> std::result::Result<P, RpcError> {
+    // methods without required params may be called without any
+    let params = match params {
+        Value::Null => Value::Object(Default::default()),
+        params => params,
+    };
+
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e))
+}
+
+/// Indices of the selected pads, like [`crate::device::select`].
+fn select<T: Transport>(
+    pads: &[(PadInfo, QckPad<T>)],
+    target: &Target,
+) -> std::result::Result<Vec<usize>, RpcError> {
+    let selector = match &target.device {
+        Some(device) => Some(
+            device
+                .parse::<DeviceSelector>()
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e))?,
+        ),
+        None => None,
+    };
+
+    let selected: Vec<usize> = pads
+        .iter()
+        .enumerate()
+        .filter(|(_, (info, _))| selector.as_ref().is_none_or(|s| s.matches(info)))
+        .map(|(index, _)| index)
+        .take(if target.all { pads.len() } else { 1 })
+        .collect();
+
+    if selected.is_empty() {
+        return Err(match &target.device {
+            Some(device) => RpcError::new(NO_PAD, format!("no pad matches {}", device)),
+            None => RpcError::new(NO_PAD, "no pad is open"),
+        });
+    }
+
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::{Client, Outcome, RemotePad};
+    use crate::transport::{MockTransport, FEATURE_REPORT, OUTPUT_REPORT};
+    use crate::{cli, Pad, BLACK_COLOR};
+    use serde_json::json;
+
+    type Output = Arc<Mutex<Vec<u8>>>;
+
+    fn daemon(serials: &[&str]) -> Daemon<MockTransport> {
+        let pads = serials
+            .iter()
+            .map(|serial| {
+                let info = PadInfo {
+                    serial: Some(serial.to_string()),
+                    ..PadInfo::default()
+                };
+                (info, QckPad::new(MockTransport::new()))
+            })
+            .collect();
+
+        Daemon::new(pads)
+    }
+
+    fn connection() -> (Output, Connection) {
+        let output: Output = Arc::new(Mutex::new(vec![]));
+        (output.clone(), output)
+    }
+
+    fn call(daemon: &Daemon<MockTransport>, request: Value) -> Outcome {
+        let (_, connection) = connection();
+        daemon
+            .handle(&request.to_string(), &connection)
+            .unwrap()
+            .outcome
+    }
+
+    fn error_code(outcome: Outcome) -> i64 {
+        match outcome {
+            Outcome::Error(e) => e.code,
+            Outcome::Result(value) => panic!("expected an error, got {}", value),
+        }
+    }
+
+    /// (value, report id) of the reports sent to every pad
+    fn sent(daemon: &Daemon<MockTransport>) -> Vec<Vec<(u16, u8)>> {
+        daemon
+            .pads
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .map(|(_, pad)| {
+                pad.transport()
+                    .take_sent()
+                    .into_iter()
+                    .map(|report| (report.value, report.data[0]))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn selects_pads_like_the_command_line() {
+        let daemon = daemon(&["AAA", "BBB"]);
+        let set_intensity = |params: Value| {
+            call(
+                &daemon,
+                json!({"jsonrpc": "2.0", "id": 1, "method": "set_intensity", "params": params}),
+            )
+        };
+
+        assert_eq!(
+            set_intensity(json!({"intensity": 255})),
+            Outcome::Result(Value::Null)
+        );
+        assert_eq!(sent(&daemon), vec![vec![(OUTPUT_REPORT, 0x0c)], vec![]]);
+
+        set_intensity(json!({"intensity": 255, "device": "BBB"}));
+        assert_eq!(sent(&daemon), vec![vec![], vec![(OUTPUT_REPORT, 0x0c)]]);
+
+        set_intensity(json!({"intensity": 255, "all": true}));
+        assert_eq!(
+            sent(&daemon),
+            vec![vec![(OUTPUT_REPORT, 0x0c)], vec![(OUTPUT_REPORT, 0x0c)]]
+        );
+
+        assert_eq!(
+            error_code(set_intensity(json!({"intensity": 255, "device": "CCC"}))),
+            NO_PAD
+        );
+        assert_eq!(
+            error_code(set_intensity(json!({"intensity": 256}))),
+            INVALID_PARAMS
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_requests() {
+        let daemon = daemon(&["AAA"]);
+        let (_, connection) = connection();
+
+        let response = daemon.handle("{", &connection).unwrap();
+        assert_eq!(error_code(response.outcome), PARSE_ERROR);
+        assert_eq!(
+            error_code(call(&daemon, json!({"jsonrpc": "2.0", "id": 1}))),
+            INVALID_REQUEST
+        );
+        assert_eq!(
+            error_code(call(
+                &daemon,
+                json!({"jsonrpc": "2.0", "id": 1, "method": "reboot"})
+            )),
+            METHOD_NOT_FOUND
+        );
+
+        let notification =
+            json!({"jsonrpc": "2.0", "method": "set_intensity", "params": {"intensity": 0}});
+        assert!(daemon
+            .handle(&notification.to_string(), &connection)
+            .is_none());
+        assert_eq!(sent(&daemon), vec![vec![(OUTPUT_REPORT, 0x0c)]]);
+    }
+
+    #[test]
+    fn subscribers_are_told_about_changes() {
+        let daemon = daemon(&["AAA"]);
+        let (output, subscriber) = connection();
+        let subscribe = json!({"jsonrpc": "2.0", "id": 1, "method": "subscribe"});
+        daemon.handle(&subscribe.to_string(), &subscriber);
+
+        let set_color = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "set_color",
+            "params": {"color": "ff0000", "zone": "upper"}
+        });
+        assert_eq!(call(&daemon, set_color), Outcome::Result(Value::Null));
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        let notification: Request = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(notification.method, STATE_CHANGED);
+
+        // the mock cannot be read back and there is no state cache
+        let state: PadState = serde_json::from_value(notification.params).unwrap();
+        assert_eq!(
+            state,
+            PadState {
+                device: "AAA".to_string(),
+                state: None
+            }
+        );
+    }
+
+    /// A subscriber that records whether the pads were locked while it was
+    /// told about a change.
+    struct Probe {
+        daemon: std::sync::Weak<Daemon<MockTransport>>,
+        pads_locked: Arc<Mutex<Vec<bool>>>,
+    }
+
+    impl Write for Probe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let daemon = self.daemon.upgrade().unwrap();
+            let locked = daemon.pads.try_lock().is_err();
+            self.pads_locked.lock().unwrap().push(locked);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn subscribers_are_told_once_the_pads_are_unlocked() {
+        let daemon = Arc::new(daemon(&["AAA", "BBB"]));
+        let pads_locked = Arc::new(Mutex::new(vec![]));
+        let subscriber: Connection = Arc::new(Mutex::new(Probe {
+            daemon: Arc::downgrade(&daemon),
+            pads_locked: pads_locked.clone(),
+        }));
+        let subscribe = json!({"jsonrpc": "2.0", "id": 1, "method": "subscribe"});
+        daemon.handle(&subscribe.to_string(), &subscriber);
+
+        let set_intensity = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "set_intensity",
+            "params": {"intensity": 255, "all": true}
+        });
+        assert_eq!(call(&daemon, set_intensity), Outcome::Result(Value::Null));
+
+        let pads_locked = pads_locked.lock().unwrap();
+        assert!(!pads_locked.is_empty());
+        assert!(pads_locked.iter().all(|locked| !locked));
+    }
+
+    #[test]
+    fn layers_are_shown_over_the_configuration_until_they_expire() {
+        let daemon = daemon(&["AAA"]);
+        let request = |method: &str, params: Value| {
+            let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+            assert_eq!(call(&daemon, request), Outcome::Result(Value::Null));
+        };
+        let shown = || {
+            let sent = daemon.pads.lock().unwrap()[0].1.transport().take_sent();
+            let report = sent
+                .iter()
+                .rev()
+                .find(|r| r.value == FEATURE_REPORT)
+                .unwrap();
+            ZoneColorReport::from_bytes(&report.data).unwrap()
+        };
+        let (red, blue) = (ColorRGB::new(0xff, 0, 0), ColorRGB::new(0, 0, 0xff));
+        let white = ColorRGB::new(0xff, 0xff, 0xff);
+
+        request("set_color", json!({"color": "0000ff"}));
+        request(
+            "set_layer",
+            json!({"name": "flash", "color": "ffffff", "zone": "upper", "ttl": 2000}),
+        );
+        assert_eq!(shown(), ZoneColorReport::steady(blue, white));
+
+        // changes go to the base scene below the layers, which is not shown
+        // on its own
+        request("set_color", json!({"color": "ff0000", "zone": "lower"}));
+        let sent = daemon.pads.lock().unwrap()[0].1.transport().take_sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(
+            ZoneColorReport::from_bytes(&sent[0].data).unwrap(),
+            ZoneColorReport::steady(red, white)
+        );
+
+        request(
+            "set_layer",
+            json!({"name": "dim", "color": "808080", "blend": "multiply", "priority": -1}),
+        );
+        assert_eq!(
+            shown(),
+            ZoneColorReport::steady(ColorRGB::new(0x80, 0, 0), white)
+        );
+
+        daemon
+            .expire_layers(Instant::now() + Duration::from_secs(3))
+            .unwrap();
+        assert_eq!(
+            shown(),
+            ZoneColorReport::steady(ColorRGB::new(0x80, 0, 0), ColorRGB::new(0, 0, 0x80))
+        );
+
+        request("remove_layer", json!({"name": "dim"}));
+        assert_eq!(shown(), ZoneColorReport::steady(red, blue));
+
+        let opacity = json!({"jsonrpc": "2.0", "id": 1, "method": "set_layer",
+            "params": {"name": "x", "color": "ffffff", "opacity": 2}});
+        assert_eq!(error_code(call(&daemon, opacity)), INVALID_PARAMS);
+
+        // would overflow the time it expires at
+        let ttl = json!({"jsonrpc": "2.0", "id": 1, "method": "set_layer",
+            "params": {"name": "x", "color": "ffffff", "ttl": 18446744073709551615u64}});
+        assert_eq!(error_code(call(&daemon, ttl)), INVALID_PARAMS);
+        let ttl = json!({"jsonrpc": "2.0", "id": 1, "method": "set_layer",
+            "params": {"name": "x", "color": "ffffff", "ttl": 86400001}});
+        assert_eq!(error_code(call(&daemon, ttl)), INVALID_PARAMS);
+    }
+
+    #[test]
+    fn frames_are_shown_without_notifications() {
+        let daemon = daemon(&["AAA"]);
+        let (output, subscriber) = connection();
+        let subscribe = json!({"jsonrpc": "2.0", "id": 1, "method": "subscribe"});
+        daemon.handle(&subscribe.to_string(), &subscriber);
+
+        let show_frame = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "show_frame",
+            "params": {"colors": ["ff0000", "0000ff"]}
+        });
+        assert_eq!(call(&daemon, show_frame), Outcome::Result(Value::Null));
+        assert_eq!(
+            sent(&daemon),
+            vec![vec![(FEATURE_REPORT, 0x0e), (OUTPUT_REPORT, 0x0d)]]
+        );
+        assert!(output.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn runs_the_command_line_through_the_socket() {
+        let daemon = Arc::new(daemon(&["AAA"]));
+        let showing = ZoneColorReport::steady(BLACK_COLOR, ColorRGB::new(0, 0, 0xff));
+        daemon.pads.lock().unwrap()[0]
+            .1
+            .transport()
+            .set_response(FEATURE_REPORT, &showing.to_bytes());
+
+        let (client, server) = UnixStream::pair().unwrap();
+        let handle = {
+            let daemon = daemon.clone();
+            thread::spawn(move || daemon.serve_client(server))
+        };
+
+        let mut client = Client::new(client).unwrap();
+        let pads = client.get_state(None, true).unwrap();
+        assert_eq!(pads.len(), 1);
+        assert_eq!(pads[0].device, "AAA");
+
+        let run = |client: &mut Client, args: &[&str]| {
+            let args = std::iter::once("rgame_linux").chain(args.iter().cloned());
+            let matches = cli::app().get_matches_from_safe(args).unwrap();
+            cli::run(&mut RemotePad::new(client, "AAA"), &matches)
+        };
+
+        run(&mut client, &["solid", "ff0000", "--zone", "lower"]).unwrap();
+        run(&mut client, &["set_intensity", "-i", "100"]).unwrap();
+        assert_eq!(
+            sent(&daemon),
+            vec![vec![
+                (FEATURE_REPORT, 0x0e),
+                (OUTPUT_REPORT, 0x0d),
+                (OUTPUT_REPORT, 0x0c)
+            ]]
+        );
+
+        let state = RemotePad::new(&mut client, "AAA").status().unwrap();
+        assert_eq!(Profile::from(state).zone_colors().unwrap(), Some(showing));
+
+        drop(client);
+        handle.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,274 @@
+//! The pads of `rqckd` on the D-Bus session bus.
+//!
+//! `rqckd --dbus` owns the name `org.rqck`. Every pad is an object
+//! `/org/rqck/pad0`, `/org/rqck/pad1`, ... with the `org.rqck.Pad1`
+//! interface, every zone of a pad an object below it, e.g.
+//! `/org/rqck/pad0/upper`, with the `org.rqck.Zone1` interface.
+//!
+//! `org.rqck.Pad1`:
+//!
+//! - properties `Device` (s), `Intensity` (i, raw 0-255, -1 if unknown) and
+//!   `Mode` (s, empty if unknown)
+//! - methods `SetIntensity(y)`, `SetSteady(s upper, s lower)`,
+//!   `SetColor(s)`, `Disable()` and `ApplyProfile(s)` with a profile as JSON
+//!
+//! `org.rqck.Zone1`:
+//!
+//! - properties `Name` (s) and `Color` (s, the first keyframe, empty if
+//!   unknown)
+//! - methods `SetColor(s)` and `Disable()`
+//!
+//! The methods are the requests of [`crate::rpc`], so changes made through
+//! D-Bus and through the socket are seen by both. `PropertiesChanged` is
+//! emitted for every change.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+
+use serde_json::{json, Value};
+use zbus::blocking::connection::Builder;
+use zbus::blocking::Connection;
+use zbus::fdo::{self, Properties};
+use zbus::names::InterfaceName;
+use zbus::object_server::SignalEmitter;
+use zbus::{interface, zvariant};
+
+use crate::daemon::{Call, Daemon};
+use crate::profile::Profile;
+use crate::report::ZoneColorReport;
+use crate::rpc::{PadState, RpcError, INVALID_PARAMS};
+use crate::status::{zone_name, DeviceState};
+use crate::transport::Transport;
+use crate::ColorRGB;
+
+/// Well-known name of `rqckd` on the bus.
+pub const BUS_NAME: &str = "org.rqck";
+
+const PAD_INTERFACE: &str = "org.rqck.Pad1";
+const ZONE_INTERFACE: &str = "org.rqck.Zone1";
+
+fn to_fdo(e: RpcError) -> fdo::Error {
+    match e.code {
+        INVALID_PARAMS => fdo::Error::InvalidArgs(e.message),
+        _ => fdo::Error::Failed(e.message),
+    }
+}
+
+fn parse_color(color: &str) -> fdo::Result<ColorRGB> {
+    color
+        .parse()
+        .map_err(|e: crate::commands::ParseColorError| fdo::Error::InvalidArgs(e.to_string()))
+}
+
+/// The object path of the pad at `index` or of one of its zones.
+fn object_path(index: usize, zone: Option<u8>) -> String {
+    match zone {
+        // object paths only allow [A-Za-z0-9_]
+        Some(zone) => format!(
+            "/org/rqck/pad{}/{}",
+            index,
+            zone_name(zone).replace(' ', "")
+        ),
+        None => format!("/org/rqck/pad{}", index),
+    }
+}
+
+fn state(call: &Call, device: &str) -> Option<DeviceState> {
+    let result = call("get_state", json!({ "device": device })).ok()?;
+    let pads: Vec<PadState> = serde_json::from_value(result).ok()?;
+
+    pads.into_iter().next()?.state
+}
+
+fn intensity(state: Option<&DeviceState>) -> i32 {
+    state
+        .and_then(|state| state.intensity)
+        .map_or(-1, i32::from)
+}
+
+fn mode(state: Option<&DeviceState>) -> String {
+    state.map_or_else(String::new, |state| state.mode.to_string())
+}
+
+fn zone_color(state: Option<&DeviceState>, name: &str) -> String {
+    state
+        .and_then(|state| state.zones.iter().find(|zone| zone.zone == name))
+        .and_then(|zone| zone.key_frames.first())
+        .map_or_else(String::new, |key_frame| key_frame.color.to_string())
+}
+
+struct PadObject {
+    call: Call,
+    device: String,
+}
+
+#[interface(name = "org.rqck.Pad1")]
+impl PadObject {
+    #[zbus(property)]
+    fn device(&self) -> String {
+        self.device.clone()
+    }
+
+    #[zbus(property)]
+    fn intensity(&self) -> i32 {
+        intensity(state(&self.call, &self.device).as_ref())
+    }
+
+    #[zbus(property)]
+    fn mode(&self) -> String {
+        mode(state(&self.call, &self.device).as_ref())
+    }
+
+    fn set_intensity(&self, intensity: u8) -> fdo::Result<()> {
+        let params = json!({ "device": self.device, "intensity": intensity });
+        (self.call)("set_intensity", params).map_err(to_fdo)?;
+        Ok(())
+    }
+
+    /// Like `solid UPPER LOWER`
+    fn set_steady(&self, upper: &str, lower: &str) -> fdo::Result<()> {
+        let report = ZoneColorReport::steady(parse_color(lower)?, parse_color(upper)?);
+        let profile = Profile::new(&report, None);
+
+        let params = json!({ "device": self.device, "profile": profile });
+        (self.call)("apply_profile", params).map_err(to_fdo)?;
+        Ok(())
+    }
+
+    /// Sets all zones to the color
+    fn set_color(&self, color: &str) -> fdo::Result<()> {
+        parse_color(color)?;
+
+        let params = json!({ "device": self.device, "color": color });
+        (self.call)("set_color", params).map_err(to_fdo)?;
+        Ok(())
+    }
+
+    /// Turns off all zones
+    fn disable(&self) -> fdo::Result<()> {
+        self.set_color("000000")
+    }
+
+    fn apply_profile(&self, profile: &str) -> fdo::Result<()> {
+        let profile: Value =
+            serde_json::from_str(profile).map_err(|e| fdo::Error::InvalidArgs(e.to_string()))?;
+
+        let params = json!({ "device": self.device, "profile": profile });
+        (self.call)("apply_profile", params).map_err(to_fdo)?;
+        Ok(())
+    }
+}
+
+struct ZoneObject {
+    call: Call,
+    device: String,
+    name: String,
+}
+
+#[interface(name = "org.rqck.Zone1")]
+impl ZoneObject {
+    #[zbus(property)]
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[zbus(property)]
+    fn color(&self) -> String {
+        zone_color(state(&self.call, &self.device).as_ref(), &self.name)
+    }
+
+    fn set_color(&self, color: &str) -> fdo::Result<()> {
+        parse_color(color)?;
+
+        let params = json!({ "device": self.device, "color": color, "zone": self.name });
+        (self.call)("set_color", params).map_err(to_fdo)?;
+        Ok(())
+    }
+
+    /// Turns off the zone, the other zones are kept
+    fn disable(&self) -> fdo::Result<()> {
+        self.set_color("000000")
+    }
+}
+
+/// Emits `PropertiesChanged` for the pad and all of its zones.
+fn properties_changed(
+    connection: &Connection,
+    index: usize,
+    zones: u8,
+    state: Option<&DeviceState>,
+) -> zbus::Result<()> {
+    let emit = |path: String, interface: &'static str, changed: HashMap<&str, zvariant::Value>| {
+        let emitter = SignalEmitter::new(connection.inner(), path)?;
+        zbus::block_on(Properties::properties_changed(
+            &emitter,
+            InterfaceName::from_static_str_unchecked(interface),
+            changed,
+            Cow::Borrowed(&[]),
+        ))
+    };
+
+    let mut changed = HashMap::new();
+    changed.insert("Intensity", zvariant::Value::from(intensity(state)));
+    changed.insert("Mode", zvariant::Value::from(mode(state)));
+    emit(object_path(index, None), PAD_INTERFACE, changed)?;
+
+    for zone in 0..zones {
+        let mut changed = HashMap::new();
+        let color = zone_color(state, &zone_name(zone));
+        changed.insert("Color", zvariant::Value::from(color));
+        emit(object_path(index, Some(zone)), ZONE_INTERFACE, changed)?;
+    }
+
+    Ok(())
+}
+
+/// Serves the pads of `daemon` on the session bus or, with an `address`, on
+/// that bus. The pads are served as long as the connection is kept.
+pub fn serve<T>(daemon: Arc<Daemon<T>>, address: Option<&str>) -> zbus::Result<Connection>
+where
+    T: Transport + Send + 'static,
+{
+    let pads = daemon.pads();
+    let changes = daemon.listen();
+    let call: Call = Arc::new(move |method: &str, params: Value| daemon.call(method, params));
+
+    let mut builder = match address {
+        Some(address) => Builder::address(address)?,
+        None => Builder::session()?,
+    };
+
+    for (index, (device, zones)) in pads.iter().enumerate() {
+        let pad = PadObject {
+            call: call.clone(),
+            device: device.clone(),
+        };
+        builder = builder.serve_at(object_path(index, None), pad)?;
+
+        for zone in 0..*zones {
+            let zone_object = ZoneObject {
+                call: call.clone(),
+                device: device.clone(),
+                name: zone_name(zone),
+            };
+            builder = builder.serve_at(object_path(index, Some(zone)), zone_object)?;
+        }
+    }
+
+    let connection = builder.name(BUS_NAME)?.build()?;
+
+    // the signals are emitted outside of the daemon, which holds the pads
+    // while it notifies
+    let signals = connection.clone();
+    thread::spawn(move || {
+        for change in changes {
+            if let Some(index) = pads.iter().position(|(device, _)| *device == change.device) {
+                let _ = properties_changed(&signals, index, pads[index].1, change.state.as_ref());
+            }
+        }
+    });
+
+    Ok(connection)
+}
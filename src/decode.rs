@@ -0,0 +1,197 @@
+//! Turns captured control transfers back into the commands of the pad.
+
+use std::fmt;
+
+use crate::capture::ControlTransfer;
+use crate::report::{ReportError, ZoneColorReport};
+use crate::status::DeviceState;
+use crate::transport::{FEATURE_REPORT, OUTPUT_REPORT};
+
+const SET_REPORT: (u8, u8) = (0x21, 0x09);
+const GET_REPORT: (u8, u8) = (0xa1, 0x01);
+
+/// A report sent to or read from the pad.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    SetIntensity(u8),
+    ReadIntensity(u8),
+    Apply,
+    SetZoneColors(Result<ZoneColorReport, ReportError>),
+    ReadZoneColors(Result<ZoneColorReport, ReportError>),
+    /// Any other HID report, printed as hex
+    Unknown {
+        set: bool,
+        value: u16,
+        data: Vec<u8>,
+    },
+}
+
+/// A [`Command`] together with the transfer it was decoded from.
+pub struct Decoded<'a> {
+    pub transfer: &'a ControlTransfer,
+    pub command: Command,
+}
+
+/// Returns `None` for transfers that are not HID SET_REPORT or GET_REPORT
+/// requests, e.g. descriptor requests during enumeration.
+pub fn decode(transfer: &ControlTransfer) -> Option<Decoded<'_>> {
+    let setup = &transfer.setup;
+    let set = match (setup.request_type, setup.request) {
+        SET_REPORT => true,
+        GET_REPORT => false,
+        _ => return None,
+    };
+    let data = &transfer.data;
+
+    let command = match (set, setup.value, data.first()) {
+        (true, OUTPUT_REPORT, Some(0x0c)) if data.len() > 2 => Command::SetIntensity(data[2]),
+        (false, OUTPUT_REPORT, Some(0x0c)) if data.len() > 2 => Command::ReadIntensity(data[2]),
+        (true, OUTPUT_REPORT, Some(0x0d)) => Command::Apply,
+        (true, FEATURE_REPORT, _) => Command::SetZoneColors(ZoneColorReport::from_bytes(data)),
+        (false, FEATURE_REPORT, _) => Command::ReadZoneColors(ZoneColorReport::from_bytes(data)),
+        _ => Command::Unknown {
+            set,
+            value: setup.value,
+            data: data.clone(),
+        },
+    };
+
+    Some(Decoded { transfer, command })
+}
+
+fn percent(raw: u8) -> u16 {
+    (u16::from(raw) * 100 + 127) / 255
+}
+
+fn write_zone_colors(
+    f: &mut fmt::Formatter,
+    report: &Result<ZoneColorReport, ReportError>,
+) -> fmt::Result {
+    match report {
+        Ok(report) => {
+            let state = DeviceState::new(report, None);
+            write!(f, " ({})", state.mode)?;
+
+            for zone in &state.zones {
+                write!(f, "\n    {}", zone)?;
+            }
+            Ok(())
+        }
+        Err(e) => write!(f, ": {}", e),
+    }
+}
+
+impl fmt::Display for Decoded<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let transfer = self.transfer;
+        write!(
+            f,
+            "{:>17.6} {}:{:03} ",
+            transfer.timestamp.as_secs_f64(),
+            transfer.bus,
+            transfer.device
+        )?;
+
+        match &self.command {
+            Command::SetIntensity(raw) => {
+                write!(f, "set intensity {}% (raw {})", percent(*raw), raw)?
+            }
+            Command::ReadIntensity(raw) => {
+                write!(f, "read intensity {}% (raw {})", percent(*raw), raw)?
+            }
+            Command::Apply => write!(f, "apply changes")?,
+            Command::SetZoneColors(report) => {
+                write!(f, "set zone colors")?;
+                write_zone_colors(f, report)?;
+            }
+            Command::ReadZoneColors(report) => {
+                write!(f, "read zone colors")?;
+                write_zone_colors(f, report)?;
+            }
+            Command::Unknown { set, value, data } => {
+                let request = if *set { "SET_REPORT" } else { "GET_REPORT" };
+                write!(f, "{} 0x{:04x}:", request, value)?;
+
+                for byte in data {
+                    write!(f, " {:02x}", byte)?;
+                }
+            }
+        }
+
+        if transfer.is_truncated() {
+            write!(
+                f,
+                "\n    (truncated, {} of {} bytes captured)",
+                transfer.data.len(),
+                transfer.setup.length
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::Setup;
+    use crate::commands::{ColorRGB, BLACK_COLOR};
+    use std::time::Duration;
+
+    fn transfer(request_type: u8, request: u8, value: u16, data: &[u8]) -> ControlTransfer {
+        ControlTransfer {
+            timestamp: Duration::from_millis(1500),
+            bus: 1,
+            device: 3,
+            setup: Setup {
+                request_type,
+                request,
+                value,
+                index: 0,
+                length: data.len() as u16,
+            },
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn decodes_output_reports() {
+        let intensity = transfer(0x21, 0x09, 0x0200, &[0x0c, 0x00, 0xff, 0x00]);
+        assert_eq!(
+            decode(&intensity).unwrap().command,
+            Command::SetIntensity(0xff)
+        );
+
+        let apply = transfer(0x21, 0x09, 0x0200, &[0x0d, 0x00]);
+        assert_eq!(decode(&apply).unwrap().command, Command::Apply);
+
+        let descriptor = transfer(0x80, 0x06, 0x0100, &[0x12, 0x01]);
+        assert!(decode(&descriptor).is_none());
+    }
+
+    #[test]
+    fn prints_zone_colors() {
+        let red = ColorRGB::new(0xff, 0x00, 0x00);
+        let report = ZoneColorReport::steady(red, BLACK_COLOR).to_bytes();
+        let mut transfer = transfer(0x21, 0x09, 0x0300, &report);
+        transfer.data.truncate(28);
+
+        assert_eq!(
+            decode(&transfer).unwrap().to_string(),
+            "         1.500000 1:003 set zone colors (steady)\n    \
+             lower:    #ff0000 200ms (brightness 100%, speed 50)\n    \
+             upper:    #000000 200ms (brightness 100%, speed 50)\n    \
+             (truncated, 28 of 524 bytes captured)"
+        );
+    }
+
+    #[test]
+    fn prints_unknown_reports_as_hex() {
+        let transfer = transfer(0x21, 0x09, 0x0200, &[0x0b, 0x01, 0x02]);
+
+        assert_eq!(
+            decode(&transfer).unwrap().to_string(),
+            "         1.500000 1:003 SET_REPORT 0x0200: 0b 01 02"
+        );
+    }
+}
@@ -0,0 +1,400 @@
+//! Pads described in TOML files.
+//!
+//! A definition describes the protocol of one model: its ids, the reports it
+//! accepts and where the colors of the zones go. `devices/qck_prism_cloth.toml`
+//! is the built-in definition and documents the format. Further definitions
+//! are loaded from `/usr/share/rqck/devices` and `~/.config/rqck/devices`
+//! (`$XDG_CONFIG_HOME/rqck/devices`), so a newly reverse engineered pad can be
+//! added by dropping in a file.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::modes::Mode;
+use crate::paths::config_dir;
+use crate::registry::{Capabilities, IntensityReport, Model, ReportLayout, ZoneFormat};
+use crate::report::ZoneColorReport;
+use crate::BLACK_COLOR;
+
+/// The definition of the QCK Prism Cloth.
+pub const BUILTIN: &str = include_str!("../devices/qck_prism_cloth.toml");
+
+/// Directory for definitions installed with the system.
+pub const SYSTEM_DIR: &str = "/usr/share/rqck/devices";
+
+#[derive(Debug, PartialEq)]
+pub struct DefinitionError {
+    /// The file, `None` for definitions not read from a file
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
+
+impl DefinitionError {
+    fn new<M: ToString>(message: M) -> Self {
+        DefinitionError {
+            path: None,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}: {}", path.display(), self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for DefinitionError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definition {
+    name: String,
+    vendor_id: u16,
+    product_id: u16,
+    modes: Vec<Mode>,
+    zone_report: ZoneReport,
+    apply_report: Report,
+    intensity_report: Option<Intensity>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Report {
+    value: u16,
+    #[serde(default)]
+    index: u16,
+    id: u8,
+    len: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ZoneReport {
+    value: u16,
+    #[serde(default)]
+    index: u16,
+    id: u8,
+    len: usize,
+    format: Format,
+    zones: Option<u8>,
+    rgb_offsets: Option<Vec<usize>>,
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    Keyframes,
+    Offsets,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Intensity {
+    value: u16,
+    #[serde(default)]
+    index: u16,
+    id: u8,
+    len: usize,
+    offset: usize,
+}
+
+fn layout(
+    name: &str,
+    value: u16,
+    index: u16,
+    id: u8,
+    len: usize,
+) -> Result<ReportLayout, DefinitionError> {
+    if len == 0 {
+        return Err(DefinitionError::new(format!("{} is empty", name)));
+    }
+
+    Ok(ReportLayout {
+        value,
+        index,
+        id,
+        len,
+    })
+}
+
+/// Parses and checks a definition.
+pub fn parse(text: &str) -> Result<Model, DefinitionError> {
+    let definition: Definition = toml::from_str(text).map_err(DefinitionError::new)?;
+    let zone_report = &definition.zone_report;
+
+    if definition.modes.is_empty() {
+        return Err(DefinitionError::new("modes is empty"));
+    }
+
+    let zone_layout = layout(
+        "zone_report",
+        zone_report.value,
+        zone_report.index,
+        zone_report.id,
+        zone_report.len,
+    )?;
+
+    let (zones, zone_format) = match (&zone_report.format, &zone_report.rgb_offsets) {
+        (Format::Keyframes, Some(_)) => {
+            return Err(DefinitionError::new(
+                "rgb_offsets is only used with format = \"offsets\"",
+            ))
+        }
+        (Format::Keyframes, None) => {
+            let zones = match zone_report.zones {
+                Some(zones) if zones > 0 => zones,
+                _ => return Err(DefinitionError::new("zone_report needs zones")),
+            };
+
+            // at least one keyframe per zone has to fit
+            ZoneColorReport::uniform(zones, BLACK_COLOR)
+                .encode(&zone_layout)
+                .map_err(|e| DefinitionError::new(format!("zone_report: {}", e)))?;
+
+            (zones, ZoneFormat::Keyframes)
+        }
+        (Format::Offsets, None) => {
+            return Err(DefinitionError::new(
+                "format = \"offsets\" needs rgb_offsets",
+            ))
+        }
+        (Format::Offsets, Some(offsets)) => {
+            if offsets.is_empty() || offsets.len() > usize::from(u8::MAX) {
+                return Err(DefinitionError::new("rgb_offsets needs 1 to 255 zones"));
+            }
+
+            if let Some(offset) = offsets
+                .iter()
+                .find(|offset| **offset == 0 || **offset + 3 > zone_report.len)
+            {
+                return Err(DefinitionError::new(format!(
+                    "rgb offset {} is outside of the {} byte zone_report",
+                    offset, zone_report.len
+                )));
+            }
+
+            if zone_report.zones.is_some() && zone_report.zones != Some(offsets.len() as u8) {
+                return Err(DefinitionError::new(
+                    "zones does not match the number of rgb_offsets",
+                ));
+            }
+
+            if definition.modes != [Mode::Steady] {
+                return Err(DefinitionError::new(
+                    "pads with format = \"offsets\" only support the steady mode",
+                ));
+            }
+
+            (offsets.len() as u8, ZoneFormat::Offsets(offsets.clone()))
+        }
+    };
+
+    let apply = &definition.apply_report;
+    let apply_layout = layout(
+        "apply_report",
+        apply.value,
+        apply.index,
+        apply.id,
+        apply.len,
+    )?;
+
+    let intensity_report = match &definition.intensity_report {
+        Some(intensity) => {
+            if intensity.offset == 0 || intensity.offset >= intensity.len {
+                return Err(DefinitionError::new(format!(
+                    "intensity offset {} is outside of the {} byte intensity_report",
+                    intensity.offset, intensity.len
+                )));
+            }
+
+            Some(IntensityReport {
+                layout: layout(
+                    "intensity_report",
+                    intensity.value,
+                    intensity.index,
+                    intensity.id,
+                    intensity.len,
+                )?,
+                offset: intensity.offset,
+            })
+        }
+        None => None,
+    };
+
+    Ok(Model {
+        name: definition.name,
+        vendor_id: definition.vendor_id,
+        product_id: definition.product_id,
+        capabilities: Capabilities {
+            zones,
+            zone_report: zone_layout,
+            zone_format,
+            apply_report: apply_layout,
+            intensity_report,
+            modes: definition.modes,
+        },
+    })
+}
+
+/// The built-in QCK Prism Cloth.
+pub fn builtin() -> Model {
+    parse(BUILTIN).expect("the built-in definition is valid")
+}
+
+/// The directories definitions are loaded from, later ones take precedence.
+pub fn dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from(SYSTEM_DIR)];
+    if let Some(dir) = config_dir() {
+        dirs.push(dir.join("devices"));
+    }
+
+    dirs
+}
+
+/// Loads all `.toml` files in `dir` in alphabetical order. A missing
+/// directory holds no definitions.
+pub fn load_dir(dir: &Path) -> (Vec<Model>, Vec<DefinitionError>) {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "toml")
+            })
+            .collect(),
+        Err(_) => return (vec![], vec![]),
+    };
+    paths.sort();
+
+    let mut models = vec![];
+    let mut errors = vec![];
+
+    for path in paths {
+        let result = fs::read_to_string(&path)
+            .map_err(DefinitionError::new)
+            .and_then(|text| parse(&text));
+
+        match result {
+            Ok(model) => models.push(model),
+            Err(e) => errors.push(DefinitionError {
+                path: Some(path),
+                ..e
+            }),
+        }
+    }
+
+    (models, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{FEATURE_REPORT, OUTPUT_REPORT};
+    use crate::{PRODUCT_ID, VENDOR_ID};
+
+    const OFFSETS: &str = r#"
+        name = "Pad with three zones"
+        vendor_id = 0x1038
+        product_id = 0x1234
+        modes = ["steady"]
+
+        [zone_report]
+        value = 0x0300
+        id = 0x0b
+        len = 32
+        format = "offsets"
+        rgb_offsets = [2, 5, 8]
+
+        [apply_report]
+        value = 0x0200
+        id = 0x09
+        len = 32
+    "#;
+
+    #[test]
+    fn builtin_definition_matches_the_qck_prism_cloth() {
+        let model = builtin();
+        let capabilities = &model.capabilities;
+
+        assert_eq!((model.vendor_id, model.product_id), (VENDOR_ID, PRODUCT_ID));
+        assert_eq!(capabilities.zones, 2);
+        assert_eq!(capabilities.zone_format, ZoneFormat::Keyframes);
+        assert_eq!(
+            capabilities.zone_report,
+            ReportLayout {
+                value: FEATURE_REPORT,
+                index: 0,
+                id: 0x0e,
+                len: 524
+            }
+        );
+        assert_eq!(
+            (
+                capabilities.apply_report.value,
+                capabilities.apply_report.id
+            ),
+            (OUTPUT_REPORT, 0x0d)
+        );
+
+        let intensity = capabilities.intensity_report.as_ref().unwrap();
+        assert_eq!((intensity.layout.id, intensity.offset), (0x0c, 2));
+    }
+
+    #[test]
+    fn parses_zones_at_byte_offsets() {
+        let model = parse(OFFSETS).unwrap();
+
+        assert_eq!(model.capabilities.zones, 3);
+        assert_eq!(
+            model.capabilities.zone_format,
+            ZoneFormat::Offsets(vec![2, 5, 8])
+        );
+        assert_eq!(model.capabilities.intensity_report, None);
+    }
+
+    #[test]
+    fn rejects_inconsistent_definitions() {
+        let error = |text: String| parse(&text).unwrap_err().message;
+
+        assert_eq!(
+            error(OFFSETS.replace("[2, 5, 8]", "[2, 5, 30]")),
+            "rgb offset 30 is outside of the 32 byte zone_report"
+        );
+        assert_eq!(
+            error(OFFSETS.replace(r#"["steady"]"#, r#"["steady", "breathe"]"#)),
+            "pads with format = \"offsets\" only support the steady mode"
+        );
+        assert_eq!(
+            error(OFFSETS.replace("format = \"offsets\"", "format = \"keyframes\"")),
+            "rgb_offsets is only used with format = \"offsets\""
+        );
+        assert!(error(OFFSETS.replace("len = 32", "length = 32")).contains("length"));
+    }
+
+    #[test]
+    fn loads_toml_files_and_reports_invalid_ones() {
+        let dir = std::env::temp_dir().join(format!("rqck-definitions-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.toml"), OFFSETS).unwrap();
+        fs::write(dir.join("b.toml"), "name = 1").unwrap();
+        fs::write(dir.join("notes.txt"), "not a definition").unwrap();
+
+        let (models, errors) = load_dir(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].product_id, 0x1234);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, Some(dir.join("b.toml")));
+
+        assert_eq!(load_dir(&dir), (vec![], vec![]));
+    }
+}
@@ -0,0 +1,226 @@
+//! Enumeration results and the selection of pads on machines with more than
+//! one of them.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use rusb::Context;
+use serde::Serialize;
+
+use crate::registry::Model;
+use crate::{hidraw, usb};
+
+/// A pad found on the bus, as shown by the `list` subcommand.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PadInfo {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub bus: Option<u8>,
+    pub address: Option<u8>,
+    /// Physical port, e.g. `1-2.3`
+    pub port_path: Option<String>,
+    pub serial: Option<String>,
+    /// Device release number (bcdDevice), e.g. `1.04`
+    pub firmware: Option<String>,
+    pub hidraw: Option<PathBuf>,
+    /// Why the pad could not be queried completely, e.g. missing permissions
+    pub error: Option<String>,
+}
+
+impl PadInfo {
+    /// Stable name of the pad: its serial or, without one, its port.
+    pub fn id(&self) -> Option<&str> {
+        self.serial.as_deref().or(self.port_path.as_deref())
+    }
+
+    /// A `--device` value that selects this pad: the serial or, without one,
+    /// the bus and address or the hidraw node.
+    pub fn selector(&self) -> String {
+        match (&self.serial, self.bus.zip(self.address), &self.hidraw) {
+            (Some(serial), _, _) => serial.clone(),
+            (None, Some((bus, address)), _) => format!("{}:{}", bus, address),
+            (None, None, Some(path)) => path.display().to_string(),
+            (None, None, None) => String::new(),
+        }
+    }
+}
+
+/// Formats a binary coded decimal version like `0x0104` as `1.04`.
+pub fn format_bcd(bcd: u16) -> String {
+    format!("{:x}.{:02x}", bcd >> 8, bcd & 0xff)
+}
+
+fn or_dash<T: fmt::Display>(value: &Option<T>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "-".to_string(),
+    }
+}
+
+/// The pads as a table, one row per pad.
+pub fn format_table(pads: &[PadInfo]) -> String {
+    let mut table = format!(
+        "{:<10}{:<4}{:<5}{:<10}{:<20}{:<9}{}\n",
+        "ID", "BUS", "ADDR", "PORT", "SERIAL", "FIRMWARE", "HIDRAW"
+    );
+
+    for pad in pads {
+        table.push_str(&format!(
+            "{:<10}{:<4}{:<5}{:<10}{:<20}{:<9}{}",
+            format!("{:04x}:{:04x}", pad.vendor_id, pad.product_id),
+            or_dash(&pad.bus.map(|bus| format!("{:03}", bus))),
+            or_dash(&pad.address.map(|address| format!("{:03}", address))),
+            or_dash(&pad.port_path),
+            or_dash(&pad.serial),
+            or_dash(&pad.firmware),
+            or_dash(&pad.hidraw.as_ref().map(|path| path.display())),
+        ));
+
+        if let Some(error) = &pad.error {
+            table.push_str(&format!(" ({})", error));
+        }
+        table.push('\n');
+    }
+
+    table
+}
+
+/// Which pad a command should run on, given with `--device`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeviceSelector {
+    /// A hidraw node, e.g. `/dev/hidraw3`
+    Hidraw(PathBuf),
+    /// Bus and address as shown by `lsusb`, e.g. `1:7`
+    BusAddress(u8, u8),
+    Serial(String),
+}
+
+impl DeviceSelector {
+    pub fn matches(&self, pad: &PadInfo) -> bool {
+        match self {
+            DeviceSelector::Hidraw(path) => pad.hidraw.as_ref() == Some(path),
+            DeviceSelector::BusAddress(bus, address) => {
+                pad.bus == Some(*bus) && pad.address == Some(*address)
+            }
+            DeviceSelector::Serial(serial) => pad.serial.as_ref() == Some(serial),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseSelectorError(String);
+
+impl fmt::Display for ParseSelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "'{}' is not a device, expected a serial, BUS:ADDRESS or a hidraw path",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseSelectorError {}
+
+/// Paths start with `/`, `BUS:ADDRESS` are two numbers, everything else is a
+/// serial.
+impl FromStr for DeviceSelector {
+    type Err = ParseSelectorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(ParseSelectorError(s.to_string()));
+        }
+
+        if s.starts_with('/') {
+            return Ok(DeviceSelector::Hidraw(PathBuf::from(s)));
+        }
+
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() == 2 && parts.iter().all(|part| part.parse::<u32>().is_ok()) {
+            return match (parts[0].parse(), parts[1].parse()) {
+                (Ok(bus), Ok(address)) => Ok(DeviceSelector::BusAddress(bus, address)),
+                _ => Err(ParseSelectorError(s.to_string())),
+            };
+        }
+
+        Ok(DeviceSelector::Serial(s.to_string()))
+    }
+}
+
+/// The pads a command runs on: every pad that matches `selector` with `all`,
+/// otherwise only the first one.
+pub fn select(pads: Vec<PadInfo>, selector: Option<&DeviceSelector>, all: bool) -> Vec<PadInfo> {
+    let matching = pads
+        .into_iter()
+        .filter(|pad| selector.is_none_or(|selector| selector.matches(pad)));
+
+    if all {
+        matching.collect()
+    } else {
+        matching.take(1).collect()
+    }
+}
+
+/// The pads of all `models`, found through sysfs or, with a `context`,
+/// libusb.
+pub fn list_pads(context: Option<&Context>, models: &[Model]) -> crate::Result<Vec<PadInfo>> {
+    let mut pads = vec![];
+
+    for model in models {
+        match context {
+            None => pads.extend(hidraw::list_pads(model.vendor_id, model.product_id)),
+            Some(context) => {
+                pads.extend(usb::list_pads(context, model.vendor_id, model.product_id)?)
+            }
+        }
+    }
+
+    Ok(pads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pad(bus: u8, address: u8, serial: &str) -> PadInfo {
+        PadInfo {
+            bus: Some(bus),
+            address: Some(address),
+            serial: Some(serial.to_string()),
+            ..PadInfo::default()
+        }
+    }
+
+    #[test]
+    fn parses_selectors() {
+        assert_eq!(
+            "/dev/hidraw3".parse(),
+            Ok(DeviceSelector::Hidraw(PathBuf::from("/dev/hidraw3")))
+        );
+        assert_eq!("1:007".parse(), Ok(DeviceSelector::BusAddress(1, 7)));
+        assert_eq!(
+            "A1B2:C3".parse(),
+            Ok(DeviceSelector::Serial("A1B2:C3".to_string()))
+        );
+        assert!("1:300".parse::<DeviceSelector>().is_err());
+    }
+
+    #[test]
+    fn selects_first_or_all_matching_pads() {
+        let pads = vec![pad(1, 3, "AAA"), pad(1, 4, "BBB"), pad(2, 3, "CCC")];
+
+        assert_eq!(select(pads.clone(), None, false), vec![pads[0].clone()]);
+        assert_eq!(select(pads.clone(), None, true), pads);
+
+        let selector = DeviceSelector::Serial("BBB".to_string());
+        assert_eq!(
+            select(pads.clone(), Some(&selector), true),
+            vec![pads[1].clone()]
+        );
+
+        let selector = DeviceSelector::BusAddress(2, 4);
+        assert!(select(pads, Some(&selector), false).is_empty());
+    }
+}
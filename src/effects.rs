@@ -0,0 +1,471 @@
+//! Effects the firmware does not have, computed on the host.
+//!
+//! An [`Effect`] calculates the color of every zone for a point in time.
+//! [`play`] does that at a fixed frame rate and writes each frame to the pad
+//! as a steady zone color report followed by `apply_changes`.
+//!
+//! Effects without an end are stopped with Ctrl-C. [`play_until_interrupted`]
+//! catches SIGINT and SIGTERM, shows the configuration of the pad again and
+//! returns, so the transport is dropped and gives the pad back to the kernel
+//! driver.
+
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::arg_enum;
+
+use crate::color::{from_hsv, from_kelvin};
+use crate::report::ZoneColorReport;
+use crate::{ColorRGB, Pad, Result, BLACK_COLOR};
+
+/// Every frame is a zone color report and an apply, at higher frame rates
+/// they do not get to the pad in time.
+pub const MAX_FPS: u32 = 60;
+
+/// Set by the handler of SIGINT and SIGTERM, see [`stop_on_interrupt`].
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// The colors of the zones over time.
+pub trait Effect {
+    /// The color of every zone, starting with zone 0, `time` after the
+    /// effect was started.
+    fn frame(&mut self, time: Duration, zones: u8) -> Vec<ColorRGB>;
+}
+
+arg_enum! {
+    /// The built-in effects
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub enum EffectName {
+        Rainbow,
+        Wave,
+        Strobe,
+        Candle,
+        Heartbeat,
+        Police
+    }
+}
+
+impl EffectName {
+    /// The effect with its default color and period unless they are given.
+    /// Rainbow and police have their own colors and ignore `color`.
+    pub fn build(self, color: Option<ColorRGB>, period: Option<Duration>) -> Box<dyn Effect> {
+        let period = period.unwrap_or_else(|| self.default_period());
+
+        match self {
+            EffectName::Rainbow => Box::new(Rainbow { period }),
+            EffectName::Wave => Box::new(Wave {
+                color: color.unwrap_or(ColorRGB::new(0x00, 0x80, 0xff)),
+                period,
+            }),
+            EffectName::Strobe => Box::new(Strobe {
+                color: color.unwrap_or(ColorRGB::new(0xff, 0xff, 0xff)),
+                period,
+            }),
+            EffectName::Candle => Box::new(Candle::new(
+                color.unwrap_or_else(|| from_kelvin(1900)),
+                period,
+            )),
+            EffectName::Heartbeat => Box::new(Heartbeat {
+                color: color.unwrap_or(ColorRGB::new(0xff, 0x00, 0x00)),
+                period,
+            }),
+            EffectName::Police => Box::new(Police { period }),
+        }
+    }
+
+    /// Length of one cycle, for the candle the time between two flickers.
+    pub fn default_period(self) -> Duration {
+        Duration::from_millis(match self {
+            EffectName::Rainbow => 5000,
+            EffectName::Wave => 2000,
+            EffectName::Strobe => 200,
+            EffectName::Candle => 100,
+            EffectName::Heartbeat => 1000,
+            EffectName::Police => 1000,
+        })
+    }
+}
+
+/// Cycles every zone through all hues, the lower zones lag behind the upper
+/// ones.
+pub struct Rainbow {
+    pub period: Duration,
+}
+
+impl Effect for Rainbow {
+    fn frame(&mut self, time: Duration, zones: u8) -> Vec<ColorRGB> {
+        let hue = phase(time, self.period) * 360.0;
+
+        (0..zones)
+            .map(|zone| from_hsv(hue - 30.0 * f64::from(zones - 1 - zone), 1.0, 1.0))
+            .collect()
+    }
+}
+
+/// A pulse of the color that runs from the upper to the lower zone.
+pub struct Wave {
+    pub color: ColorRGB,
+    pub period: Duration,
+}
+
+impl Effect for Wave {
+    fn frame(&mut self, time: Duration, zones: u8) -> Vec<ColorRGB> {
+        // runs from one zone before the upper zone to one zone after the
+        // lower zone, so every zone is dark once per period
+        let position = phase(time, self.period) * (f64::from(zones) + 1.0) - 1.0;
+
+        (0..zones)
+            .map(|zone| {
+                let distance = (position - f64::from(zones - 1 - zone)).abs();
+                scale(self.color, 1.0 - distance)
+            })
+            .collect()
+    }
+}
+
+/// Flashes all zones, on for the first half of every period.
+pub struct Strobe {
+    pub color: ColorRGB,
+    pub period: Duration,
+}
+
+impl Effect for Strobe {
+    fn frame(&mut self, time: Duration, zones: u8) -> Vec<ColorRGB> {
+        let color = if phase(time, self.period) < 0.5 {
+            self.color
+        } else {
+            BLACK_COLOR
+        };
+
+        vec![color; zones as usize]
+    }
+}
+
+/// Every zone flickers on its own like a candle flame.
+pub struct Candle {
+    color: ColorRGB,
+    period: Duration,
+    random: u32,
+    /// The brightness of every zone at the last flicker and the next one
+    levels: Vec<(f64, f64)>,
+    flicker: u64,
+}
+
+impl Candle {
+    pub fn new(color: ColorRGB, period: Duration) -> Self {
+        Candle {
+            color,
+            period,
+            random: 0x2545_f491,
+            levels: vec![],
+            flicker: 0,
+        }
+    }
+
+    /// xorshift, the flame does not need more
+    fn next_level(&mut self) -> f64 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+
+        0.55 + 0.45 * f64::from(self.random) / f64::from(u32::MAX)
+    }
+}
+
+impl Effect for Candle {
+    fn frame(&mut self, time: Duration, zones: u8) -> Vec<ColorRGB> {
+        let flickers = time.as_secs_f64() / self.period.as_secs_f64().max(0.001);
+
+        while self.levels.len() < zones as usize {
+            let level = self.next_level();
+            self.levels.push((level, level));
+        }
+
+        while self.flicker < flickers as u64 {
+            for i in 0..self.levels.len() {
+                let next = self.next_level();
+                self.levels[i] = (self.levels[i].1, next);
+            }
+            self.flicker += 1;
+        }
+
+        let t = flickers.fract();
+        self.levels
+            .iter()
+            .take(zones as usize)
+            .map(|(from, to)| scale(self.color, from + (to - from) * t))
+            .collect()
+    }
+}
+
+/// Two beats per period, the second one weaker, then a pause.
+pub struct Heartbeat {
+    pub color: ColorRGB,
+    pub period: Duration,
+}
+
+impl Effect for Heartbeat {
+    fn frame(&mut self, time: Duration, zones: u8) -> Vec<ColorRGB> {
+        let phase = phase(time, self.period);
+        let beat = |start: f64| {
+            let t = (phase - start) / 0.15;
+            if (0.0..1.0).contains(&t) {
+                1.0 - t
+            } else {
+                0.0
+            }
+        };
+
+        let level = beat(0.0).max(0.6 * beat(0.25));
+        vec![scale(self.color, level); zones as usize]
+    }
+}
+
+/// The upper zones flash red twice, then the lower zones flash blue twice.
+/// With more zones every other zone flashes.
+pub struct Police {
+    pub period: Duration,
+}
+
+impl Effect for Police {
+    fn frame(&mut self, time: Duration, zones: u8) -> Vec<ColorRGB> {
+        let phase = phase(time, self.period);
+        let red = phase < 0.5;
+        // two flashes per half: on in the first and third eighth
+        let lit = matches!((phase * 8.0) as u8 % 4, 0 | 2);
+
+        (0..zones)
+            .map(|zone| {
+                let upper = (zones - 1 - zone).is_multiple_of(2);
+                match (lit, red, upper) {
+                    (true, true, true) => ColorRGB::new(0xff, 0x00, 0x00),
+                    (true, false, false) => ColorRGB::new(0x00, 0x00, 0xff),
+                    _ => BLACK_COLOR,
+                }
+            })
+            .collect()
+    }
+}
+
+/// How long and how fast an effect is played.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Playback {
+    pub fps: u32,
+    /// Number of zones of the pad
+    pub zones: u8,
+    /// Plays until an error occurs if `None`
+    pub length: Option<Duration>,
+}
+
+/// Plays the effect on the pad and returns the number of frames written.
+///
+/// Frames are computed for their scheduled time, if writing to the pad takes
+/// longer than a frame the late frames are skipped. Stops before the next
+/// frame once [`interrupted`].
+pub fn play<P: Pad + ?Sized>(
+    pad: &mut P,
+    effect: &mut dyn Effect,
+    playback: &Playback,
+) -> Result<u64> {
+    let interval = Duration::from_secs(1) / playback.fps.max(1);
+    let start = Instant::now();
+    let mut written = 0;
+    let mut frame = 0u32;
+
+    loop {
+        let time = interval * frame;
+        if playback.length.is_some_and(|length| time >= length) || interrupted() {
+            return Ok(written);
+        }
+
+        let colors = effect.frame(time, playback.zones);
+        pad.show_frame(&ZoneColorReport::per_zone(&colors))?;
+        written += 1;
+
+        let elapsed = start.elapsed();
+        frame = (frame + 1).max((elapsed.as_secs_f64() / interval.as_secs_f64()) as u32);
+        if let Some(wait) = (interval * frame).checked_sub(elapsed) {
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// Plays the effect like [`play`] until it ends or the process gets SIGINT
+/// or SIGTERM. An interrupted effect leaves the pad showing its
+/// configuration, see [`Pad::restore_configuration`].
+pub fn play_until_interrupted<P: Pad + ?Sized>(
+    pad: &mut P,
+    effect: &mut dyn Effect,
+    playback: &Playback,
+) -> Result<u64> {
+    stop_on_interrupt()?;
+    let written = play(pad, effect, playback)?;
+
+    if interrupted() {
+        pad.restore_configuration()?;
+    }
+    Ok(written)
+}
+
+/// Makes SIGINT and SIGTERM set [`interrupted`] instead of ending the
+/// process. The handler is reset by the first signal, so a second Ctrl-C
+/// still ends a process that does not stop.
+pub fn stop_on_interrupt() -> io::Result<()> {
+    extern "C" fn handle(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // the handler only stores to an atomic, which is safe in a signal
+        // handler
+        let result = unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESETHAND | libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, ptr::null_mut())
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether SIGINT or SIGTERM arrived since [`stop_on_interrupt`].
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Position within the current period, between 0 and 1.
+fn phase(time: Duration, period: Duration) -> f64 {
+    let period = period.as_secs_f64();
+    if period <= 0.0 {
+        return 0.0;
+    }
+
+    (time.as_secs_f64() / period).fract()
+}
+
+/// The color at `level` between 0 (black) and 1.
+pub(crate) fn scale(color: ColorRGB, level: f64) -> ColorRGB {
+    let level = level.clamp(0.0, 1.0);
+    let component = |value: u8| (f64::from(value) * level).round() as u8;
+
+    ColorRGB::new(
+        component(color.red),
+        component(color.green),
+        component(color.blue),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{MockTransport, FEATURE_REPORT, OUTPUT_REPORT};
+    use crate::QckPad;
+
+    const RED: ColorRGB = ColorRGB {
+        red: 0xff,
+        green: 0,
+        blue: 0,
+    };
+
+    fn at(effect: &mut dyn Effect, millis: u64) -> Vec<ColorRGB> {
+        effect.frame(Duration::from_millis(millis), 2)
+    }
+
+    #[test]
+    fn effects_compute_every_zone() {
+        let mut rainbow = EffectName::Rainbow.build(None, Some(Duration::from_secs(6)));
+        assert_eq!(
+            at(&mut *rainbow, 0),
+            vec![ColorRGB::new(0xff, 0x00, 0x80), RED]
+        );
+        assert_eq!(
+            at(&mut *rainbow, 1000),
+            vec![
+                ColorRGB::new(0xff, 0x80, 0x00),
+                ColorRGB::new(0xff, 0xff, 0x00)
+            ]
+        );
+
+        // the pulse is at the upper zone after a third, at the lower one
+        // after two thirds of the period
+        let mut wave = EffectName::Wave.build(Some(RED), Some(Duration::from_millis(3000)));
+        assert_eq!(at(&mut *wave, 1000), vec![BLACK_COLOR, RED]);
+        assert_eq!(
+            at(&mut *wave, 1500),
+            vec![ColorRGB::new(0x80, 0, 0), ColorRGB::new(0x80, 0, 0)]
+        );
+        assert_eq!(at(&mut *wave, 2000), vec![RED, BLACK_COLOR]);
+
+        let mut strobe = EffectName::Strobe.build(Some(RED), None);
+        assert_eq!(at(&mut *strobe, 50), vec![RED, RED]);
+        assert_eq!(at(&mut *strobe, 150), vec![BLACK_COLOR, BLACK_COLOR]);
+
+        let mut heartbeat = EffectName::Heartbeat.build(Some(RED), None);
+        assert_eq!(at(&mut *heartbeat, 0), vec![RED, RED]);
+        assert_eq!(at(&mut *heartbeat, 250)[0], ColorRGB::new(0x99, 0, 0));
+        assert_eq!(at(&mut *heartbeat, 600), vec![BLACK_COLOR, BLACK_COLOR]);
+
+        let blue = ColorRGB::new(0, 0, 0xff);
+        let mut police = EffectName::Police.build(None, None);
+        assert_eq!(at(&mut *police, 0), vec![BLACK_COLOR, RED]);
+        assert_eq!(at(&mut *police, 200), vec![BLACK_COLOR, BLACK_COLOR]);
+        assert_eq!(at(&mut *police, 500), vec![blue, BLACK_COLOR]);
+    }
+
+    #[test]
+    fn effects_compute_up_to_255_zones() {
+        for name in [
+            EffectName::Rainbow,
+            EffectName::Wave,
+            EffectName::Strobe,
+            EffectName::Candle,
+            EffectName::Heartbeat,
+            EffectName::Police,
+        ] {
+            let mut effect = name.build(None, None);
+            assert_eq!(effect.frame(Duration::from_millis(500), 255).len(), 255);
+        }
+    }
+
+    #[test]
+    fn candle_flickers_between_dim_and_full() {
+        let mut candle = EffectName::Candle.build(Some(RED), None);
+
+        let frames: Vec<_> = (0..50).map(|i| at(&mut *candle, i * 33)).collect();
+        for colors in &frames {
+            assert!(colors.iter().all(|c| c.red >= 0x8c && c.green == 0));
+        }
+        assert!(frames.windows(2).any(|pair| pair[0] != pair[1]));
+        assert!(frames.iter().any(|colors| colors[0] != colors[1]));
+    }
+
+    #[test]
+    fn play_writes_and_applies_every_frame() {
+        let mut pad = QckPad::new(MockTransport::new());
+        let mut effect = EffectName::Strobe.build(Some(RED), Some(Duration::from_millis(40)));
+        let playback = Playback {
+            fps: 50,
+            zones: 2,
+            length: Some(Duration::from_millis(100)),
+        };
+
+        let written = play(&mut pad, &mut *effect, &playback).unwrap();
+        assert!((1..=5).contains(&written), "{} frames", written);
+
+        let sent = pad.transport().take_sent();
+        assert_eq!(sent.len() as u64, 2 * written);
+        for pair in sent.chunks(2) {
+            assert_eq!((pair[0].value, pair[0].data[0]), (FEATURE_REPORT, 0x0e));
+            assert_eq!((pair[1].value, pair[1].data[0]), (OUTPUT_REPORT, 0x0d));
+        }
+    }
+}
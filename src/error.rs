@@ -0,0 +1,94 @@
+use std::fmt;
+use std::io;
+
+use crate::capture::CaptureError;
+use crate::config::ConfigError;
+use crate::profile::ProfileError;
+use crate::report::ReportError;
+use crate::rpc::RpcError;
+
+#[derive(Debug)]
+pub enum Error {
+    Usb(rusb::Error),
+    Io(io::Error),
+    Report(ReportError),
+    Capture(CaptureError),
+    Profile(ProfileError),
+    Config(ConfigError),
+    /// An error returned by `rqckd`
+    Daemon(RpcError),
+    Bus(zbus::Error),
+    /// A mode, zone or report the pad does not have
+    Unsupported(String),
+    /// Invalid command line arguments
+    Cli(clap::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usb(e) => write!(f, "usb error: {}", e),
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Report(e) => write!(f, "invalid report: {}", e),
+            Error::Capture(e) => write!(f, "invalid capture: {}", e),
+            Error::Profile(e) => write!(f, "invalid profile: {}", e),
+            Error::Config(e) => write!(f, "invalid configuration: {}", e),
+            Error::Daemon(e) => write!(f, "rqckd: {}", e),
+            Error::Bus(e) => write!(f, "d-bus error: {}", e),
+            Error::Unsupported(what) => write!(f, "not supported by the pad: {}", what),
+            Error::Cli(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<rusb::Error> for Error {
+    fn from(e: rusb::Error) -> Self {
+        Error::Usb(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<CaptureError> for Error {
+    fn from(e: CaptureError) -> Self {
+        Error::Capture(e)
+    }
+}
+
+impl From<ReportError> for Error {
+    fn from(e: ReportError) -> Self {
+        Error::Report(e)
+    }
+}
+
+impl From<ProfileError> for Error {
+    fn from(e: ProfileError) -> Self {
+        Error::Profile(e)
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::Config(e)
+    }
+}
+
+impl From<RpcError> for Error {
+    fn from(e: RpcError) -> Self {
+        Error::Daemon(e)
+    }
+}
+
+impl From<zbus::Error> for Error {
+    fn from(e: zbus::Error) -> Self {
+        Error::Bus(e)
+    }
+}
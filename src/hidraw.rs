@@ -0,0 +1,327 @@
+//! Access to the pad through the `hidraw` driver of the kernel.
+//!
+//! Unlike [`crate::usb::UsbTransport`] this leaves `usbhid` bound to the pad,
+//! nothing is detached or claimed. Feature reports are sent with
+//! `HIDIOCSFEATURE`, output reports with a plain `write`.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::device::{format_bcd, PadInfo};
+use crate::transport::Transport;
+use crate::Result;
+
+const HIDIOCSFEATURE: u8 = 0x06;
+const HIDIOCGFEATURE: u8 = 0x07;
+const HIDIOCGOUTPUT: u8 = 0x0c;
+
+/// `_IOC(_IOC_READ | _IOC_WRITE, 'H', nr, len)`
+fn hid_ioctl(nr: u8, len: usize) -> libc::c_ulong {
+    (3 << 30)
+        | ((len as libc::c_ulong) << 16)
+        | ((b'H' as libc::c_ulong) << 8)
+        | nr as libc::c_ulong
+}
+
+/// A `/dev/hidrawN` node that belongs to a pad.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HidrawDevice {
+    pub path: PathBuf,
+    /// USB interface of the node, `None` for devices that are not on USB
+    pub interface: Option<u8>,
+    /// sysfs directory of the USB device the node belongs to
+    pub usb_device: Option<PathBuf>,
+}
+
+/// Finds the hidraw nodes of all devices with the ids through sysfs, ordered
+/// by interface.
+pub fn find_devices(vid: u16, pid: u16) -> Vec<HidrawDevice> {
+    find_devices_in(Path::new("/sys/class/hidraw"), Path::new("/dev"), vid, pid)
+}
+
+fn find_devices_in(class: &Path, dev: &Path, vid: u16, pid: u16) -> Vec<HidrawDevice> {
+    let entries = match fs::read_dir(class) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    let mut devices: Vec<HidrawDevice> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            fs::read_to_string(entry.path().join("device/uevent"))
+                .ok()
+                .and_then(|uevent| parse_hid_id(&uevent))
+                == Some((vid, pid))
+        })
+        .map(|entry| {
+            // the parent of the hid device is the usb interface, its parent
+            // the usb device
+            let interface = read_attribute(&entry.path().join("device/.."), "bInterfaceNumber")
+                .and_then(|number| u8::from_str_radix(&number, 16).ok());
+            let usb_device =
+                interface.and_then(|_| fs::canonicalize(entry.path().join("device/../..")).ok());
+
+            HidrawDevice {
+                path: dev.join(entry.file_name()),
+                interface,
+                usb_device,
+            }
+        })
+        .collect();
+
+    devices.sort_by(|a, b| {
+        (a.interface.is_none(), a.interface, &a.path).cmp(&(
+            b.interface.is_none(),
+            b.interface,
+            &b.path,
+        ))
+    });
+    devices
+}
+
+/// Lists every pad once, with the hidraw node of its first interface.
+pub fn list_pads(vid: u16, pid: u16) -> Vec<PadInfo> {
+    pads_of(find_devices(vid, pid))
+        .into_iter()
+        .map(|pad| PadInfo {
+            vendor_id: vid,
+            product_id: pid,
+            ..pad
+        })
+        .collect()
+}
+
+fn pads_of(devices: Vec<HidrawDevice>) -> Vec<PadInfo> {
+    let mut pads: Vec<PadInfo> = vec![];
+    let mut seen: Vec<PathBuf> = vec![];
+
+    for device in devices {
+        let usb_device = match device.usb_device {
+            Some(usb_device) if seen.contains(&usb_device) => continue,
+            Some(usb_device) => usb_device,
+            None => {
+                pads.push(PadInfo {
+                    hidraw: Some(device.path),
+                    ..PadInfo::default()
+                });
+                continue;
+            }
+        };
+
+        pads.push(PadInfo {
+            bus: read_attribute(&usb_device, "busnum").and_then(|bus| bus.parse().ok()),
+            address: read_attribute(&usb_device, "devnum").and_then(|address| address.parse().ok()),
+            port_path: usb_device
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            serial: read_attribute(&usb_device, "serial"),
+            firmware: read_attribute(&usb_device, "bcdDevice")
+                .and_then(|bcd| u16::from_str_radix(&bcd, 16).ok())
+                .map(format_bcd),
+            hidraw: Some(device.path),
+            ..PadInfo::default()
+        });
+        seen.push(usb_device);
+    }
+
+    pads
+}
+
+fn read_attribute(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+/// Vendor and product id from the `HID_ID=0003:00001038:0000150D` line of a
+/// uevent file.
+fn parse_hid_id(uevent: &str) -> Option<(u16, u16)> {
+    let id = uevent
+        .lines()
+        .find_map(|line| line.strip_prefix("HID_ID="))?;
+    let mut parts = id.split(':').skip(1);
+
+    let vid = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let pid = u32::from_str_radix(parts.next()?, 16).ok()?;
+
+    Some((vid as u16, pid as u16))
+}
+
+/// Sends the reports to a hidraw node.
+///
+/// The kernel applies its own timeouts to the transfers, the timeouts of the
+/// commands are ignored.
+pub struct HidrawTransport {
+    file: File,
+    path: PathBuf,
+}
+
+impl HidrawTransport {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+
+        Ok(HidrawTransport { file, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn ioctl(&mut self, nr: u8, buf: &mut [u8]) -> io::Result<usize> {
+        let request = hid_ioctl(nr, buf.len());
+        let res = unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, buf.as_mut_ptr()) };
+
+        if res < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(res as usize)
+        }
+    }
+}
+
+/// Splits the `value` of a control transfer into report type and id.
+fn report_type_and_id(value: u16) -> (u8, u8) {
+    ((value >> 8) as u8, value as u8)
+}
+
+fn unsupported_report(value: u16) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("report 0x{:04x} is not supported by hidraw", value),
+    )
+}
+
+/// The node belongs to a single interface, the `index` of the requests is
+/// ignored.
+impl Transport for HidrawTransport {
+    fn write_report(
+        &mut self,
+        value: u16,
+        _index: u16,
+        data: &[u8],
+        _timeout: Duration,
+    ) -> Result<usize> {
+        let (report_type, id) = report_type_and_id(value);
+
+        // hidraw expects the report id in front of the data, 0 for unnumbered
+        // reports
+        let mut buf = Vec::with_capacity(data.len() + 1);
+        buf.push(id);
+        buf.extend_from_slice(data);
+
+        let len = match report_type {
+            0x02 => self.file.write(&buf)?,
+            0x03 => self.ioctl(HIDIOCSFEATURE, &mut buf)?,
+            _ => return Err(unsupported_report(value).into()),
+        };
+
+        Ok(len.saturating_sub(1))
+    }
+
+    fn read_report(
+        &mut self,
+        value: u16,
+        _index: u16,
+        data: &mut [u8],
+        _timeout: Duration,
+    ) -> Result<usize> {
+        let (report_type, id) = report_type_and_id(value);
+
+        let mut buf = vec![0u8; data.len() + 1];
+        buf[0] = id;
+
+        let len = match report_type {
+            0x02 => self.ioctl(HIDIOCGOUTPUT, &mut buf)?,
+            0x03 => self.ioctl(HIDIOCGFEATURE, &mut buf)?,
+            _ => return Err(unsupported_report(value).into()),
+        };
+
+        let len = len.saturating_sub(1).min(data.len());
+        data[..len].copy_from_slice(&buf[1..=len]);
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn ioctl_numbers_match_kernel_headers() {
+        // HIDIOCSFEATURE(64) and HIDIOCGFEATURE(525) as computed by the C macros
+        assert_eq!(hid_ioctl(HIDIOCSFEATURE, 64), 0xc040_4806);
+        assert_eq!(hid_ioctl(HIDIOCGFEATURE, 525), 0xc20d_4807);
+    }
+
+    #[test]
+    fn parses_hid_id() {
+        let uevent = "DRIVER=hid-generic\nHID_ID=0003:00001038:0000150D\nHID_NAME=SteelSeries\n";
+        assert_eq!(parse_hid_id(uevent), Some((0x1038, 0x150d)));
+        assert_eq!(parse_hid_id("DRIVER=hid-generic\n"), None);
+    }
+
+    #[test]
+    fn finds_devices_in_sysfs() {
+        let root = std::env::temp_dir().join(format!("rqck-sysfs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let fake_device = |name: &str, port: &str, interface: &str, hid_id: &str| {
+            let usb_device = root.join("devices").join(port);
+            let usb_interface = usb_device.join(format!("{}:1.{}", port, interface));
+            let hid = usb_interface.join("hid");
+            fs::create_dir_all(&hid).unwrap();
+            fs::write(usb_device.join("busnum"), "1\n").unwrap();
+            fs::write(usb_device.join("devnum"), "7\n").unwrap();
+            fs::write(usb_device.join("serial"), "ABC123\n").unwrap();
+            fs::write(usb_device.join("bcdDevice"), "0104\n").unwrap();
+            fs::write(usb_interface.join("bInterfaceNumber"), interface).unwrap();
+            fs::write(hid.join("uevent"), format!("HID_ID={}\n", hid_id)).unwrap();
+
+            let class = root.join("class").join(name);
+            fs::create_dir_all(&class).unwrap();
+            symlink(&hid, class.join("device")).unwrap();
+        };
+        fake_device("hidraw3", "1-2", "01", "0003:00001038:0000150D");
+        fake_device("hidraw2", "1-2", "00", "0003:00001038:0000150D");
+        fake_device("hidraw1", "1-3", "00", "0003:0000046D:0000C52B");
+
+        let devices = find_devices_in(&root.join("class"), Path::new("/dev"), 0x1038, 0x150d);
+        let usb_device = fs::canonicalize(root.join("devices/1-2")).unwrap();
+        let pads = pads_of(devices.clone());
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            devices,
+            vec![
+                HidrawDevice {
+                    path: PathBuf::from("/dev/hidraw2"),
+                    interface: Some(0),
+                    usb_device: Some(usb_device.clone()),
+                },
+                HidrawDevice {
+                    path: PathBuf::from("/dev/hidraw3"),
+                    interface: Some(1),
+                    usb_device: Some(usb_device),
+                },
+            ]
+        );
+        assert_eq!(
+            pads,
+            vec![PadInfo {
+                bus: Some(1),
+                address: Some(7),
+                port_path: Some("1-2".to_string()),
+                serial: Some("ABC123".to_string()),
+                firmware: Some("1.04".to_string()),
+                hidraw: Some(PathBuf::from("/dev/hidraw2")),
+                ..PadInfo::default()
+            }]
+        );
+    }
+}
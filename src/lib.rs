@@ -0,0 +1,46 @@
+//! Control library for the SteelSeries QCK Prism Cloth mousepad.
+//!
+//! [`QckPad`] owns the [`transport::Transport`] to the pad and offers the high
+//! level operations, the raw reports are available in [`commands`] and
+//! [`report`].
+
+pub mod capture;
+pub mod cli;
+pub mod color;
+pub mod commands;
+pub mod compositor;
+pub mod config;
+pub mod daemon;
+pub mod dbus;
+pub mod decode;
+pub mod definition;
+pub mod device;
+pub mod effects;
+mod error;
+pub mod hidraw;
+pub mod modes;
+pub mod openrgb;
+mod pad;
+mod paths;
+pub mod profile;
+pub mod registry;
+pub mod report;
+pub mod rpc;
+pub mod state;
+pub mod status;
+pub mod timeline;
+pub mod transition;
+pub mod transport;
+pub mod units;
+pub mod usb;
+pub mod watch;
+
+pub use commands::{ColorRGB, Zone, BLACK_COLOR};
+pub use error::{Error, Result};
+pub use modes::{ColorShift, EmulatedBreathe, KeyFrame, Mode};
+pub use pad::{Pad, QckPad};
+
+/// SteelSeries, see [`registry`] for all supported pads
+pub const VENDOR_ID: u16 = 0x1038;
+/// QCK Prism Cloth
+pub const PRODUCT_ID: u16 = 0x150d;
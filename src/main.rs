@@ -1,209 +1,281 @@
-use clap::{clap_app, values_t, ArgMatches};
-use rusb::{Context, Device, DeviceDescriptor, DeviceHandle, Result, UsbContext};
-
-#[derive(Debug)]
-struct Endpoint {
-    config: u8,
-    iface: u8,
-    setting: u8,
-    address: u8,
-}
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::{value_t, ArgMatches};
+use rusb::Context;
 
-mod commands;
-use commands::{
-    apply_changes, disable_lower_zone, disable_upper_zone, set_intensity, switch_mode_to_steady,
-    BlackColor, ColorRGB, Zone,
-};
+use rgame_linux::cli::{self, Backend};
+use rgame_linux::device::{self, DeviceSelector, PadInfo};
+use rgame_linux::registry::{Model, Registry};
+use rgame_linux::rpc::{self, Client, RemotePad};
+use rgame_linux::state::StateCache;
+use rgame_linux::transport::Transport;
+use rgame_linux::watch::{Event, Watcher};
+use rgame_linux::{Error, QckPad, Result};
 
 fn main() -> Result<()> {
-    match Context::new() {
-        Ok(mut context) => match open_device(&mut context, 4152, 5389) {
-            Some((mut device, _, mut handle)) => {
-                let endpoints = find_readable_endpoints(&mut device)?;
-                let endpoint = endpoints
-                    .first()
-                    .expect("No Configurable endpoint found on device");
-                let has_kernel_driver = match handle.kernel_driver_active(endpoint.iface) {
-                    Ok(true) => {
-                        handle.detach_kernel_driver(endpoint.iface)?;
-                        true
-                    }
-                    _ => false,
-                };
-
-                configure_endpoint(&mut handle, endpoint)?;
-
-                let matches = clap_app!(myapp =>
-                    (about: "Controls your Steelseries QCK Cloth mousepad")
-                    (@subcommand set_intensity =>
-                        (about: "Sets the intensity of the LEDs")
-                        (@arg INTENSITY: +required +takes_value -i --intensity "value between 0 and 100 for the intensity")
-                    )
-
-                    (@subcommand disable =>
-                        (about: "Disables one of the two zones of the LEDs on your pad")
-                        (@arg ZONE: +required ... "Zones you want to disable")
-                    )
-
-                    (@subcommand solid =>
-                        (about: "Disables one of the two zones of the LEDs on your pad")
-                        (@arg RED: +required "Amount of red color for the specified ZONE")
-                        (@arg GREEN: +required "Amount of green color for the specified ZONE")
-                        (@arg BLUE: +required "Amount of blue color for the specified ZONE")
-                        (@arg RED2: "Amount of red color for the specified ZONE")
-                        (@arg GREEN2: "Amount of green color for the specified ZONE")
-                        (@arg BLUE2: "Amount of blue color for the specified ZONE")
-                    )
-                ).get_matches();
-
-                if let Some(matches) = matches.subcommand_matches("set_intensity") {
-                    let intensity = matches.value_of("INTENSITY").unwrap();
-
-                    match intensity.parse::<i16>() {
-                        Ok(intensity) => {
-                            let range = 0..101;
-
-                            if !range.contains(&intensity) {
-                                println!("The provided value for INTENSITY is not in the range from 0 to 100.");
-                            }
+    let matches = cli::app().get_matches();
 
-                            let intensity = (intensity as f32 * 2.55) as u8;
-                            set_intensity(&mut handle, intensity)?;
-                        }
-                        Err(_) => println!("The provided value for INTENSITY is not a number"),
-                    }
-                }
+    if let Some(matches) = matches.subcommand_matches("decode") {
+        return cli::decode(matches);
+    }
 
-                if let Some(matches) = matches.subcommand_matches("disable") {
-                    let zones = values_t!(matches, "ZONE", Zone).unwrap_or_else(|e| e.exit());
+    let profile = matches.subcommand_matches("profile");
+    if profile.is_some_and(|profile| profile.subcommand_matches("list").is_some()) {
+        return cli::list_profiles(&matches);
+    }
 
-                    for zone in zones {
-                        match zone {
-                            Zone::Upper => {
-                                disable_upper_zone(&mut handle)?;
-                                apply_changes(&mut handle)?;
-                            }
-                            Zone::Lower => {
-                                disable_lower_zone(&mut handle)?;
-                                apply_changes(&mut handle)?;
+    let backend = value_t!(matches, "BACKEND", Backend).unwrap_or_else(|e| e.exit());
+    let selector = matches
+        .value_of("DEVICE")
+        .map(|_| value_t!(matches, "DEVICE", DeviceSelector).unwrap_or_else(|e| e.exit()));
+
+    let context = match backend {
+        Backend::Hidraw => None,
+        Backend::Libusb => match Context::new() {
+            Ok(context) => Some(context),
+            Err(e) => panic!("could not initialize libusb: {}", e),
+        },
+    };
+
+    let (registry, errors) = Registry::load();
+    for e in errors {
+        eprintln!("ignoring device definition {}", e);
+    }
+
+    let models = match cli::usb_ids(&matches) {
+        Some((vid, pid)) => vec![registry.model(vid, pid)],
+        None => registry.models().to_vec(),
+    };
+
+    if matches.subcommand_matches("watch").is_some() {
+        let context = match context {
+            Some(context) => context,
+            None => Context::new()?,
+        };
+        let libusb = backend == Backend::Libusb;
+
+        return watch(&context, libusb, &registry, &models, selector.as_ref());
+    }
+
+    // rqckd only has the pads of its own models open
+    if matches.subcommand_matches("list").is_none()
+        && !matches.is_present("NO_DAEMON")
+        && cli::usb_ids(&matches).is_none()
+    {
+        let socket = matches
+            .value_of("SOCKET")
+            .map_or_else(rpc::socket_path, PathBuf::from);
+
+        if let Ok(client) = Client::connect(socket) {
+            return run_remote(client, &matches);
+        }
+    }
+
+    let pads = device::list_pads(context.as_ref(), &models)?;
+
+    if matches.subcommand_matches("list").is_some() {
+        if pads.is_empty() {
+            not_found(&models);
+        } else {
+            print!("{}", device::format_table(&pads));
+        }
+        return Ok(());
+    }
+
+    let pads = device::select(pads, selector.as_ref(), matches.is_present("ALL"));
+    if pads.is_empty() {
+        match matches.value_of("DEVICE") {
+            Some(device) => println!("could not find device {}", device),
+            None => not_found(&models),
+        }
+        return Ok(());
+    }
+
+    for info in &pads {
+        if pads.len() > 1 {
+            println!("{}:", name(info));
+        }
+
+        match &context {
+            None => {
+                let path = info.hidraw.as_ref().expect("hidraw pads have a node");
+                run(QckPad::open_hidraw_path(path)?, &registry, info, &matches)?;
+            }
+            Some(context) => {
+                let (bus, address) = (info.bus.unwrap_or(0), info.address.unwrap_or(0));
+                run(
+                    QckPad::open_at(context, bus, address)?,
+                    &registry,
+                    info,
+                    &matches,
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reapplies the last configuration of every pad that is plugged in, until
+/// the process is stopped.
+fn watch(
+    context: &Context,
+    libusb: bool,
+    registry: &Registry,
+    models: &[Model],
+    selector: Option<&DeviceSelector>,
+) -> Result<()> {
+    let mut watcher = Watcher::new(context, models)?;
+    println!("waiting for pads to be plugged in, stop with Ctrl-C");
+
+    loop {
+        for event in watcher.wait(Duration::from_secs(1))? {
+            match event {
+                Event::Arrived { bus, address } => {
+                    let result =
+                        find_arrived(context, libusb, models, bus, address).and_then(|info| {
+                            match info {
+                                Some(info) if selector.is_none_or(|s| s.matches(&info)) => {
+                                    restore(context, libusb, registry, &info)
+                                }
+                                _ => Ok(()),
                             }
-                        }
+                        });
+
+                    if let Err(e) = result {
+                        eprintln!("{:03}:{:03} could not be restored: {}", bus, address, e);
                     }
                 }
+                Event::Left { bus, address } => println!("{:03}:{:03} removed", bus, address),
+            }
+        }
+    }
+}
 
-                if let Some(matches) = matches.subcommand_matches("solid") {
-                    //let zone1 = value_t!(matches, "ZONE", Zone);
-                    let upper_zone_color = parse_color(matches, "RED", "GREEN", "BLUE");
+/// The pad at `bus` and `address`. Its hidraw node shows up only once the
+/// kernel driver is bound, so with the hidraw backend it is waited for.
+fn find_arrived(
+    context: &Context,
+    libusb: bool,
+    models: &[Model],
+    bus: u8,
+    address: u8,
+) -> Result<Option<PadInfo>> {
+    let context = if libusb { Some(context) } else { None };
+    let start = Instant::now();
 
-                    //let zone2 = value_t!(matches, "SECOND_ZONE", Zone);
-                    let lower_zone_color = parse_color(matches, "RED2", "GREEN2", "BLUE2");
+    loop {
+        let found = device::list_pads(context, models)?
+            .into_iter()
+            .find(|info| info.bus == Some(bus) && info.address == Some(address));
 
-                    switch_mode_to_steady(&mut handle, upper_zone_color, lower_zone_color)?;
-                    apply_changes(&mut handle)?;                    
-                }
+        if found.is_some() || libusb || start.elapsed() > Duration::from_secs(5) {
+            return Ok(found);
+        }
 
-                // cleanup after use
-                handle.release_interface(endpoint.iface)?;
-                if has_kernel_driver {
-                    handle.attach_kernel_driver(endpoint.iface)?;
-                }
-            }
-            None => println!("could not find device {:04x}:{:04x}", 4152, 5389),
-        },
-        Err(e) => panic!("could not initialize libusb: {}", e),
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+fn restore(context: &Context, libusb: bool, registry: &Registry, info: &PadInfo) -> Result<()> {
+    let restored = if libusb {
+        let (bus, address) = (info.bus.unwrap_or(0), info.address.unwrap_or(0));
+        restore_pad(QckPad::open_at(context, bus, address)?, registry, info)?
+    } else {
+        let path = info.hidraw.as_ref().expect("hidraw pads have a node");
+        restore_pad(QckPad::open_hidraw_path(path)?, registry, info)?
+    };
+
+    if restored {
+        println!("{} restored", name(info));
+    } else {
+        println!("{} plugged in, no configuration to restore", name(info));
     }
 
     Ok(())
 }
 
-fn parse_color(
+fn restore_pad<T: Transport>(
+    mut pad: QckPad<T>,
+    registry: &Registry,
+    info: &PadInfo,
+) -> Result<bool> {
+    let state = StateCache::load(info.id());
+
+    pad.use_capabilities(registry.model(info.vendor_id, info.product_id).capabilities);
+    pad.use_state_cache(state.clone());
+    pad.restore(&state)
+}
+
+fn run<T: Transport>(
+    mut pad: QckPad<T>,
+    registry: &Registry,
+    info: &PadInfo,
     matches: &ArgMatches,
-    red_arg_str: &str,
-    green_arg_str: &str,
-    blue_arg_str: &str,
-) -> ColorRGB {
-    let red = matches.value_of(red_arg_str).unwrap();
-    let green = matches.value_of(green_arg_str).unwrap();
-    let blue = matches.value_of(blue_arg_str).unwrap();
-
-    if let (Ok(red), Ok(green), Ok(blue)) =
-        (red.parse::<u8>(), green.parse::<u8>(), blue.parse::<u8>())
-    {
-        let range = 0..=255;
+) -> Result<()> {
+    pad.use_capabilities(registry.model(info.vendor_id, info.product_id).capabilities);
+    pad.use_state_cache(StateCache::load(info.id()));
 
-        if !range.contains(&red) || !range.contains(&green) || !range.contains(&blue) {
-            println!("The provided values for RED, GREEN, BLUE must be positive numbers are not in the range of 0-255");
-        }
+    let result = cli::run(&mut pad, matches);
 
-        return ColorRGB::new(red, green, blue);
-    }
+    // hands the pad back to the kernel before exiting
+    drop(pad);
 
-    BlackColor
+    match result {
+        Err(Error::Cli(e)) => e.exit(),
+        result => result,
+    }
 }
 
-fn open_device<T: UsbContext>(
-    context: &mut T,
-    vid: u16,
-    pid: u16,
-) -> Option<(Device<T>, DeviceDescriptor, DeviceHandle<T>)> {
-    context.set_log_level(rusb::LogLevel::Debug);
+/// Runs the command on the pads opened by rqckd.
+fn run_remote(mut client: Client, matches: &ArgMatches) -> Result<()> {
+    let device = matches.value_of("DEVICE");
 
-    let devices = match context.devices() {
-        Ok(d) => d,
-        Err(_) => return None,
+    let pads = match client.get_state(device, matches.is_present("ALL")) {
+        Ok(pads) => pads,
+        Err(Error::Daemon(e)) if e.code == rpc::NO_PAD => {
+            match device {
+                Some(device) => println!("could not find device {}", device),
+                None => println!("rqckd has no pads open"),
+            }
+            return Ok(());
+        }
+        Err(e) => return Err(e),
     };
 
-    for device in devices.iter() {
-        let device_desc = match device.device_descriptor() {
-            Ok(d) => d,
-            Err(_) => continue,
-        };
+    for pad in &pads {
+        if pads.len() > 1 {
+            println!("{}:", pad.device);
+        }
 
-        if device_desc.vendor_id() == vid && device_desc.product_id() == pid {
-            match device.open() {
-                Ok(handle) => return Some((device, device_desc, handle)),
-                Err(_) => continue,
-            }
+        match cli::run(&mut RemotePad::new(&mut client, &pad.device), matches) {
+            Err(Error::Cli(e)) => e.exit(),
+            result => result?,
         }
     }
 
-    None
+    Ok(())
 }
 
-// returns all readable endpoints for given usb device and descriptor
-fn find_readable_endpoints<T: UsbContext>(device: &mut Device<T>) -> Result<Vec<Endpoint>> {
-    let device_desc = device.device_descriptor()?;
-    let mut endpoints = vec![];
-    for n in 0..device_desc.num_configurations() {
-        let config_desc = match device.config_descriptor(n) {
-            Ok(c) => c,
-            Err(_) => continue,
-        };
-        // println!("{:#?}", config_desc);
-        for interface in config_desc.interfaces() {
-            for interface_desc in interface.descriptors() {
-                // println!("{:#?}", interface_desc);
-                for endpoint_desc in interface_desc.endpoint_descriptors() {
-                    // println!("{:#?}", endpoint_desc);
-                    endpoints.push(Endpoint {
-                        config: config_desc.number(),
-                        iface: interface_desc.interface_number(),
-                        setting: interface_desc.setting_number(),
-                        address: endpoint_desc.address(),
-                    });
-                }
-            }
-        }
-    }
+fn not_found(models: &[Model]) {
+    let ids: Vec<String> = models
+        .iter()
+        .map(|model| format!("{:04x}:{:04x}", model.vendor_id, model.product_id))
+        .collect();
 
-    Ok(endpoints)
+    println!("could not find device {}", ids.join(", "));
 }
 
-fn configure_endpoint<T: UsbContext>(
-    handle: &mut DeviceHandle<T>,
-    endpoint: &Endpoint,
-) -> Result<()> {
-    //handle.set_active_configuration(endpoint.config)?;
-    handle.claim_interface(endpoint.iface)?;
-    handle.set_alternate_setting(endpoint.iface, endpoint.setting)
+fn name(info: &PadInfo) -> String {
+    match (info.id(), &info.hidraw) {
+        (Some(id), _) => id.to_string(),
+        (None, Some(path)) => path.display().to_string(),
+        (None, None) => format!(
+            "{:03}:{:03}",
+            info.bus.unwrap_or(0),
+            info.address.unwrap_or(0)
+        ),
+    }
 }
@@ -0,0 +1,388 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::commands::{ColorRGB, ParseColorError, BLACK_COLOR};
+use serde::{Deserialize, Serialize};
+
+use crate::report::{ReportError, ZoneColorReport, ZoneEntry};
+use crate::units::{parse_duration, ParseUnitError, Speed};
+
+/// A color of a zone and how long it is shown before the pad fades to the
+/// next keyframe.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyFrame {
+    pub color: ColorRGB,
+    pub duration: Duration,
+}
+
+impl KeyFrame {
+    pub fn new(color: ColorRGB, duration: Duration) -> Self {
+        KeyFrame { color, duration }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseKeyFrameError {
+    Color(ParseColorError),
+    Duration(ParseUnitError),
+    MissingDuration(String),
+}
+
+impl fmt::Display for ParseKeyFrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseKeyFrameError::Color(e) => e.fmt(f),
+            ParseKeyFrameError::Duration(e) => e.fmt(f),
+            ParseKeyFrameError::MissingDuration(s) => {
+                write!(f, "'{}' has no duration, expected COLOR:DURATION", s)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseKeyFrameError {}
+
+/// Parses keyframes written as `COLOR:DURATION`, e.g. `ff0000:500ms`
+impl FromStr for KeyFrame {
+    type Err = ParseKeyFrameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (color, duration) = match s.find(':') {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => return Err(ParseKeyFrameError::MissingDuration(s.to_string())),
+        };
+
+        let color = color.parse().map_err(ParseKeyFrameError::Color)?;
+        let duration = parse_duration(duration).map_err(ParseKeyFrameError::Duration)?;
+
+        Ok(KeyFrame::new(color, duration))
+    }
+}
+
+/// The "ColorShift" mode: every zone cycles through its keyframes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorShift {
+    speed: Speed,
+    lower: Vec<KeyFrame>,
+    upper: Vec<KeyFrame>,
+}
+
+impl ColorShift {
+    /// Fails if a zone has no keyframes or if the keyframes do not fit into a
+    /// single report.
+    pub fn new(
+        speed: Speed,
+        lower: Vec<KeyFrame>,
+        upper: Vec<KeyFrame>,
+    ) -> Result<Self, ReportError> {
+        if lower.is_empty() {
+            return Err(ReportError::EmptyZone(0));
+        }
+
+        if upper.is_empty() {
+            return Err(ReportError::EmptyZone(1));
+        }
+
+        let color_shift = ColorShift {
+            speed,
+            lower,
+            upper,
+        };
+        color_shift.build_report()?;
+
+        Ok(color_shift)
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    pub fn lower(&self) -> &[KeyFrame] {
+        &self.lower
+    }
+
+    pub fn upper(&self) -> &[KeyFrame] {
+        &self.upper
+    }
+
+    /// The keyframes of the lower zone come first, followed by the ones of the
+    /// upper zone, like the entries of the "Steady" mode.
+    pub fn to_report(&self) -> ZoneColorReport {
+        // the keyframes were checked in `new`
+        self.build_report().unwrap()
+    }
+
+    fn build_report(&self) -> Result<ZoneColorReport, ReportError> {
+        let mut builder = ZoneColorReport::builder().speed(self.speed);
+
+        let zones = [(0, &self.lower), (1, &self.upper)];
+        for (zone, key_frames) in zones.iter() {
+            for key_frame in key_frames.iter() {
+                builder = builder
+                    .duration(key_frame.duration)
+                    .entry(*zone, key_frame.color);
+            }
+        }
+
+        builder.build()
+    }
+}
+
+/// Breathing emulated with the "ColorShift" mode: every zone fades in one of
+/// its colors, fades it out again and continues with the next color.
+///
+/// This is not the "Multi Color Breathe" mode of the firmware, whose entries
+/// have not been captured yet. A breath is a ColorShift keyframe with the
+/// color followed by a black keyframe, each lasting half of the period.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmulatedBreathe {
+    period: Duration,
+    lower: Vec<ColorRGB>,
+    upper: Vec<ColorRGB>,
+}
+
+impl EmulatedBreathe {
+    /// `period` is the length of a single breath.
+    pub fn new(
+        period: Duration,
+        lower: Vec<ColorRGB>,
+        upper: Vec<ColorRGB>,
+    ) -> Result<Self, ReportError> {
+        let breathe = EmulatedBreathe {
+            period,
+            lower,
+            upper,
+        };
+
+        // validates the number of keyframes
+        breathe.to_color_shift()?;
+
+        Ok(breathe)
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    pub fn lower(&self) -> &[ColorRGB] {
+        &self.lower
+    }
+
+    pub fn upper(&self) -> &[ColorRGB] {
+        &self.upper
+    }
+
+    pub fn to_color_shift(&self) -> Result<ColorShift, ReportError> {
+        let half_period = self.period / 2;
+        let key_frames = |colors: &[ColorRGB]| {
+            colors
+                .iter()
+                .flat_map(|color| {
+                    vec![
+                        KeyFrame::new(*color, half_period),
+                        KeyFrame::new(BLACK_COLOR, half_period),
+                    ]
+                })
+                .collect::<Vec<_>>()
+        };
+
+        ColorShift::new(
+            Speed::default(),
+            key_frames(&self.lower),
+            key_frames(&self.upper),
+        )
+    }
+
+    pub fn to_report(&self) -> ZoneColorReport {
+        // the keyframes were checked in `new`
+        self.to_color_shift().unwrap().to_report()
+    }
+}
+
+/// The mode a zone color report belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Steady,
+    ColorShift,
+    /// ColorShift keyframes written by [`EmulatedBreathe`]
+    Breathe,
+}
+
+impl Mode {
+    /// Zones with a single entry are steady. Zones that alternate between a
+    /// color and black with the same duration breathe, everything else is a
+    /// color shift.
+    pub fn of(report: &ZoneColorReport) -> Mode {
+        let mut zones: Vec<Vec<&ZoneEntry>> = vec![];
+        for entry in report.entries() {
+            match zones.iter_mut().find(|zone| zone[0].zone == entry.zone) {
+                Some(zone) => zone.push(entry),
+                None => zones.push(vec![entry]),
+            }
+        }
+
+        if zones.iter().all(|entries| entries.len() == 1) {
+            return Mode::Steady;
+        }
+
+        let breathes = |entries: &Vec<&ZoneEntry>| {
+            entries.len().is_multiple_of(2)
+                && entries.iter().all(|e| e.duration == entries[0].duration)
+                && entries
+                    .chunks(2)
+                    .all(|breath| breath[0].color != BLACK_COLOR && breath[1].color == BLACK_COLOR)
+        };
+
+        if zones.iter().all(breathes) {
+            Mode::Breathe
+        } else {
+            Mode::ColorShift
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mode::Steady => write!(f, "steady"),
+            Mode::ColorShift => write!(f, "colorshift"),
+            Mode::Breathe => write!(f, "breathe"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: ColorRGB = ColorRGB {
+        red: 0xff,
+        green: 0x00,
+        blue: 0x00,
+    };
+    const GREEN: ColorRGB = ColorRGB {
+        red: 0x00,
+        green: 0xff,
+        blue: 0x00,
+    };
+    const BLUE: ColorRGB = ColorRGB {
+        red: 0x00,
+        green: 0x00,
+        blue: 0xff,
+    };
+
+    /// `colorshift --speed 100 --lower ff0000:500ms --upper 00ff00:1s,0000ff:1.5s`
+    const COLOR_SHIFT: [u8; 40] = [
+        0x0e, 0x00, 0x03, 0x00, 0xff, 0x00, 0x00, 0xff, 0x64, 0xf4, 0x01, 0x00, 0x00, 0x01, 0x00,
+        0x00, 0x00, 0xff, 0x00, 0xff, 0x64, 0xe8, 0x03, 0x00, 0x01, 0x01, 0x00, 0x01, 0x00, 0x00,
+        0xff, 0xff, 0x64, 0xdc, 0x05, 0x00, 0x01, 0x01, 0x00, 0x02,
+    ];
+
+    fn key_frame(color: ColorRGB, millis: u64) -> KeyFrame {
+        KeyFrame::new(color, Duration::from_millis(millis))
+    }
+
+    #[test]
+    fn parses_key_frames() {
+        assert_eq!("ff0000:500ms".parse(), Ok(key_frame(RED, 500)));
+        assert_eq!("blue:1.5s".parse(), Ok(key_frame(BLUE, 1500)));
+
+        assert_eq!(
+            "ff0000".parse::<KeyFrame>(),
+            Err(ParseKeyFrameError::MissingDuration("ff0000".to_string()))
+        );
+        assert!(matches!(
+            "notacolor:1s".parse::<KeyFrame>(),
+            Err(ParseKeyFrameError::Color(_))
+        ));
+        assert_eq!(
+            "red:fast".parse::<KeyFrame>(),
+            Err(ParseKeyFrameError::Duration(ParseUnitError::Duration(
+                "fast".to_string()
+            )))
+        );
+        assert_eq!(
+            "red:70s".parse::<KeyFrame>(),
+            Err(ParseKeyFrameError::Duration(
+                ParseUnitError::DurationTooLong("70s".to_string())
+            ))
+        );
+    }
+
+    #[test]
+    fn color_shift_encodes_the_lower_zone_first() {
+        let color_shift = ColorShift::new(
+            Speed::new(100),
+            vec![key_frame(RED, 500)],
+            vec![key_frame(GREEN, 1000), key_frame(BLUE, 1500)],
+        )
+        .unwrap();
+
+        let bytes = color_shift.to_report().to_bytes();
+        assert_eq!(bytes[..COLOR_SHIFT.len()], COLOR_SHIFT[..]);
+        assert!(bytes[COLOR_SHIFT.len()..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn color_shift_needs_key_frames_that_fit_into_a_report() {
+        let key_frames = |count| vec![key_frame(RED, 200); count];
+
+        assert_eq!(
+            ColorShift::new(Speed::default(), vec![], key_frames(1)),
+            Err(ReportError::EmptyZone(0))
+        );
+        assert_eq!(
+            ColorShift::new(Speed::default(), key_frames(1), vec![]),
+            Err(ReportError::EmptyZone(1))
+        );
+
+        assert!(ColorShift::new(Speed::default(), key_frames(21), key_frames(22)).is_ok());
+        assert_eq!(
+            ColorShift::new(Speed::default(), key_frames(22), key_frames(22)),
+            Err(ReportError::TooManyEntries(44))
+        );
+    }
+
+    #[test]
+    fn breathing_is_emulated_with_color_shift_key_frames() {
+        let breathe =
+            EmulatedBreathe::new(Duration::from_secs(2), vec![GREEN], vec![RED, BLUE]).unwrap();
+
+        assert_eq!(
+            breathe.to_color_shift().unwrap(),
+            ColorShift::new(
+                Speed::default(),
+                vec![key_frame(GREEN, 1000), key_frame(BLACK_COLOR, 1000)],
+                vec![
+                    key_frame(RED, 1000),
+                    key_frame(BLACK_COLOR, 1000),
+                    key_frame(BLUE, 1000),
+                    key_frame(BLACK_COLOR, 1000),
+                ],
+            )
+            .unwrap()
+        );
+        assert_eq!(Mode::of(&breathe.to_report()), Mode::Breathe);
+
+        assert_eq!(
+            EmulatedBreathe::new(Duration::from_secs(2), vec![RED; 11], vec![RED; 11]),
+            Err(ReportError::TooManyEntries(44))
+        );
+    }
+
+    #[test]
+    fn mode_is_decoded_from_the_entries() {
+        let data = {
+            let mut data = [0u8; crate::report::ZONE_COLOR_REPORT_LEN];
+            data[..COLOR_SHIFT.len()].copy_from_slice(&COLOR_SHIFT);
+            data
+        };
+        let report = ZoneColorReport::from_bytes(&data).unwrap();
+        assert_eq!(Mode::of(&report), Mode::ColorShift);
+
+        assert_eq!(Mode::of(&ZoneColorReport::steady(RED, BLUE)), Mode::Steady);
+        assert_eq!(Mode::of(&ZoneColorReport::new()), Mode::Steady);
+    }
+}
@@ -0,0 +1,664 @@
+//! The pads of `rqckd` as controllers of the OpenRGB SDK.
+//!
+//! `rqckd --openrgb` serves the network protocol of the OpenRGB SDK, so
+//! OpenRGB itself and the tools built on its SDK can set the colors of the
+//! pads. Every pad is a mousemat controller with a single `Direct` mode and
+//! one zone with a single LED per zone of the pad, e.g. `lower` and `upper`.
+//!
+//! Every packet starts with a header of the magic `ORGB`, the index of the
+//! controller, the packet id and the size of the data that follows, all
+//! numbers are little endian. Colors are sent as `0x00BBGGRR`.
+//!
+//! `UpdateLEDs`, `UpdateZoneLEDs` and `UpdateSingleLED` become a
+//! `show_frame` request of [`crate::rpc`], clients like the effects of
+//! OpenRGB send them many times a second. The colors are only stored as the
+//! configuration of the pad, with an `apply_profile` request, on `SaveMode`
+//! or when the client disconnects. Modes and profiles of OpenRGB are not
+//! supported.
+
+use std::cmp;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use serde_json::json;
+
+use crate::daemon::{Call, Daemon};
+use crate::profile::Profile;
+use crate::report::{ZoneColorReport, ZoneEntry};
+use crate::rpc::PadState;
+use crate::status::{zone_name, DeviceState};
+use crate::transport::Transport;
+use crate::{ColorRGB, BLACK_COLOR};
+
+/// Port of the OpenRGB SDK server.
+pub const DEFAULT_PORT: u16 = 6742;
+
+/// Newest protocol version that is served. Version 1 added the vendor, 2
+/// the profiles and 3 the brightness of the modes.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+const MAGIC: &[u8; 4] = b"ORGB";
+const HEADER_SIZE: usize = 16;
+/// Larger packets are not sent by OpenRGB, the client is dropped
+const MAX_PACKET_SIZE: u32 = 1 << 20;
+
+const REQUEST_CONTROLLER_COUNT: u32 = 0;
+const REQUEST_CONTROLLER_DATA: u32 = 1;
+const REQUEST_PROTOCOL_VERSION: u32 = 40;
+const SET_CLIENT_NAME: u32 = 50;
+const REQUEST_PROFILE_LIST: u32 = 150;
+const UPDATE_LEDS: u32 = 1050;
+const UPDATE_ZONE_LEDS: u32 = 1051;
+const UPDATE_SINGLE_LED: u32 = 1052;
+const SAVE_MODE: u32 = 1102;
+
+const DEVICE_TYPE_MOUSEMAT: i32 = 7;
+const ZONE_TYPE_SINGLE: i32 = 0;
+const MODE_FLAG_HAS_PER_LED_COLOR: u32 = 1 << 5;
+const MODE_COLORS_PER_LED: u32 = 1;
+
+/// Header of every packet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Header {
+    /// Index of the controller the packet is about
+    device: u32,
+    id: u32,
+    size: u32,
+}
+
+impl Header {
+    fn parse(data: &[u8; HEADER_SIZE]) -> Option<Self> {
+        if &data[..4] != MAGIC {
+            return None;
+        }
+
+        let field = |offset: usize| {
+            u32::from_le_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ])
+        };
+
+        Some(Header {
+            device: field(4),
+            id: field(8),
+            size: field(12),
+        })
+    }
+
+    fn to_bytes(self) -> [u8; HEADER_SIZE] {
+        let mut data = [0; HEADER_SIZE];
+        data[..4].copy_from_slice(MAGIC);
+        data[4..8].copy_from_slice(&self.device.to_le_bytes());
+        data[8..12].copy_from_slice(&self.id.to_le_bytes());
+        data[12..].copy_from_slice(&self.size.to_le_bytes());
+        data
+    }
+}
+
+/// Writes the fields of a packet.
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u16(&mut self, value: u16) -> &mut Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u32(&mut self, value: u32) -> &mut Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn i32(&mut self, value: i32) -> &mut Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    /// Length including the terminating NUL, then the string and the NUL.
+    fn string(&mut self, value: &str) -> &mut Self {
+        self.u16(value.len() as u16 + 1);
+        self.0.extend_from_slice(value.as_bytes());
+        self.0.push(0);
+        self
+    }
+
+    fn color(&mut self, color: ColorRGB) -> &mut Self {
+        self.0
+            .extend_from_slice(&[color.red, color.green, color.blue, 0]);
+        self
+    }
+
+    fn colors(&mut self, colors: &[ColorRGB]) -> &mut Self {
+        self.u16(colors.len() as u16);
+        for color in colors {
+            self.color(*color);
+        }
+        self
+    }
+
+    /// Prepends the size of the data, which includes the size itself.
+    fn with_size(self) -> Vec<u8> {
+        let size = (self.0.len() + 4) as u32;
+        let mut data = size.to_le_bytes().to_vec();
+        data.extend(self.0);
+        data
+    }
+}
+
+/// Reads the fields of a packet, `None` if the packet is too short.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Option<&[u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+
+        let (field, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(field)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)
+            .map(|data| u16::from_le_bytes([data[0], data[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|data| u32::from_le_bytes([data[0], data[1], data[2], data[3]]))
+    }
+
+    fn color(&mut self) -> Option<ColorRGB> {
+        self.take(4)
+            .map(|data| ColorRGB::new(data[0], data[1], data[2]))
+    }
+
+    fn colors(&mut self) -> Option<Vec<ColorRGB>> {
+        let count = self.u16()?;
+        (0..count).map(|_| self.color()).collect()
+    }
+}
+
+/// The description of a pad as `RGBController`, with the current colors
+/// of its zones.
+fn controller_data(device: &str, colors: &[ColorRGB], protocol: u32) -> Vec<u8> {
+    let mut data = Writer::default();
+
+    data.i32(DEVICE_TYPE_MOUSEMAT).string("SteelSeries QCK");
+    if protocol >= 1 {
+        data.string("SteelSeries");
+    }
+    data.string("SteelSeries QCK mousepad controlled by rqckd")
+        .string("")
+        .string(device)
+        .string(&format!("rqckd: {}", device));
+
+    data.u16(1).i32(0);
+    data.string("Direct")
+        .i32(0)
+        .u32(MODE_FLAG_HAS_PER_LED_COLOR)
+        .u32(0)
+        .u32(0);
+    if protocol >= 3 {
+        data.u32(0).u32(0);
+    }
+    data.u32(0).u32(0).u32(0);
+    if protocol >= 3 {
+        data.u32(0);
+    }
+    data.u32(0).u32(MODE_COLORS_PER_LED).u16(0);
+
+    data.u16(colors.len() as u16);
+    for zone in 0..colors.len() {
+        let name = zone_name(zone as u8);
+        data.string(&name)
+            .i32(ZONE_TYPE_SINGLE)
+            .u32(1)
+            .u32(1)
+            .u32(1)
+            // no matrix
+            .u16(0);
+    }
+
+    data.u16(colors.len() as u16);
+    for zone in 0..colors.len() {
+        data.string(&zone_name(zone as u8)).u32(zone as u32);
+    }
+
+    data.colors(colors);
+
+    data.with_size()
+}
+
+/// The first color of every zone, black if it is not known.
+fn zone_colors(state: Option<&DeviceState>, zones: u8) -> Vec<ColorRGB> {
+    (0..zones)
+        .map(|zone| {
+            state
+                .and_then(|state| state.zones.iter().find(|z| z.zone == zone_name(zone)))
+                .and_then(|zone| zone.key_frames.first())
+                .map_or(BLACK_COLOR, |key_frame| key_frame.color)
+        })
+        .collect()
+}
+
+fn state(call: &Call, device: &str) -> Option<DeviceState> {
+    let result = call("get_state", json!({ "device": device })).ok()?;
+    let pads: Vec<PadState> = serde_json::from_value(result).ok()?;
+
+    pads.into_iter().next()?.state
+}
+
+/// The colors a client shows on a pad and has not saved yet.
+#[derive(Clone, Debug, PartialEq)]
+struct Frame {
+    /// The color of every zone
+    colors: Vec<ColorRGB>,
+    /// Whether the client set the zone, the others keep their configuration
+    changed: Vec<bool>,
+}
+
+/// One connected client of the SDK.
+struct Client<'a> {
+    call: &'a Call,
+    /// The `device` and the number of zones of every pad
+    pads: &'a [(String, u8)],
+    protocol: u32,
+    /// The unsaved frame of every pad
+    frames: Vec<Option<Frame>>,
+}
+
+impl Client<'_> {
+    /// Shows `colors` on the zones from `first` on, colors beyond the last
+    /// zone are ignored.
+    fn show_colors(&mut self, pad: u32, first: u32, colors: &[ColorRGB]) -> Option<()> {
+        let (device, zones) = self.pads.get(pad as usize)?;
+        if first >= u32::from(*zones) || colors.is_empty() {
+            return None;
+        }
+        let call = self.call;
+        let frame = self.frames[pad as usize].get_or_insert_with(|| Frame {
+            colors: zone_colors(state(call, device).as_ref(), *zones),
+            changed: vec![false; usize::from(*zones)],
+        });
+
+        for (zone, color) in (first as usize..usize::from(*zones)).zip(colors) {
+            frame.colors[zone] = *color;
+            frame.changed[zone] = true;
+        }
+
+        // the SDK has no way to report errors back to the client
+        let _ = call(
+            "show_frame",
+            json!({ "device": device, "colors": frame.colors }),
+        );
+        Some(())
+    }
+
+    /// Stores the zones the client set on the pad as its configuration.
+    fn save(&mut self, pad: u32) -> Option<()> {
+        let (device, _) = self.pads.get(pad as usize)?;
+        let frame = self.frames[pad as usize].take()?;
+
+        let mut report = ZoneColorReport::new();
+        for (zone, color) in frame.colors.iter().enumerate() {
+            if frame.changed[zone] {
+                report.push(ZoneEntry::new(zone as u8, *color)).ok()?;
+            }
+        }
+
+        let profile = Profile::new(&report, None);
+        let _ = (self.call)(
+            "apply_profile",
+            json!({ "device": device, "profile": profile }),
+        );
+        Some(())
+    }
+
+    /// Handles one packet, returns the data of the reply if there is one.
+    fn handle(&mut self, header: Header, data: &[u8]) -> Option<Vec<u8>> {
+        let mut data = Reader(data);
+
+        match header.id {
+            REQUEST_CONTROLLER_COUNT => Some((self.pads.len() as u32).to_le_bytes().to_vec()),
+            REQUEST_CONTROLLER_DATA => {
+                // clients before version 1 send no version
+                let protocol = data
+                    .u32()
+                    .map_or(0, |version| cmp::min(version, PROTOCOL_VERSION));
+                let (device, zones) = self.pads.get(header.device as usize)?;
+                let colors = zone_colors(state(self.call, device).as_ref(), *zones);
+
+                Some(controller_data(device, &colors, protocol))
+            }
+            REQUEST_PROTOCOL_VERSION => {
+                self.protocol = cmp::min(data.u32().unwrap_or(0), PROTOCOL_VERSION);
+                Some(PROTOCOL_VERSION.to_le_bytes().to_vec())
+            }
+            REQUEST_PROFILE_LIST if self.protocol >= 2 => {
+                let mut list = Writer::default();
+                list.u16(0);
+                Some(list.with_size())
+            }
+            UPDATE_LEDS => {
+                data.u32()?;
+                let colors = data.colors()?;
+                self.show_colors(header.device, 0, &colors);
+                None
+            }
+            UPDATE_ZONE_LEDS => {
+                data.u32()?;
+                let zone = data.u32()?;
+                let colors = data.colors()?;
+                // every zone has a single LED
+                self.show_colors(header.device, zone, &colors[..cmp::min(colors.len(), 1)]);
+                None
+            }
+            UPDATE_SINGLE_LED => {
+                let led = data.u32()?;
+                let color = data.color()?;
+                self.show_colors(header.device, led, &[color]);
+                None
+            }
+            SAVE_MODE => {
+                self.save(header.device);
+                None
+            }
+            SET_CLIENT_NAME => None,
+            // the modes and everything else is ignored
+            _ => None,
+        }
+    }
+}
+
+/// Answers the packets of a client until it disconnects or sends something
+/// that is not a packet of the SDK, then saves the colors it left on the
+/// pads.
+pub fn serve_client<S: Read + Write>(
+    call: &Call,
+    pads: &[(String, u8)],
+    stream: S,
+) -> io::Result<()> {
+    let mut client = Client {
+        call,
+        pads,
+        protocol: 0,
+        frames: vec![None; pads.len()],
+    };
+
+    let result = answer(&mut client, stream);
+    for pad in 0..pads.len() {
+        client.save(pad as u32);
+    }
+
+    result
+}
+
+fn answer<S: Read + Write>(client: &mut Client, mut stream: S) -> io::Result<()> {
+    loop {
+        let mut header = [0; HEADER_SIZE];
+        match stream.read_exact(&mut header) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+
+        let header = Header::parse(&header)
+            .filter(|header| header.size <= MAX_PACKET_SIZE)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not an OpenRGB packet"))?;
+
+        let mut data = vec![0; header.size as usize];
+        stream.read_exact(&mut data)?;
+
+        if let Some(reply) = client.handle(header, &data) {
+            let reply_header = Header {
+                size: reply.len() as u32,
+                ..header
+            };
+            stream.write_all(&reply_header.to_bytes())?;
+            stream.write_all(&reply)?;
+        }
+    }
+}
+
+/// Accepts clients of the SDK until `listener` fails.
+pub fn serve<T>(daemon: Arc<Daemon<T>>, listener: TcpListener) -> io::Result<()>
+where
+    T: Transport + Send + 'static,
+{
+    let pads = Arc::new(daemon.pads());
+    let call: Call = Arc::new(move |method: &str, params| daemon.call(method, params));
+
+    for stream in listener.incoming() {
+        let stream: TcpStream = stream?;
+        let (call, pads) = (call.clone(), pads.clone());
+
+        thread::spawn(move || {
+            let _ = stream.set_nodelay(true);
+            let _ = serve_client(&call, &pads, stream);
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::RpcError;
+    use serde_json::Value;
+    use std::io::Cursor;
+    use std::sync::Mutex;
+
+    fn packet(device: u32, id: u32, data: &[u8]) -> Vec<u8> {
+        let header = Header {
+            device,
+            id,
+            size: data.len() as u32,
+        };
+        let mut packet = header.to_bytes().to_vec();
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    /// A stream that reads `input` and collects what is written.
+    struct Stream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Stream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Stream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Serves `input` to a pad showing red and blue, returns the replies and
+    /// the requests sent to the daemon.
+    fn serve(input: Vec<u8>) -> (Vec<u8>, Vec<(String, Value)>) {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let recorded = calls.clone();
+        let call: Call = Arc::new(move |method: &str, params: Value| {
+            recorded.lock().unwrap().push((method.to_string(), params));
+
+            let report =
+                ZoneColorReport::steady(ColorRGB::new(0, 0, 0xff), ColorRGB::new(0xff, 0, 0));
+            let state = PadState {
+                device: "AAA".to_string(),
+                state: Some(DeviceState::new(&report, None)),
+            };
+            Ok::<_, RpcError>(serde_json::to_value(vec![state]).unwrap())
+        });
+
+        let mut stream = Stream {
+            input: Cursor::new(input),
+            output: vec![],
+        };
+        serve_client(&call, &[("AAA".to_string(), 2)], &mut stream).unwrap();
+
+        let calls = calls.lock().unwrap().clone();
+        (stream.output, calls)
+    }
+
+    #[test]
+    fn reports_the_pad_as_a_controller() {
+        let mut input = packet(0, REQUEST_PROTOCOL_VERSION, &4u32.to_le_bytes());
+        input.extend(packet(0, REQUEST_CONTROLLER_COUNT, &[]));
+        input.extend(packet(0, REQUEST_CONTROLLER_DATA, &3u32.to_le_bytes()));
+        // there is no second pad
+        input.extend(packet(1, REQUEST_CONTROLLER_DATA, &3u32.to_le_bytes()));
+
+        let (output, _) = serve(input);
+
+        let mut expected = packet(0, REQUEST_PROTOCOL_VERSION, &3u32.to_le_bytes());
+        expected.extend(packet(0, REQUEST_CONTROLLER_COUNT, &1u32.to_le_bytes()));
+        let controller = controller_data(
+            "AAA",
+            &[ColorRGB::new(0, 0, 0xff), ColorRGB::new(0xff, 0, 0)],
+            3,
+        );
+        expected.extend(packet(0, REQUEST_CONTROLLER_DATA, &controller));
+        assert_eq!(output, expected);
+
+        let mut data = Reader(&controller);
+        assert_eq!(data.u32(), Some(controller.len() as u32));
+        assert_eq!(data.u32(), Some(DEVICE_TYPE_MOUSEMAT as u32));
+        assert_eq!(data.u16(), Some(16));
+        assert_eq!(data.take(16), Some(&b"SteelSeries QCK\0"[..]));
+        // the two LEDs, then their colors
+        assert!(controller.ends_with(&[2, 0, /* blue */ 0, 0, 0xff, 0, /* red */ 0xff, 0, 0, 0]));
+    }
+
+    /// The zone colors of a `show_frame` or `apply_profile` request.
+    fn colors(method: &str, params: &Value) -> Vec<(String, ColorRGB)> {
+        assert_eq!(params["device"], "AAA");
+        match method {
+            "show_frame" => {
+                let colors: Vec<ColorRGB> =
+                    serde_json::from_value(params["colors"].clone()).unwrap();
+                colors
+                    .into_iter()
+                    .enumerate()
+                    .map(|(zone, color)| (zone_name(zone as u8), color))
+                    .collect()
+            }
+            "apply_profile" => {
+                let profile: Profile = serde_json::from_value(params["profile"].clone()).unwrap();
+                profile
+                    .zones
+                    .iter()
+                    .map(|zone| (zone.zone.clone(), zone.key_frames[0].color))
+                    .collect()
+            }
+            _ => panic!("unexpected request {}", method),
+        }
+    }
+
+    #[test]
+    fn updates_show_frames_that_are_saved_on_disconnect() {
+        let leds = [4, 0, 0, 0, 2, 0, 0xff, 0, 0, 0, 0, 0, 0xff, 0];
+        let mut input = packet(0, UPDATE_LEDS, &leds);
+        let zone = [4, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0x12, 0x34, 0x56, 0];
+        input.extend(packet(0, UPDATE_ZONE_LEDS, &zone));
+        input.extend(packet(0, UPDATE_SINGLE_LED, &[0, 0, 0, 0, 1, 2, 3, 0]));
+        // out of range and unknown packets are ignored
+        input.extend(packet(1, UPDATE_SINGLE_LED, &[0, 0, 0, 0, 1, 2, 3, 0]));
+        input.extend(packet(0, UPDATE_SINGLE_LED, &[5, 0, 0, 0, 1, 2, 3, 0]));
+        input.extend(packet(0, 1100, &[]));
+
+        let (output, calls) = serve(input);
+        assert!(output.is_empty());
+
+        // the first frame starts from the colors the pad shows
+        assert_eq!(calls[0].0, "get_state");
+        let requests: Vec<(&str, Vec<(String, ColorRGB)>)> = calls[1..]
+            .iter()
+            .map(|(method, params)| (method.as_str(), colors(method, params)))
+            .collect();
+
+        let red = ColorRGB::new(0xff, 0, 0);
+        let blue = ColorRGB::new(0, 0, 0xff);
+        let set = ColorRGB::new(0x12, 0x34, 0x56);
+        let led = ColorRGB::new(1, 2, 3);
+        assert_eq!(
+            requests,
+            vec![
+                (
+                    "show_frame",
+                    vec![("lower".to_string(), red), ("upper".to_string(), blue)]
+                ),
+                (
+                    "show_frame",
+                    vec![("lower".to_string(), red), ("upper".to_string(), set)]
+                ),
+                (
+                    "show_frame",
+                    vec![("lower".to_string(), led), ("upper".to_string(), set)]
+                ),
+                (
+                    "apply_profile",
+                    vec![("lower".to_string(), led), ("upper".to_string(), set)]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn save_mode_stores_the_zones_the_client_set() {
+        let mut input = packet(0, UPDATE_SINGLE_LED, &[1, 0, 0, 0, 1, 2, 3, 0]);
+        input.extend(packet(0, SAVE_MODE, &[]));
+
+        let (_, calls) = serve(input);
+
+        let requests: Vec<(&str, Vec<(String, ColorRGB)>)> = calls[1..]
+            .iter()
+            .map(|(method, params)| (method.as_str(), colors(method, params)))
+            .collect();
+        // the lower zone keeps its configuration and nothing is left to save
+        // on disconnect
+        assert_eq!(
+            requests,
+            vec![
+                (
+                    "show_frame",
+                    vec![
+                        ("lower".to_string(), ColorRGB::new(0, 0, 0xff)),
+                        ("upper".to_string(), ColorRGB::new(1, 2, 3))
+                    ]
+                ),
+                (
+                    "apply_profile",
+                    vec![("upper".to_string(), ColorRGB::new(1, 2, 3))]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_clients_that_do_not_speak_the_protocol() {
+        let calls: Call = Arc::new(|_: &str, _| Ok::<_, RpcError>(Value::Null));
+        let mut stream = Stream {
+            input: Cursor::new(b"GET / HTTP/1.1\r\n\r\n".to_vec()),
+            output: vec![],
+        };
+
+        let e = serve_client(&calls, &[], &mut stream).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,458 @@
+use std::io;
+use std::path::Path;
+
+use rusb::{Device, DeviceHandle, UsbContext};
+
+use crate::commands::{
+    apply_changes, clear, read_zone_colors, set_intensity, set_zone_colors, ColorRGB, Zone,
+    BLACK_COLOR,
+};
+use crate::hidraw::{find_devices, HidrawTransport};
+use crate::modes::{ColorShift, EmulatedBreathe};
+use crate::profile::Profile;
+use crate::registry::Capabilities;
+use crate::report::{ZoneColorReport, ZoneEntry};
+use crate::state::StateCache;
+use crate::status::DeviceState;
+use crate::transport::Transport;
+use crate::usb::{open_at, open_device, UsbTransport};
+use crate::{Result, PRODUCT_ID, VENDOR_ID};
+
+/// The operations of the command line interface.
+///
+/// Implemented by [`QckPad`], which talks to the pad itself, and by
+/// [`crate::rpc::RemotePad`], which forwards them to `rqckd`.
+pub trait Pad {
+    /// Sets the raw intensity of the LEDs (0-255).
+    fn set_intensity(&mut self, intensity: u8) -> Result<()>;
+
+    /// Writes the report and applies it.
+    fn set_zone_colors(&mut self, report: &ZoneColorReport) -> Result<()>;
+
+    /// Replaces the entries of the zones that appear in `update` and keeps
+    /// the current configuration of all other zones.
+    fn update_zones(&mut self, update: &ZoneColorReport) -> Result<()>;
+
+    /// Reads the current mode and colors back from the pad, see
+    /// [`QckPad::status`] for the intensity.
+    fn status(&mut self) -> Result<DeviceState>;
+
+    /// Like [`Pad::status`], but what cannot be read back is taken from what
+    /// was set before, see [`QckPad::current_state`]. `None` if the zone
+    /// colors are unknown.
+    fn current_state(&mut self) -> Option<DeviceState> {
+        self.status().ok()
+    }
+
+    /// What the pad supports. Pads opened by `rqckd` are taken for QCK Prism
+    /// Cloths, the daemon rejects the reports its pads do not support.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::qck_prism_cloth()
+    }
+
+    /// Writes and applies a frame of a [`crate::effects::Effect`]. Unlike
+    /// [`Pad::set_zone_colors`] a frame does not have to be remembered as the
+    /// configuration of the pad.
+    fn show_frame(&mut self, report: &ZoneColorReport) -> Result<()> {
+        self.set_zone_colors(report)
+    }
+
+    /// Shows the configuration again after frames, e.g. when an effect is
+    /// stopped. Pads that do not tell frames apart keep showing the last one.
+    fn restore_configuration(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Sets the zones and the intensity of the profile.
+    fn apply_profile(&mut self, profile: &Profile) -> Result<()> {
+        if let Some(report) = profile.zone_colors()? {
+            self.update_zones(&report)?;
+        }
+
+        if let Some(intensity) = profile.intensity {
+            self.set_intensity(intensity)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A QCK pad behind a [`Transport`].
+///
+/// [`QckPad::open`] opens the pad with rusb, [`QckPad::open_hidraw`] through
+/// the hidraw driver. [`QckPad::new`] accepts any other transport, e.g. a
+/// [`crate::transport::MockTransport`].
+///
+/// The pad is assumed to be a QCK Prism Cloth, other models are set with
+/// [`QckPad::use_capabilities`].
+pub struct QckPad<T: Transport> {
+    transport: T,
+    capabilities: Capabilities,
+    state: Option<StateCache>,
+    /// The zone colors last set, the pad shows them unless `showing_frame`
+    configuration: Option<ZoneColorReport>,
+    showing_frame: bool,
+    /// Zone colors are only recorded while frames are shown over them
+    covered: bool,
+}
+
+impl<C: UsbContext> QckPad<UsbTransport<C>> {
+    /// Opens the first pad found on the bus.
+    pub fn open(context: &mut C) -> rusb::Result<Self> {
+        match open_device(context, VENDOR_ID, PRODUCT_ID)? {
+            Some((device, _, handle)) => QckPad::from_device(device, handle),
+            None => Err(rusb::Error::NoDevice),
+        }
+    }
+
+    /// Opens the pad at `bus` and `address`, as listed by
+    /// [`crate::usb::list_pads`].
+    pub fn open_at(context: &C, bus: u8, address: u8) -> rusb::Result<Self> {
+        let (device, handle) = open_at(context, bus, address)?;
+        QckPad::from_device(device, handle)
+    }
+
+    /// Detaches the kernel driver if necessary and claims the first interface
+    /// with an endpoint.
+    pub fn from_device(device: Device<C>, handle: DeviceHandle<C>) -> rusb::Result<Self> {
+        Ok(QckPad::new(UsbTransport::new(device, handle)?))
+    }
+
+    /// Access to the raw handle.
+    pub fn handle(&mut self) -> &mut DeviceHandle<C> {
+        self.transport.handle()
+    }
+}
+
+impl QckPad<HidrawTransport> {
+    /// Opens the hidraw node of the first pad, found through sysfs.
+    pub fn open_hidraw() -> io::Result<Self> {
+        let device = find_devices(VENDOR_ID, PRODUCT_ID)
+            .into_iter()
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no hidraw device"))?;
+
+        QckPad::open_hidraw_path(device.path)
+    }
+
+    /// Opens a hidraw node, as listed by [`crate::hidraw::list_pads`].
+    pub fn open_hidraw_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(QckPad::new(HidrawTransport::open(path)?))
+    }
+}
+
+impl<T: Transport> QckPad<T> {
+    pub fn new(transport: T) -> Self {
+        QckPad {
+            transport,
+            capabilities: Capabilities::qck_prism_cloth(),
+            state: None,
+            configuration: None,
+            showing_frame: false,
+            covered: false,
+        }
+    }
+
+    /// Builds the reports for a pad with the `capabilities`, e.g. of a
+    /// [`crate::registry::Model`].
+    pub fn use_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Records everything written to the pad in `cache` and saves it after
+    /// every change. Saving is best effort, errors are ignored.
+    pub fn use_state_cache(&mut self, cache: StateCache) {
+        self.state = Some(cache);
+    }
+
+    /// Access to the transport for the functions in [`crate::commands`].
+    pub fn transport(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// Sets the raw intensity of the LEDs (0-255). Fails for pads without an
+    /// intensity report.
+    pub fn set_intensity(&mut self, intensity: u8) -> Result<()> {
+        set_intensity(&mut self.transport, &self.capabilities, intensity)?;
+        self.record(|state| state.set_intensity(intensity));
+        Ok(())
+    }
+
+    /// Switches to the "Steady" mode and applies the colors. The upper zone
+    /// comes first, unlike in [`ZoneColorReport::steady`].
+    pub fn set_steady(&mut self, upper_color: ColorRGB, lower_color: ColorRGB) -> Result<()> {
+        self.set_zone_colors(&ZoneColorReport::steady(lower_color, upper_color))
+    }
+
+    /// Sets all zones to the same color and applies it.
+    pub fn set_solid_color(&mut self, color: ColorRGB) -> Result<()> {
+        self.set_zone_colors(&ZoneColorReport::uniform(self.capabilities.zones, color))
+    }
+
+    /// Writes the report and applies it. While the pad is covered it is only
+    /// recorded as the configuration, see [`QckPad::set_covered`].
+    pub fn set_zone_colors(&mut self, report: &ZoneColorReport) -> Result<()> {
+        if self.covered && self.showing_frame {
+            self.record(|state| state.set_zone_colors(report));
+            self.configuration = Some(report.clone());
+            return Ok(());
+        }
+
+        set_zone_colors(&mut self.transport, &self.capabilities, report)?;
+        self.record(|state| state.set_zone_colors(report));
+        self.configuration = Some(report.clone());
+        self.showing_frame = false;
+        self.apply_changes()
+    }
+
+    /// While `covered` the zone colors that are set while a frame is shown
+    /// become the configuration without being written, e.g. while the layers
+    /// of `rqckd` are shown over it. The next frame shows them.
+    pub fn set_covered(&mut self, covered: bool) {
+        self.covered = covered;
+    }
+
+    /// Switches to the "ColorShift" mode and applies the keyframes.
+    pub fn set_color_shift(&mut self, color_shift: &ColorShift) -> Result<()> {
+        self.set_zone_colors(&color_shift.to_report())
+    }
+
+    /// Switches to the "ColorShift" mode with the keyframes of the emulated
+    /// breathing and applies them.
+    pub fn set_emulated_breathe(&mut self, breathe: &EmulatedBreathe) -> Result<()> {
+        self.set_zone_colors(&breathe.to_report())
+    }
+
+    /// Replaces the entries of the zones that appear in `update` and keeps
+    /// the current configuration of all other zones.
+    ///
+    /// The current configuration is read back from the pad, if that fails it
+    /// is taken from the state cache. Without either the other zones are
+    /// turned off.
+    pub fn update_zones(&mut self, update: &ZoneColorReport) -> Result<()> {
+        let zones = self.capabilities.zones;
+        let mut report = self
+            .current_zone_colors()
+            .unwrap_or_else(|| ZoneColorReport::uniform(zones, BLACK_COLOR));
+
+        for zone in update.zones() {
+            let entries = update
+                .entries()
+                .iter()
+                .filter(|e| e.zone == zone)
+                .cloned()
+                .collect();
+            report.replace_zone(zone, entries)?;
+        }
+
+        self.set_zone_colors(&report)
+    }
+
+    /// Turns off the LEDs of the zone and applies it. The other zone is not
+    /// changed.
+    pub fn disable_zone(&mut self, zone: Zone) -> Result<()> {
+        let mut update = ZoneColorReport::new();
+        update.push(ZoneEntry::new(zone.index(), BLACK_COLOR))?;
+
+        self.update_zones(&update)
+    }
+
+    /// The zone colors the pad is configured with, read back from the pad or
+    /// taken from the state cache. While the pad shows a frame the last
+    /// configuration is used instead of reading the frame back.
+    pub fn current_zone_colors(&mut self) -> Option<ZoneColorReport> {
+        if self.showing_frame {
+            return self
+                .configuration
+                .clone()
+                .or_else(|| self.state.as_ref().and_then(StateCache::zone_colors));
+        }
+
+        match read_zone_colors(&mut self.transport, &self.capabilities) {
+            Ok(report) => Some(report),
+            Err(_) => self.state.as_ref().and_then(StateCache::zone_colors),
+        }
+    }
+
+    /// The zone colors last set with [`QckPad::set_zone_colors`], frames left
+    /// aside. Before anything was set, the ones the pad is configured with.
+    pub fn configuration(&mut self) -> Option<ZoneColorReport> {
+        match &self.configuration {
+            Some(report) => Some(report.clone()),
+            None => self.current_zone_colors(),
+        }
+    }
+
+    /// Reads the current mode and colors back from the pad. The intensity is
+    /// unknown, no capture shows the pad answering a read of its intensity
+    /// report.
+    pub fn status(&mut self) -> Result<DeviceState> {
+        let report = read_zone_colors(&mut self.transport, &self.capabilities)?;
+
+        Ok(DeviceState::new(&report, None))
+    }
+
+    /// Like [`QckPad::status`], but the parts that cannot be read back are
+    /// taken from the state cache, including the intensity last set. `None`
+    /// if the zone colors are unknown.
+    pub fn current_state(&mut self) -> Option<DeviceState> {
+        let report = self.current_zone_colors()?;
+        let intensity = self.state.as_ref().and_then(StateCache::intensity);
+
+        Some(DeviceState::new(&report, intensity))
+    }
+
+    /// Writes the zone colors and the intensity recorded in `state` to the
+    /// pad again, e.g. after it was plugged back in. Returns `false` if
+    /// `state` holds nothing to restore.
+    pub fn restore(&mut self, state: &StateCache) -> Result<bool> {
+        let zone_colors = state.zone_colors();
+        let intensity = state.intensity();
+
+        if let Some(report) = &zone_colors {
+            self.set_zone_colors(report)?;
+        }
+
+        if let Some(intensity) = intensity {
+            self.set_intensity(intensity)?;
+        }
+
+        Ok(zone_colors.is_some() || intensity.is_some())
+    }
+
+    /// Writes the report and applies it without recording it in the state
+    /// cache or as the configuration, which
+    /// [`QckPad::restore_configuration`] shows again after an effect.
+    pub fn show_frame(&mut self, report: &ZoneColorReport) -> Result<()> {
+        if !self.showing_frame && self.configuration.is_none() {
+            // the configuration cannot be read back once the frame replaced it
+            self.configuration = self.current_zone_colors();
+        }
+
+        set_zone_colors(&mut self.transport, &self.capabilities, report)?;
+        self.showing_frame = true;
+        self.apply_changes()
+    }
+
+    /// Writes the configuration from before the frames back to the pad,
+    /// unless it is unknown. Does nothing if no frame is shown.
+    pub fn restore_configuration(&mut self) -> Result<()> {
+        let report = match &self.configuration {
+            Some(report) if self.showing_frame => report.clone(),
+            _ => return Ok(()),
+        };
+
+        set_zone_colors(&mut self.transport, &self.capabilities, &report)?;
+        self.showing_frame = false;
+        self.apply_changes()
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        clear(&mut self.transport, &self.capabilities)?;
+        Ok(())
+    }
+
+    pub fn apply_changes(&mut self) -> Result<()> {
+        apply_changes(&mut self.transport, &self.capabilities)?;
+        Ok(())
+    }
+
+    fn record<F: FnOnce(&mut StateCache)>(&mut self, change: F) {
+        if let Some(state) = self.state.as_mut() {
+            change(state);
+            let _ = state.save();
+        }
+    }
+}
+
+impl<T: Transport> Pad for QckPad<T> {
+    fn set_intensity(&mut self, intensity: u8) -> Result<()> {
+        QckPad::set_intensity(self, intensity)
+    }
+
+    fn set_zone_colors(&mut self, report: &ZoneColorReport) -> Result<()> {
+        QckPad::set_zone_colors(self, report)
+    }
+
+    fn update_zones(&mut self, update: &ZoneColorReport) -> Result<()> {
+        QckPad::update_zones(self, update)
+    }
+
+    fn status(&mut self) -> Result<DeviceState> {
+        QckPad::status(self)
+    }
+
+    fn current_state(&mut self) -> Option<DeviceState> {
+        QckPad::current_state(self)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        QckPad::capabilities(self).clone()
+    }
+
+    fn show_frame(&mut self, report: &ZoneColorReport) -> Result<()> {
+        QckPad::show_frame(self, report)
+    }
+
+    fn restore_configuration(&mut self) -> Result<()> {
+        QckPad::restore_configuration(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{MockTransport, FEATURE_REPORT, OUTPUT_REPORT};
+
+    #[test]
+    fn restore_writes_the_recorded_configuration() {
+        let mut pad = QckPad::new(MockTransport::new());
+        assert!(!pad.restore(&StateCache::default()).unwrap());
+        assert!(pad.transport().sent().is_empty());
+
+        let report = ZoneColorReport::steady(ColorRGB::new(0xff, 0, 0), BLACK_COLOR);
+        let mut state = StateCache::default();
+        state.set_zone_colors(&report);
+        state.set_intensity(0x7f);
+
+        assert!(pad.restore(&state).unwrap());
+
+        let sent: Vec<_> = pad
+            .transport()
+            .take_sent()
+            .into_iter()
+            .map(|report| (report.value, report.data[0]))
+            .collect();
+        assert_eq!(
+            sent,
+            vec![
+                (FEATURE_REPORT, 0x0e),
+                (OUTPUT_REPORT, 0x0d),
+                (OUTPUT_REPORT, 0x0c)
+            ]
+        );
+    }
+
+    #[test]
+    fn restore_configuration_shows_the_configuration_again_after_frames() {
+        let mut pad = QckPad::new(MockTransport::new());
+        let configuration = ZoneColorReport::steady(ColorRGB::new(0xff, 0, 0), BLACK_COLOR);
+        pad.set_zone_colors(&configuration).unwrap();
+        let written = pad.transport().take_sent();
+
+        pad.show_frame(&ZoneColorReport::uniform(2, ColorRGB::new(0, 0, 0xff)))
+            .unwrap();
+        assert_ne!(pad.transport().take_sent(), written);
+
+        pad.restore_configuration().unwrap();
+        assert_eq!(pad.transport().take_sent(), written);
+
+        // the pad already shows it
+        pad.restore_configuration().unwrap();
+        assert!(pad.transport().sent().is_empty());
+    }
+}
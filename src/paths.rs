@@ -0,0 +1,29 @@
+use std::env;
+use std::path::PathBuf;
+
+/// `$XDG_CACHE_HOME/rqck`, falling back to `~/.cache/rqck`
+pub fn cache_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CACHE_HOME", ".cache")
+}
+
+/// `$XDG_CONFIG_HOME/rqck`, falling back to `~/.config/rqck`
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_RUNTIME_DIR/rqck`, `None` if there is no runtime directory
+pub fn runtime_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("rqck")),
+        _ => None,
+    }
+}
+
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    let base = match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(fallback),
+    };
+
+    Some(base.join("rqck"))
+}
@@ -0,0 +1,206 @@
+//! A configuration of the pad that is applied as a whole.
+//!
+//! A profile has the same shape as the output of `status --json`, so the
+//! state of a pad can be saved and applied again later.
+
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::report::{ReportError, ZoneColorReport, ZoneEntry, DEFAULT_FLAGS};
+use crate::status::{zone_index, DeviceState, KeyFrameState, ZoneState};
+use crate::units::{Brightness, Speed, MAX_DURATION};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    /// Raw intensity (0-255), `None` keeps the current intensity
+    #[serde(default)]
+    pub intensity: Option<u8>,
+    /// The zones to set, zones that are not listed keep their configuration
+    #[serde(default)]
+    pub zones: Vec<ZoneState>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ProfileError {
+    UnknownZone(String),
+    EmptyZone(String),
+    Brightness(u8),
+    DurationTooLong(u64),
+    Report(ReportError),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProfileError::UnknownZone(zone) => write!(
+                f,
+                "'{}' is not a zone, expected upper, lower or zone N",
+                zone
+            ),
+            ProfileError::EmptyZone(zone) => write!(f, "zone {} has no keyframes", zone),
+            ProfileError::Brightness(brightness) => {
+                write!(f, "brightness {} is not between 0% and 100%", brightness)
+            }
+            ProfileError::DurationTooLong(millis) => write!(
+                f,
+                "keyframes can be at most {}ms long, got {}ms",
+                MAX_DURATION.as_millis(),
+                millis
+            ),
+            ProfileError::Report(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+impl From<ReportError> for ProfileError {
+    fn from(e: ReportError) -> Self {
+        ProfileError::Report(e)
+    }
+}
+
+impl Profile {
+    /// The zones of `report` and the raw `intensity`.
+    pub fn new(report: &ZoneColorReport, intensity: Option<u8>) -> Self {
+        Profile::from(DeviceState::new(report, intensity))
+    }
+
+    /// The zone color report that sets the zones of the profile, `None` if
+    /// the profile has no zones.
+    pub fn zone_colors(&self) -> Result<Option<ZoneColorReport>, ProfileError> {
+        if self.zones.is_empty() {
+            return Ok(None);
+        }
+
+        let mut report = ZoneColorReport::new();
+
+        for zone in &self.zones {
+            let index = zone_index(&zone.zone)
+                .ok_or_else(|| ProfileError::UnknownZone(zone.zone.clone()))?;
+
+            if zone.key_frames.is_empty() {
+                return Err(ProfileError::EmptyZone(zone.zone.clone()));
+            }
+
+            for key_frame in &zone.key_frames {
+                report.push(entry(index, key_frame)?)?;
+            }
+        }
+
+        Ok(Some(report))
+    }
+}
+
+fn entry(zone: u8, key_frame: &KeyFrameState) -> Result<ZoneEntry, ProfileError> {
+    let brightness = Brightness::from_percent(key_frame.brightness)
+        .ok_or(ProfileError::Brightness(key_frame.brightness))?;
+
+    if key_frame.duration_ms > MAX_DURATION.as_millis() as u64 {
+        return Err(ProfileError::DurationTooLong(key_frame.duration_ms));
+    }
+
+    Ok(ZoneEntry {
+        color: key_frame.color,
+        brightness,
+        speed: Speed::new(key_frame.speed),
+        duration: Duration::from_millis(key_frame.duration_ms),
+        zone,
+        flags: DEFAULT_FLAGS,
+    })
+}
+
+/// Drops the mode, it follows from the keyframes.
+impl From<DeviceState> for Profile {
+    fn from(state: DeviceState) -> Self {
+        Profile {
+            intensity: state.intensity,
+            zones: state.zones,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modes::ColorShift;
+    use crate::{ColorRGB, KeyFrame, BLACK_COLOR};
+
+    #[test]
+    fn round_trips_through_json() {
+        let red = ColorRGB::new(0xff, 0, 0);
+        let color_shift = ColorShift::new(
+            Speed::new(0x40),
+            vec![KeyFrame::new(red, Duration::from_millis(500))],
+            vec![
+                KeyFrame::new(red, Duration::from_millis(500)),
+                KeyFrame::new(BLACK_COLOR, Duration::from_secs(1)),
+            ],
+        )
+        .unwrap();
+        let report = color_shift.to_report();
+
+        let json = serde_json::to_string(&Profile::new(&report, Some(0x80))).unwrap();
+        let profile: Profile = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(profile.intensity, Some(0x80));
+        assert_eq!(profile.zone_colors().unwrap(), Some(report));
+    }
+
+    #[test]
+    fn parses_partial_profiles() {
+        let profile: Profile = serde_json::from_str(
+            r##"{"zones": [{"zone": "upper", "key_frames": [
+                {"color": "#00ff00", "brightness": 50, "speed": 50, "duration_ms": 200}
+            ]}]}"##,
+        )
+        .unwrap();
+        assert_eq!(profile.intensity, None);
+
+        let report = profile.zone_colors().unwrap().unwrap();
+        assert_eq!(report.zones(), vec![1]);
+        assert_eq!(report.entries()[0].brightness.percent(), 50);
+
+        let profile: Profile = serde_json::from_str(r#"{"intensity": 255}"#).unwrap();
+        assert_eq!(profile.zone_colors(), Ok(None));
+    }
+
+    #[test]
+    fn rejects_unknown_zones_and_values() {
+        let zone = |name: &str, brightness: u8, duration_ms: u64| Profile {
+            intensity: None,
+            zones: vec![ZoneState {
+                zone: name.to_string(),
+                key_frames: vec![KeyFrameState {
+                    color: BLACK_COLOR,
+                    brightness,
+                    speed: 50,
+                    duration_ms,
+                }],
+            }],
+        };
+
+        assert_eq!(
+            zone("zone 2", 100, 200)
+                .zone_colors()
+                .unwrap()
+                .unwrap()
+                .zones(),
+            vec![2]
+        );
+        assert_eq!(
+            zone("middle", 100, 200).zone_colors(),
+            Err(ProfileError::UnknownZone("middle".to_string()))
+        );
+        assert_eq!(
+            zone("lower", 101, 200).zone_colors(),
+            Err(ProfileError::Brightness(101))
+        );
+        assert_eq!(
+            zone("lower", 100, 70000).zone_colors(),
+            Err(ProfileError::DurationTooLong(70000))
+        );
+    }
+}
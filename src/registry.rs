@@ -0,0 +1,317 @@
+//! The pads that can be controlled and what they support.
+//!
+//! The commands are built from the [`Capabilities`] of a pad instead of fixed
+//! report sizes, so another model only needs a definition file, see
+//! [`crate::definition`]. Pads without a definition can be used with `--vid`
+//! and `--pid`, they are assumed to work like the QCK Prism Cloth.
+
+use std::path::PathBuf;
+
+use crate::definition::{self, DefinitionError};
+use crate::modes::Mode;
+use crate::report::{ReportError, ZoneColorReport};
+use crate::{ColorRGB, Error, Result, BLACK_COLOR};
+
+/// A HID report the pad accepts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReportLayout {
+    /// wValue of the control transfer, see [`crate::transport`]
+    pub value: u16,
+    /// wIndex of the control transfer, the HID interface
+    pub index: u16,
+    /// First byte of the report
+    pub id: u8,
+    /// Size of the report including trailing padding
+    pub len: usize,
+}
+
+/// How the colors of the zones are laid out in the zone color report.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ZoneFormat {
+    /// A list of keyframes, see [`crate::report`]
+    Keyframes,
+    /// One color per zone, the red, green and blue byte of zone `n` start at
+    /// the `n`th offset
+    Offsets(Vec<usize>),
+}
+
+/// An output report holding the raw intensity.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntensityReport {
+    pub layout: ReportLayout,
+    /// Position of the intensity in the report
+    pub offset: usize,
+}
+
+/// What a model supports and how its reports look.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capabilities {
+    /// Number of LED zones, the zone indices start at 0
+    pub zones: u8,
+    /// Report with the zone colors
+    pub zone_report: ReportLayout,
+    pub zone_format: ZoneFormat,
+    /// Output report that applies the zone colors, only its id is set
+    pub apply_report: ReportLayout,
+    /// `None` if the intensity cannot be changed
+    pub intensity_report: Option<IntensityReport>,
+    pub modes: Vec<Mode>,
+}
+
+impl Capabilities {
+    /// Two zones, 524 byte zone color reports and all modes, as described
+    /// by [`definition::BUILTIN`].
+    pub fn qck_prism_cloth() -> Self {
+        definition::builtin().capabilities
+    }
+
+    /// Fails if the report uses a zone or a mode the pad does not have.
+    pub fn check(&self, report: &ZoneColorReport) -> Result<()> {
+        if let Some(zone) = report.zones().into_iter().find(|zone| *zone >= self.zones) {
+            return Err(Error::Unsupported(format!(
+                "zone {}, the pad has {} zones",
+                zone, self.zones
+            )));
+        }
+
+        let mode = Mode::of(report);
+        if !self.modes.contains(&mode) {
+            return Err(Error::Unsupported(format!("{} mode", mode)));
+        }
+
+        Ok(())
+    }
+
+    /// The zone color report the pad expects. With [`ZoneFormat::Offsets`]
+    /// the first keyframe of every zone is sent, zones without one are off.
+    pub fn encode_zone_colors(&self, report: &ZoneColorReport) -> Result<Vec<u8>> {
+        self.check(report)?;
+        let layout = &self.zone_report;
+
+        match &self.zone_format {
+            ZoneFormat::Keyframes => Ok(report.encode(layout)?),
+            ZoneFormat::Offsets(offsets) => {
+                let mut data = vec![0u8; layout.len];
+                data[0] = layout.id;
+
+                for (zone, offset) in offsets.iter().enumerate() {
+                    let color = report
+                        .entries()
+                        .iter()
+                        .find(|entry| usize::from(entry.zone) == zone)
+                        .map_or(BLACK_COLOR, |entry| entry.color);
+
+                    data[*offset..*offset + 3].copy_from_slice(&[
+                        color.red,
+                        color.green,
+                        color.blue,
+                    ]);
+                }
+
+                Ok(data)
+            }
+        }
+    }
+
+    /// Parses a zone color report read back from the pad.
+    pub fn decode_zone_colors(&self, data: &[u8]) -> Result<ZoneColorReport> {
+        let layout = &self.zone_report;
+
+        match &self.zone_format {
+            ZoneFormat::Keyframes => Ok(ZoneColorReport::decode(data, layout)?),
+            ZoneFormat::Offsets(offsets) => {
+                match data.first() {
+                    Some(id) if *id == layout.id => (),
+                    Some(id) => return Err(ReportError::UnexpectedReportId(*id).into()),
+                    None => return Err(ReportError::TooShort(0).into()),
+                }
+
+                let mut builder = ZoneColorReport::builder();
+                for (zone, offset) in offsets.iter().enumerate() {
+                    let rgb = data
+                        .get(*offset..*offset + 3)
+                        .ok_or(ReportError::TooShort(data.len()))?;
+                    builder = builder.entry(zone as u8, ColorRGB::new(rgb[0], rgb[1], rgb[2]));
+                }
+
+                Ok(builder.build()?)
+            }
+        }
+    }
+}
+
+/// A model of pad, identified by its USB ids.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Model {
+    pub name: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub capabilities: Capabilities,
+}
+
+/// The known models.
+#[derive(Clone, Debug)]
+pub struct Registry {
+    models: Vec<Model>,
+}
+
+impl Registry {
+    /// Only the built-in QCK Prism Cloth.
+    pub fn builtin() -> Self {
+        Registry {
+            models: vec![definition::builtin()],
+        }
+    }
+
+    /// The built-in models and the definitions in [`definition::dirs`].
+    /// Invalid definition files are skipped and returned.
+    pub fn load() -> (Self, Vec<DefinitionError>) {
+        Registry::load_from(&definition::dirs())
+    }
+
+    pub fn load_from(dirs: &[PathBuf]) -> (Self, Vec<DefinitionError>) {
+        let mut registry = Registry::builtin();
+        let mut errors = vec![];
+
+        for dir in dirs {
+            let (models, mut dir_errors) = definition::load_dir(dir);
+            models.into_iter().for_each(|model| registry.add(model));
+            errors.append(&mut dir_errors);
+        }
+
+        (registry, errors)
+    }
+
+    /// Adds the model, replacing the model with the same ids.
+    pub fn add(&mut self, model: Model) {
+        self.models
+            .retain(|m| (m.vendor_id, m.product_id) != (model.vendor_id, model.product_id));
+        self.models.push(model);
+    }
+
+    pub fn models(&self) -> &[Model] {
+        &self.models
+    }
+
+    /// The known model with the ids.
+    pub fn find(&self, vendor_id: u16, product_id: u16) -> Option<&Model> {
+        self.models
+            .iter()
+            .find(|model| model.vendor_id == vendor_id && model.product_id == product_id)
+    }
+
+    /// The model with the ids, unknown pads get the capabilities of the QCK
+    /// Prism Cloth.
+    pub fn model(&self, vendor_id: u16, product_id: u16) -> Model {
+        match self.find(vendor_id, product_id) {
+            Some(model) => model.clone(),
+            None => Model {
+                name: format!("unknown pad {:04x}:{:04x}", vendor_id, product_id),
+                vendor_id,
+                product_id,
+                capabilities: Capabilities::qck_prism_cloth(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modes::EmulatedBreathe;
+    use crate::report::ZoneEntry;
+    use crate::{PRODUCT_ID, VENDOR_ID};
+    use std::time::Duration;
+
+    fn offsets() -> Capabilities {
+        Capabilities {
+            zones: 2,
+            zone_report: ReportLayout {
+                len: 10,
+                id: 0x0b,
+                ..Capabilities::qck_prism_cloth().zone_report
+            },
+            zone_format: ZoneFormat::Offsets(vec![4, 1]),
+            modes: vec![Mode::Steady],
+            ..Capabilities::qck_prism_cloth()
+        }
+    }
+
+    #[test]
+    fn unknown_pads_work_like_the_qck_prism_cloth() {
+        let registry = Registry::builtin();
+        assert_eq!(
+            registry
+                .find(VENDOR_ID, PRODUCT_ID)
+                .unwrap()
+                .capabilities
+                .zones,
+            2
+        );
+        assert!(registry.find(0x1038, 0x0001).is_none());
+
+        let model = registry.model(0x1038, 0x0001);
+        assert_eq!(model.name, "unknown pad 1038:0001");
+        assert_eq!(model.capabilities, Capabilities::qck_prism_cloth());
+    }
+
+    #[test]
+    fn added_models_replace_models_with_the_same_ids() {
+        let mut registry = Registry::builtin();
+        registry.add(Model {
+            name: "QCK Prism Cloth, one zone".to_string(),
+            vendor_id: VENDOR_ID,
+            product_id: PRODUCT_ID,
+            capabilities: Capabilities {
+                zones: 1,
+                ..Capabilities::qck_prism_cloth()
+            },
+        });
+
+        assert_eq!(registry.models().len(), 1);
+        assert_eq!(registry.model(VENDOR_ID, PRODUCT_ID).capabilities.zones, 1);
+    }
+
+    #[test]
+    fn check_rejects_missing_zones_and_modes() {
+        let capabilities = Capabilities {
+            zones: 1,
+            modes: vec![Mode::Steady],
+            ..Capabilities::qck_prism_cloth()
+        };
+        let red = ColorRGB::new(0xff, 0, 0);
+
+        let mut report = ZoneColorReport::new();
+        report.push(ZoneEntry::new(0, red)).unwrap();
+        assert!(capabilities.check(&report).is_ok());
+
+        let report = ZoneColorReport::steady(red, BLACK_COLOR);
+        assert_eq!(
+            capabilities.check(&report).unwrap_err().to_string(),
+            "not supported by the pad: zone 1, the pad has 1 zones"
+        );
+
+        let breathe = EmulatedBreathe::new(Duration::from_secs(4), vec![red], vec![red]).unwrap();
+        let capabilities = Capabilities {
+            modes: vec![Mode::Steady],
+            ..Capabilities::qck_prism_cloth()
+        };
+        assert_eq!(
+            capabilities
+                .check(&breathe.to_report())
+                .unwrap_err()
+                .to_string(),
+            "not supported by the pad: breathe mode"
+        );
+    }
+
+    #[test]
+    fn encodes_zone_colors_at_offsets() {
+        let capabilities = offsets();
+        let report = ZoneColorReport::steady(ColorRGB::new(1, 2, 3), ColorRGB::new(4, 5, 6));
+
+        let data = capabilities.encode_zone_colors(&report).unwrap();
+        assert_eq!(data, vec![0x0b, 4, 5, 6, 1, 2, 3, 0, 0, 0]);
+        assert_eq!(capabilities.decode_zone_colors(&data).unwrap(), report);
+    }
+}
@@ -0,0 +1,509 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::commands::ColorRGB;
+use crate::registry::ReportLayout;
+use crate::units::{duration_to_millis, Brightness, Speed, MAX_DURATION};
+
+/// First byte of every zone color report.
+pub const ZONE_COLOR_REPORT_ID: u8 = 0x0e;
+
+/// Size of the feature report the pad expects, including trailing padding.
+pub const ZONE_COLOR_REPORT_LEN: usize = 524;
+
+const HEADER_LEN: usize = 4;
+const ENTRY_LEN: usize = 12;
+
+/// Maximum number of entries that fit into a single report.
+pub const MAX_ENTRIES: usize = (ZONE_COLOR_REPORT_LEN - HEADER_LEN) / ENTRY_LEN;
+
+/// Keyframe duration the vendor software sends for the "Steady" mode.
+pub const DEFAULT_DURATION: Duration = Duration::from_millis(200);
+
+/// Flags byte observed in every entry sent by the vendor software.
+pub const DEFAULT_FLAGS: u8 = 0x01;
+
+#[derive(Debug, PartialEq)]
+pub enum ReportError {
+    TooShort(usize),
+    UnexpectedReportId(u8),
+    TooManyEntries(usize),
+    EmptyZone(u8),
+    DurationTooLong(Duration),
+    /// The entries need more bytes than the report of the pad has
+    TooLarge {
+        len: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for ReportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReportError::TooShort(len) => write!(f, "report is too short ({} bytes)", len),
+            ReportError::UnexpectedReportId(id) => {
+                write!(
+                    f,
+                    "expected report 0x{:02x}, got 0x{:02x}",
+                    ZONE_COLOR_REPORT_ID, id
+                )
+            }
+            ReportError::TooManyEntries(count) => write!(
+                f,
+                "report holds {} entries, at most {} are supported",
+                count, MAX_ENTRIES
+            ),
+            ReportError::EmptyZone(zone) => write!(f, "zone {} has no keyframes", zone),
+            ReportError::DurationTooLong(duration) => write!(
+                f,
+                "keyframes can be at most {}ms long, got {}ms",
+                MAX_DURATION.as_millis(),
+                duration.as_millis()
+            ),
+            ReportError::TooLarge { len, max } => {
+                write!(f, "report needs {} bytes, the pad accepts {}", len, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReportError {}
+
+/// A single entry of the zone color report. Every entry is a keyframe of its
+/// zone, a zone with a single entry shows a steady color.
+///
+/// Layout (12 bytes):
+///
+/// | offset | meaning                                  |
+/// |--------|------------------------------------------|
+/// | 0..3   | red, green, blue                         |
+/// | 3      | brightness                               |
+/// | 4      | speed                                    |
+/// | 5..7   | duration in ms, little endian            |
+/// | 7      | always zero                              |
+/// | 8      | zone index (0 = lower, 1 = upper)        |
+/// | 9      | `flags`                                  |
+/// | 10     | always zero                              |
+/// | 11     | position of the entry inside the report  |
+///
+/// See [`crate::units`] for the brightness, speed and duration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZoneEntry {
+    pub color: ColorRGB,
+    pub brightness: Brightness,
+    pub speed: Speed,
+    pub duration: Duration,
+    pub zone: u8,
+    pub flags: u8,
+}
+
+impl ZoneEntry {
+    pub fn new(zone: u8, color: ColorRGB) -> Self {
+        ZoneEntry {
+            color,
+            brightness: Brightness::default(),
+            speed: Speed::default(),
+            duration: DEFAULT_DURATION,
+            zone,
+            flags: DEFAULT_FLAGS,
+        }
+    }
+
+    fn write(&self, position: u8, out: &mut [u8]) {
+        out[0] = self.color.red;
+        out[1] = self.color.green;
+        out[2] = self.color.blue;
+        out[3] = self.brightness.raw();
+        out[4] = self.speed.raw();
+        out[5..7].copy_from_slice(&duration_to_millis(self.duration).to_le_bytes());
+        out[8] = self.zone;
+        out[9] = self.flags;
+        out[11] = position;
+    }
+
+    fn read(data: &[u8]) -> Self {
+        ZoneEntry {
+            color: ColorRGB::new(data[0], data[1], data[2]),
+            brightness: Brightness::from_raw(data[3]),
+            speed: Speed::new(data[4]),
+            duration: Duration::from_millis(u64::from(u16::from_le_bytes([data[5], data[6]]))),
+            zone: data[8],
+            flags: data[9],
+        }
+    }
+}
+
+/// The 0x0e feature report that sets the colors of the zones.
+///
+/// The report starts with a 4 byte header (report id, a zero byte and the
+/// number of entries as little endian u16) followed by the entries. The rest
+/// of the report is zero padded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ZoneColorReport {
+    entries: Vec<ZoneEntry>,
+}
+
+impl ZoneColorReport {
+    pub fn new() -> Self {
+        ZoneColorReport::default()
+    }
+
+    pub fn builder() -> ReportBuilder {
+        ReportBuilder::default()
+    }
+
+    /// Report with one entry per zone, the way the vendor software sends it
+    /// for the "Steady" mode.
+    pub fn steady(lower_color: ColorRGB, upper_color: ColorRGB) -> Self {
+        ZoneColorReport {
+            entries: vec![
+                ZoneEntry::new(0, lower_color),
+                ZoneEntry::new(1, upper_color),
+            ],
+        }
+    }
+
+    /// Report with one entry of the same color for each of the `zones`.
+    pub fn uniform(zones: u8, color: ColorRGB) -> Self {
+        ZoneColorReport {
+            entries: (0..zones).map(|zone| ZoneEntry::new(zone, color)).collect(),
+        }
+    }
+
+    /// Report with one entry per color, the first color for zone 0.
+    pub fn per_zone(colors: &[ColorRGB]) -> Self {
+        ZoneColorReport {
+            entries: colors
+                .iter()
+                .enumerate()
+                .map(|(zone, color)| ZoneEntry::new(zone as u8, *color))
+                .collect(),
+        }
+    }
+
+    pub fn entries(&self) -> &[ZoneEntry] {
+        &self.entries
+    }
+
+    /// Indices of the zones that have at least one entry, in report order.
+    pub fn zones(&self) -> Vec<u8> {
+        let mut zones = vec![];
+        for entry in &self.entries {
+            if !zones.contains(&entry.zone) {
+                zones.push(entry.zone);
+            }
+        }
+
+        zones
+    }
+
+    /// Replaces all entries of `zone`, the entries of the other zones are kept.
+    pub fn replace_zone(&mut self, zone: u8, entries: Vec<ZoneEntry>) -> Result<(), ReportError> {
+        let kept = self.entries.iter().filter(|e| e.zone != zone).count();
+        if kept + entries.len() > MAX_ENTRIES {
+            return Err(ReportError::TooManyEntries(kept + entries.len()));
+        }
+
+        self.entries.retain(|e| e.zone != zone);
+        self.entries
+            .extend(entries.into_iter().map(|entry| ZoneEntry { zone, ..entry }));
+        // stable, the keyframes of each zone stay in order
+        self.entries.sort_by_key(|e| e.zone);
+
+        Ok(())
+    }
+
+    /// Number of bytes up to the last entry, the rest of the report is padding.
+    pub fn encoded_len(&self) -> usize {
+        HEADER_LEN + self.entries.len() * ENTRY_LEN
+    }
+
+    pub fn push(&mut self, entry: ZoneEntry) -> Result<(), ReportError> {
+        if self.entries.len() == MAX_ENTRIES {
+            return Err(ReportError::TooManyEntries(MAX_ENTRIES + 1));
+        }
+
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> [u8; ZONE_COLOR_REPORT_LEN] {
+        let mut data = [0u8; ZONE_COLOR_REPORT_LEN];
+        self.write(ZONE_COLOR_REPORT_ID, &mut data);
+
+        data
+    }
+
+    /// Encodes the report for a pad whose zone color report has another id
+    /// or size than the one of the QCK Prism Cloth.
+    pub fn encode(&self, layout: &ReportLayout) -> Result<Vec<u8>, ReportError> {
+        if self.encoded_len() > layout.len {
+            return Err(ReportError::TooLarge {
+                len: self.encoded_len(),
+                max: layout.len,
+            });
+        }
+
+        let mut data = vec![0u8; layout.len];
+        self.write(layout.id, &mut data);
+
+        Ok(data)
+    }
+
+    fn write(&self, id: u8, data: &mut [u8]) {
+        let count = self.entries.len() as u16;
+        data[0] = id;
+        data[2..4].copy_from_slice(&count.to_le_bytes());
+
+        for (position, entry) in self.entries.iter().enumerate() {
+            let offset = HEADER_LEN + position * ENTRY_LEN;
+            entry.write(position as u8, &mut data[offset..offset + ENTRY_LEN]);
+        }
+    }
+
+    /// Parses a report. Trailing padding is optional, so truncated captures
+    /// can be parsed as long as all announced entries are present.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ReportError> {
+        Self::parse(data, ZONE_COLOR_REPORT_ID, MAX_ENTRIES)
+    }
+
+    /// Parses a report read from a pad with the `layout`.
+    pub fn decode(data: &[u8], layout: &ReportLayout) -> Result<Self, ReportError> {
+        let max_entries = layout.len.saturating_sub(HEADER_LEN) / ENTRY_LEN;
+        Self::parse(data, layout.id, max_entries)
+    }
+
+    fn parse(data: &[u8], id: u8, max_entries: usize) -> Result<Self, ReportError> {
+        if data.len() < HEADER_LEN {
+            return Err(ReportError::TooShort(data.len()));
+        }
+
+        if data[0] != id {
+            return Err(ReportError::UnexpectedReportId(data[0]));
+        }
+
+        let count = u16::from_le_bytes([data[2], data[3]]) as usize;
+        if count > max_entries {
+            return Err(ReportError::TooManyEntries(count));
+        }
+
+        if data.len() < HEADER_LEN + count * ENTRY_LEN {
+            return Err(ReportError::TooShort(data.len()));
+        }
+
+        let entries = data[HEADER_LEN..HEADER_LEN + count * ENTRY_LEN]
+            .chunks(ENTRY_LEN)
+            .map(ZoneEntry::read)
+            .collect();
+
+        Ok(ZoneColorReport { entries })
+    }
+}
+
+/// Builds a report entry by entry. Brightness, speed and duration apply to
+/// all entries added after they were set.
+///
+/// ```
+/// use rgame_linux::report::ZoneColorReport;
+/// use rgame_linux::units::Brightness;
+/// use rgame_linux::ColorRGB;
+/// use std::time::Duration;
+///
+/// let report = ZoneColorReport::builder()
+///     .brightness(Brightness::from_percent(50).unwrap())
+///     .duration(Duration::from_millis(500))
+///     .entry(0, ColorRGB::new(255, 0, 0))
+///     .entry(1, ColorRGB::new(0, 0, 255))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ReportBuilder {
+    brightness: Brightness,
+    speed: Speed,
+    duration: Option<Duration>,
+    entries: Vec<ZoneEntry>,
+}
+
+impl ReportBuilder {
+    pub fn brightness(mut self, brightness: Brightness) -> Self {
+        self.brightness = brightness;
+        self
+    }
+
+    pub fn speed(mut self, speed: Speed) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    pub fn entry(mut self, zone: u8, color: ColorRGB) -> Self {
+        self.entries.push(ZoneEntry {
+            brightness: self.brightness,
+            speed: self.speed,
+            duration: self.duration.unwrap_or(DEFAULT_DURATION),
+            ..ZoneEntry::new(zone, color)
+        });
+        self
+    }
+
+    /// Fails if the entries do not fit into a single report or a duration does
+    /// not fit into an entry.
+    pub fn build(self) -> Result<ZoneColorReport, ReportError> {
+        if self.entries.len() > MAX_ENTRIES {
+            return Err(ReportError::TooManyEntries(self.entries.len()));
+        }
+
+        if let Some(entry) = self.entries.iter().find(|e| e.duration > MAX_DURATION) {
+            return Err(ReportError::DurationTooLong(entry.duration));
+        }
+
+        Ok(ZoneColorReport {
+            entries: self.entries,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::BLACK_COLOR;
+
+    /// Pads a captured report prefix with zeros, the captures only differ from
+    /// zero in their first 28 bytes.
+    fn golden(prefix: &[u8]) -> [u8; ZONE_COLOR_REPORT_LEN] {
+        let mut data = [0u8; ZONE_COLOR_REPORT_LEN];
+        data[..prefix.len()].copy_from_slice(prefix);
+        data
+    }
+
+    const DISABLE_LOWER_ZONE: [u8; 28] = [
+        0x0e, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0xff, 0x32, 0xc8, 0x00, 0x00, 0x00, 0x01, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0xff, 0x32, 0xc8, 0x00, 0x00, 0x01, 0x01, 0x00, 0x01,
+    ];
+
+    const DISABLE_UPPER_ZONE: [u8; 28] = [
+        0x0e, 0x00, 0x02, 0x00, 0xff, 0x52, 0x00, 0xff, 0x32, 0xc8, 0x00, 0x00, 0x00, 0x01, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0xff, 0x32, 0xc8, 0x00, 0x00, 0x01, 0x01, 0x00, 0x01,
+    ];
+
+    /// `switch_mode_to_steady` with lower = (0x11, 0x22, 0x33), upper = (0x44, 0x55, 0x66)
+    const STEADY: [u8; 28] = [
+        0x0e, 0x00, 0x02, 0x00, 0x11, 0x22, 0x33, 0xff, 0x32, 0xc8, 0x00, 0x00, 0x00, 0x01, 0x00,
+        0x00, 0x44, 0x55, 0x66, 0xff, 0x32, 0xc8, 0x00, 0x00, 0x01, 0x01, 0x00, 0x01,
+    ];
+
+    /// The literal that used to live in `set_solid_color`: a usbmon URB header
+    /// followed by the first 460 bytes of the report.
+    const SOLID_CAPTURE: [u8; 92] = [
+        0x80, 0xb6, 0xdc, 0x42, 0x9f, 0x89, 0xff, 0xff, 0x53, 0x02, 0x00, 0x03, 0x01, 0x00, 0x00,
+        0x00, 0xee, 0xfe, 0x9e, 0x5f, 0x00, 0x00, 0x00, 0x00, 0x8d, 0x18, 0x08, 0x00, 0x8d, 0xff,
+        0xff, 0xff, 0x0c, 0x02, 0x00, 0x00, 0x0c, 0x02, 0x00, 0x00, 0x21, 0x09, 0x00, 0x03, 0x00,
+        0x00, 0x0c, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x0e, 0x00, 0x02, 0x00, 0xff, 0x00, 0x00, 0xff, 0x32, 0xc8, 0x00,
+        0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x32, 0xc8, 0x00, 0x00, 0x01, 0x01,
+        0x00, 0x01,
+    ];
+
+    #[test]
+    fn disable_lower_zone_matches_capture() {
+        let report = ZoneColorReport::steady(BLACK_COLOR, BLACK_COLOR);
+        assert_eq!(report.to_bytes()[..], golden(&DISABLE_LOWER_ZONE)[..]);
+    }
+
+    #[test]
+    fn disable_upper_zone_matches_capture() {
+        let report = ZoneColorReport::steady(ColorRGB::new(0xff, 0x52, 0x00), BLACK_COLOR);
+        assert_eq!(report.to_bytes()[..], golden(&DISABLE_UPPER_ZONE)[..]);
+    }
+
+    #[test]
+    fn steady_matches_capture() {
+        let report = ZoneColorReport::steady(
+            ColorRGB::new(0x11, 0x22, 0x33),
+            ColorRGB::new(0x44, 0x55, 0x66),
+        );
+        assert_eq!(report.to_bytes()[..], golden(&STEADY)[..]);
+    }
+
+    #[test]
+    fn captures_round_trip() {
+        for capture in [&DISABLE_LOWER_ZONE, &DISABLE_UPPER_ZONE, &STEADY].iter() {
+            let data = golden(*capture);
+            let report = ZoneColorReport::from_bytes(&data).unwrap();
+            assert_eq!(report.entries().len(), 2);
+            assert_eq!(report.to_bytes()[..], data[..]);
+        }
+    }
+
+    #[test]
+    fn parses_report_behind_urb_header() {
+        let report = ZoneColorReport::from_bytes(&SOLID_CAPTURE[64..]).unwrap();
+        assert_eq!(
+            report,
+            ZoneColorReport::steady(ColorRGB::new(0xff, 0x00, 0x00), BLACK_COLOR)
+        );
+        assert_eq!(report.to_bytes()[..28], SOLID_CAPTURE[64..]);
+    }
+
+    #[test]
+    fn rejects_other_reports() {
+        assert_eq!(
+            ZoneColorReport::from_bytes(&SOLID_CAPTURE),
+            Err(ReportError::UnexpectedReportId(0x80))
+        );
+        assert_eq!(
+            ZoneColorReport::from_bytes(&DISABLE_LOWER_ZONE[..20]),
+            Err(ReportError::TooShort(20))
+        );
+    }
+
+    #[test]
+    fn push_stops_at_report_size() {
+        let mut report = ZoneColorReport::new();
+        for _ in 0..MAX_ENTRIES {
+            report.push(ZoneEntry::new(0, BLACK_COLOR)).unwrap();
+        }
+        assert!(report.push(ZoneEntry::new(0, BLACK_COLOR)).is_err());
+    }
+
+    #[test]
+    fn encodes_reports_of_other_sizes() {
+        let layout = ReportLayout {
+            value: 0x0300,
+            index: 0,
+            id: 0x0f,
+            len: 28,
+        };
+        let report = ZoneColorReport::uniform(2, BLACK_COLOR);
+
+        let data = report.encode(&layout).unwrap();
+        assert_eq!(data[0], 0x0f);
+        assert_eq!(data[1..], DISABLE_LOWER_ZONE[1..]);
+        assert_eq!(ZoneColorReport::decode(&data, &layout), Ok(report));
+
+        let report = ZoneColorReport::uniform(3, BLACK_COLOR);
+        assert_eq!(
+            report.encode(&layout),
+            Err(ReportError::TooLarge { len: 40, max: 28 })
+        );
+    }
+
+    #[test]
+    fn replace_zone_keeps_other_zone() {
+        let red = ColorRGB::new(0xff, 0, 0);
+        let blue = ColorRGB::new(0, 0, 0xff);
+        let mut report = ZoneColorReport::steady(red, red);
+
+        report
+            .replace_zone(0, vec![ZoneEntry::new(1, blue), ZoneEntry::new(1, red)])
+            .unwrap();
+
+        let colors: Vec<_> = report.entries().iter().map(|e| (e.zone, e.color)).collect();
+        assert_eq!(colors, vec![(0, blue), (0, red), (1, red)]);
+    }
+}
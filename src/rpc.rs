@@ -0,0 +1,356 @@
+//! The JSON-RPC 2.0 API of `rqckd` and a client for it.
+//!
+//! `rqckd` listens on a Unix socket, see [`socket_path`]. Requests,
+//! responses and notifications are JSON objects, one per line.
+//!
+//! | method          | params                               | result               |
+//! |-----------------|--------------------------------------|----------------------|
+//! | `set_color`     | `color`, optional `zone`             | `null`               |
+//! | `set_intensity` | `intensity`, raw value (0-255)       | `null`               |
+//! | `apply_profile` | `profile`, see [`Profile`]           | `null`               |
+//! | `show_frame`    | `colors`, one per zone from zone 0   | `null`               |
+//! | `set_layer`     | `name`, `color`, see below           | `null`               |
+//! | `remove_layer`  | `name`                               | `null`               |
+//! | `get_state`     |                                      | list of [`PadState`] |
+//! | `subscribe`     |                                      | `true`               |
+//!
+//! `set_layer` sets a layer of the [`crate::compositor`], replacing the one
+//! with the same `name`. It takes an optional `zone` like `set_color`,
+//! `priority` (default 0), `opacity` between 0 and 1 (default 1), `blend`
+//! (`normal`, `add` or `multiply`) and `ttl`, the milliseconds until the
+//! layer is removed, at most a day.
+//!
+//! Every method also takes `device` and `all`, they select the pads like
+//! `--device` and `--all` do. Without them the first pad is used.
+//!
+//! After `subscribe` the daemon sends a [`STATE_CHANGED`] notification with a
+//! [`PadState`] whenever a pad was changed, by any client. Frames of effects
+//! sent with `show_frame` are not changes.
+
+use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::pad::Pad;
+use crate::paths::runtime_dir;
+use crate::profile::Profile;
+use crate::report::ZoneColorReport;
+use crate::status::DeviceState;
+use crate::{ColorRGB, Error, Result};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// The pad failed or does not support the request
+pub const PAD_ERROR: i64 = -32000;
+/// No pad matches `device`
+pub const NO_PAD: i64 = -32001;
+
+/// Method of the notifications sent after `subscribe`.
+pub const STATE_CHANGED: &str = "state_changed";
+
+/// `$XDG_RUNTIME_DIR/rqck/rqckd.sock`, without a runtime directory
+/// `rqckd-UID.sock` in the temporary directory.
+pub fn socket_path() -> PathBuf {
+    match runtime_dir() {
+        Some(dir) => dir.join("rqckd.sock"),
+        None => {
+            let uid = unsafe { libc::getuid() };
+            env::temp_dir().join(format!("rqckd-{}.sock", uid))
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    /// `None` for notifications, they get no response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
+}
+
+impl Request {
+    pub fn new(id: Option<Value>, method: &str, params: Value) -> Self {
+        Request {
+            jsonrpc: "2.0".to_string(),
+            id,
+            method: method.to_string(),
+            params,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+/// Either the `result` or the `error` of a [`Response`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Result(Value),
+    Error(RpcError),
+}
+
+impl Response {
+    pub fn new(id: Value, result: std::result::Result<Value, RpcError>) -> Self {
+        Response {
+            jsonrpc: "2.0".to_string(),
+            id,
+            outcome: match result {
+                Ok(value) => Outcome::Result(value),
+                Err(e) => Outcome::Error(e),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new<M: ToString>(code: i64, message: M) -> Self {
+        RpcError {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/// The state of one pad, as returned by `get_state` and sent with
+/// [`STATE_CHANGED`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PadState {
+    /// Selects the pad in the `device` param, see
+    /// [`crate::device::PadInfo::selector`]
+    pub device: String,
+    /// `None` if the pad cannot be read back and nothing was written to it
+    pub state: Option<DeviceState>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Message {
+    Request(Request),
+    Response(Response),
+}
+
+/// A connection to `rqckd`.
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next_id: u64,
+    notifications: VecDeque<Request>,
+}
+
+impl Client {
+    /// Fails if no daemon listens on `path`.
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Client::new(UnixStream::connect(path)?)
+    }
+
+    pub fn new(stream: UnixStream) -> io::Result<Self> {
+        Ok(Client {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            next_id: 1,
+            notifications: VecDeque::new(),
+        })
+    }
+
+    /// Sends a request and waits for its result. Notifications that arrive
+    /// meanwhile are kept for [`Client::next_notification`].
+    pub fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let request = Request::new(Some(id.into()), method, params);
+        writeln!(self.writer, "{}", serde_json::to_string(&request).unwrap())?;
+
+        loop {
+            match self.read()? {
+                Message::Request(notification) => self.notifications.push_back(notification),
+                Message::Response(response) if response.id == id => {
+                    return match response.outcome {
+                        Outcome::Result(value) => Ok(value),
+                        Outcome::Error(e) => Err(e.into()),
+                    }
+                }
+                Message::Response(_) => (),
+            }
+        }
+    }
+
+    /// The state of the pads selected by `device` and `all`.
+    pub fn get_state(&mut self, device: Option<&str>, all: bool) -> Result<Vec<PadState>> {
+        let result = self.call("get_state", json!({ "device": device, "all": all }))?;
+
+        serde_json::from_value(result).map_err(invalid_data)
+    }
+
+    /// Asks for a notification whenever a pad changes.
+    pub fn subscribe(&mut self) -> Result<()> {
+        self.call("subscribe", Value::Null)?;
+        Ok(())
+    }
+
+    /// Waits for the next notification.
+    pub fn next_notification(&mut self) -> Result<Request> {
+        if let Some(notification) = self.notifications.pop_front() {
+            return Ok(notification);
+        }
+
+        loop {
+            if let Message::Request(notification) = self.read()? {
+                return Ok(notification);
+            }
+        }
+    }
+
+    fn read(&mut self) -> Result<Message> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "rqckd closed the connection",
+            )
+            .into());
+        }
+
+        serde_json::from_str(&line).map_err(invalid_data)
+    }
+}
+
+fn invalid_data(e: serde_json::Error) -> Error {
+    io::Error::new(io::ErrorKind::InvalidData, e).into()
+}
+
+/// A pad opened by `rqckd`, every operation is a request to the daemon.
+///
+/// Zone colors are sent as `apply_profile`, so zones that are not in the
+/// report keep their configuration.
+pub struct RemotePad<'a> {
+    client: &'a mut Client,
+    device: String,
+}
+
+impl<'a> RemotePad<'a> {
+    /// `device` as returned in [`PadState::device`]
+    pub fn new(client: &'a mut Client, device: &str) -> Self {
+        RemotePad {
+            client,
+            device: device.to_string(),
+        }
+    }
+}
+
+impl Pad for RemotePad<'_> {
+    fn set_intensity(&mut self, intensity: u8) -> Result<()> {
+        let params = json!({ "device": self.device, "intensity": intensity });
+        self.client.call("set_intensity", params)?;
+        Ok(())
+    }
+
+    fn set_zone_colors(&mut self, report: &ZoneColorReport) -> Result<()> {
+        self.apply_profile(&Profile::new(report, None))
+    }
+
+    fn update_zones(&mut self, update: &ZoneColorReport) -> Result<()> {
+        self.apply_profile(&Profile::new(update, None))
+    }
+
+    fn status(&mut self) -> Result<DeviceState> {
+        self.client
+            .get_state(Some(&self.device), false)?
+            .into_iter()
+            .next()
+            .and_then(|pad| pad.state)
+            .ok_or_else(|| Error::Unsupported("reading back the state".to_string()))
+    }
+
+    fn show_frame(&mut self, report: &ZoneColorReport) -> Result<()> {
+        let colors: Vec<ColorRGB> = report.entries().iter().map(|entry| entry.color).collect();
+        let params = json!({ "device": self.device, "colors": colors });
+        self.client.call("show_frame", params)?;
+        Ok(())
+    }
+
+    fn restore_configuration(&mut self) -> Result<()> {
+        // while a frame is shown rqckd answers with the configuration
+        let profile = Profile {
+            intensity: None,
+            ..Profile::from(self.status()?)
+        };
+        self.apply_profile(&profile)
+    }
+
+    fn apply_profile(&mut self, profile: &Profile) -> Result<()> {
+        let params = json!({ "device": self.device, "profile": profile });
+        self.client.call("apply_profile", params)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses_have_either_a_result_or_an_error() {
+        let ok = Response::new(1.into(), Ok(Value::Null));
+        assert_eq!(
+            serde_json::to_string(&ok).unwrap(),
+            r#"{"jsonrpc":"2.0","id":1,"result":null}"#
+        );
+
+        let error = Response::new(2.into(), Err(RpcError::new(METHOD_NOT_FOUND, "no")));
+        let json = serde_json::to_string(&error).unwrap();
+        assert_eq!(
+            json,
+            r#"{"jsonrpc":"2.0","id":2,"error":{"code":-32601,"message":"no"}}"#
+        );
+        assert_eq!(serde_json::from_str::<Response>(&json).unwrap(), error);
+    }
+
+    #[test]
+    fn tells_notifications_from_responses() {
+        let message = |json: &str| serde_json::from_str::<Message>(json).unwrap();
+
+        assert!(matches!(
+            message(r#"{"jsonrpc":"2.0","method":"state_changed","params":{}}"#),
+            Message::Request(Request { id: None, .. })
+        ));
+        assert!(matches!(
+            message(r#"{"jsonrpc":"2.0","id":1,"result":null}"#),
+            Message::Response(Response {
+                outcome: Outcome::Result(Value::Null),
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,105 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::paths::cache_dir;
+use crate::report::ZoneColorReport;
+
+/// The last configuration written to the pad, persisted in
+/// `~/.cache/rqck/state.json`, or `state-<pad>.json` for pads with a serial
+/// or known port.
+///
+/// Used to fill in the zones a command does not touch when the pad cannot be
+/// read back.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StateCache {
+    /// hex encoded zone color report without its padding
+    zone_colors: Option<String>,
+    intensity: Option<u8>,
+    #[serde(skip)]
+    pad: Option<String>,
+}
+
+impl StateCache {
+    /// `pad` is the id of the pad, see [`crate::device::PadInfo::id`].
+    pub fn path(pad: Option<&str>) -> Option<PathBuf> {
+        let name = match pad {
+            Some(pad) => {
+                let pad: String = pad
+                    .chars()
+                    .map(|c| {
+                        if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                            c
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect();
+                format!("state-{}.json", pad)
+            }
+            None => "state.json".to_string(),
+        };
+
+        cache_dir().map(|dir| dir.join(name))
+    }
+
+    /// Returns an empty cache if there is no cache file or it cannot be read.
+    pub fn load(pad: Option<&str>) -> StateCache {
+        let cache: StateCache = StateCache::path(pad)
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        StateCache {
+            pad: pad.map(str::to_string),
+            ..cache
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = StateCache::path(self.pad.as_deref())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no cache directory"))?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        fs::write(path, serde_json::to_string(self)?)
+    }
+
+    pub fn zone_colors(&self) -> Option<ZoneColorReport> {
+        let data = decode_hex(self.zone_colors.as_ref()?)?;
+
+        ZoneColorReport::from_bytes(&data).ok()
+    }
+
+    pub fn set_zone_colors(&mut self, report: &ZoneColorReport) {
+        let len = report.encoded_len();
+        self.zone_colors = Some(encode_hex(&report.to_bytes()[..len]));
+    }
+
+    pub fn intensity(&self) -> Option<u8> {
+        self.intensity
+    }
+
+    pub fn set_intensity(&mut self, intensity: u8) {
+        self.intensity = Some(intensity);
+    }
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
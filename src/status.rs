@@ -0,0 +1,121 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::commands::{ColorRGB, Zone};
+use crate::modes::Mode;
+use crate::report::ZoneColorReport;
+
+/// What the pad currently shows, as read back from the device.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeviceState {
+    pub mode: Mode,
+    /// Raw intensity (0-255) last set, `None` if it is unknown. The pad is
+    /// not asked for it, so it is only known from the state cache.
+    pub intensity: Option<u8>,
+    pub zones: Vec<ZoneState>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ZoneState {
+    pub zone: String,
+    pub key_frames: Vec<KeyFrameState>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyFrameState {
+    pub color: ColorRGB,
+    /// in percent
+    pub brightness: u8,
+    pub speed: u8,
+    pub duration_ms: u64,
+}
+
+impl DeviceState {
+    pub fn new(report: &ZoneColorReport, intensity: Option<u8>) -> Self {
+        let mut zones: Vec<(u8, ZoneState)> = vec![];
+
+        for entry in report.entries() {
+            let key_frame = KeyFrameState {
+                color: entry.color,
+                brightness: entry.brightness.percent(),
+                speed: entry.speed.raw(),
+                duration_ms: entry.duration.as_millis() as u64,
+            };
+
+            match zones.iter_mut().find(|(zone, _)| *zone == entry.zone) {
+                Some((_, state)) => state.key_frames.push(key_frame),
+                None => zones.push((
+                    entry.zone,
+                    ZoneState {
+                        zone: zone_name(entry.zone),
+                        key_frames: vec![key_frame],
+                    },
+                )),
+            }
+        }
+
+        DeviceState {
+            mode: Mode::of(report),
+            intensity,
+            zones: zones.into_iter().map(|(_, state)| state).collect(),
+        }
+    }
+}
+
+/// `lower`, `upper` or `zone N`
+pub fn zone_name(index: u8) -> String {
+    match Zone::from_index(index) {
+        Some(zone) => zone.to_string().to_lowercase(),
+        None => format!("zone {}", index),
+    }
+}
+
+/// The index of a zone named by [`ZoneState::zone`], e.g. `upper` or `zone 2`.
+pub fn zone_index(name: &str) -> Option<u8> {
+    match name.strip_prefix("zone ") {
+        Some(index) => index.parse().ok(),
+        None => name.parse::<Zone>().ok().map(Zone::index),
+    }
+}
+
+impl fmt::Display for DeviceState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "mode:      {}", self.mode)?;
+
+        match self.intensity {
+            Some(intensity) => writeln!(
+                f,
+                "intensity: {}%",
+                (u16::from(intensity) * 100 + 127) / 255
+            )?,
+            None => writeln!(f, "intensity: unknown")?,
+        }
+
+        for zone in &self.zones {
+            writeln!(f, "{}", zone)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for ZoneState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<10}", format!("{}:", self.zone))?;
+
+        for (i, key_frame) in self.key_frames.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+
+            write!(
+                f,
+                "{} {}ms (brightness {}%, speed {})",
+                key_frame.color, key_frame.duration_ms, key_frame.brightness, key_frame.speed
+            )?;
+        }
+
+        Ok(())
+    }
+}
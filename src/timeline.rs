@@ -0,0 +1,409 @@
+//! Effect descriptions: every zone fades through a list of colors, each with
+//! its own duration and easing, and starts over after the last one.
+//!
+//! [`Timeline::compile`] turns the description into keyframes the pad plays
+//! by itself, like the "ColorShift" mode and the emulated breathing, as long
+//! as they fit into the zone color report of the pad. Otherwise the timeline
+//! is an [`Effect`] that is streamed from the host.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::commands::ParseColorError;
+use crate::effects::{self, Effect, Playback};
+use crate::registry::Capabilities;
+use crate::report::{ReportError, ZoneColorReport};
+use crate::units::{parse_duration, ParseUnitError, MAX_DURATION};
+use crate::{ColorRGB, Mode, Pad, Result, BLACK_COLOR};
+
+/// Number of linear keyframes a step with another easing becomes.
+const EASED_KEYFRAMES: u32 = 4;
+
+/// How a step moves from its color to the next one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// The progress at `t`, both between 0 and 1.
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut if t < 0.5 => 2.0 * t * t,
+            Easing::EaseInOut => 1.0 - 2.0 * (1.0 - t) * (1.0 - t),
+        }
+    }
+}
+
+impl fmt::Display for Easing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Easing::Linear => write!(f, "linear"),
+            Easing::EaseIn => write!(f, "ease-in"),
+            Easing::EaseOut => write!(f, "ease-out"),
+            Easing::EaseInOut => write!(f, "ease-in-out"),
+        }
+    }
+}
+
+impl FromStr for Easing {
+    type Err = ParseStepError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim() {
+            "linear" => Ok(Easing::Linear),
+            "ease-in" => Ok(Easing::EaseIn),
+            "ease-out" => Ok(Easing::EaseOut),
+            "ease-in-out" => Ok(Easing::EaseInOut),
+            _ => Err(ParseStepError::Easing(s.to_string())),
+        }
+    }
+}
+
+/// The zone shows `color` and fades to the color of the next step over
+/// `duration`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub color: ColorRGB,
+    pub duration: Duration,
+    pub easing: Easing,
+}
+
+impl Step {
+    pub fn new(color: ColorRGB, duration: Duration, easing: Easing) -> Self {
+        Step {
+            color,
+            duration,
+            easing,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseStepError {
+    Color(ParseColorError),
+    Duration(ParseUnitError),
+    ZeroDuration(String),
+    Easing(String),
+    MissingDuration(String),
+}
+
+impl fmt::Display for ParseStepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseStepError::Color(e) => e.fmt(f),
+            ParseStepError::Duration(e) => e.fmt(f),
+            ParseStepError::ZeroDuration(s) => write!(f, "'{}' must last longer than 0ms", s),
+            ParseStepError::Easing(s) => write!(
+                f,
+                "'{}' is not an easing, expected linear, ease-in, ease-out or ease-in-out",
+                s
+            ),
+            ParseStepError::MissingDuration(s) => {
+                write!(
+                    f,
+                    "'{}' has no duration, expected COLOR:DURATION[:EASING]",
+                    s
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseStepError {}
+
+/// Parses steps written as `COLOR:DURATION[:EASING]`, e.g. `red:1s:ease-in`.
+/// Without an easing the step is linear.
+impl FromStr for Step {
+    type Err = ParseStepError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let color = parts.next().unwrap_or_default();
+        let duration = parts
+            .next()
+            .ok_or_else(|| ParseStepError::MissingDuration(s.to_string()))?;
+
+        let color = color.parse().map_err(ParseStepError::Color)?;
+        let duration = parse_duration(duration).map_err(ParseStepError::Duration)?;
+        if duration.is_zero() {
+            return Err(ParseStepError::ZeroDuration(s.to_string()));
+        }
+
+        let easing = match parts.next() {
+            Some(easing) if parts.next().is_none() => easing.parse()?,
+            Some(_) => return Err(ParseStepError::Easing(s.to_string())),
+            None => Easing::Linear,
+        };
+
+        Ok(Step::new(color, duration, easing))
+    }
+}
+
+/// The steps of every zone, starting with zone 0.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timeline {
+    zones: Vec<Vec<Step>>,
+}
+
+/// How a timeline is played.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Plan {
+    /// The pad plays the keyframes by itself. `approximated` if eased steps
+    /// were split into linear keyframes.
+    Upload {
+        report: ZoneColorReport,
+        approximated: bool,
+    },
+    /// The keyframes do not fit, the frames have to be computed on the host.
+    Stream { reason: String },
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Plan::Upload {
+                report,
+                approximated,
+            } => {
+                write!(
+                    f,
+                    "played by the pad as {} with {} keyframes",
+                    Mode::of(report),
+                    report.entries().len()
+                )?;
+                if *approximated {
+                    write!(f, ", eased steps are approximated by linear fades")?;
+                }
+                Ok(())
+            }
+            Plan::Stream { reason } => write!(f, "streamed from this computer, {}", reason),
+        }
+    }
+}
+
+impl Timeline {
+    /// Fails if a zone has no steps.
+    pub fn new(zones: Vec<Vec<Step>>) -> std::result::Result<Self, ReportError> {
+        if let Some(zone) = zones.iter().position(Vec::is_empty) {
+            return Err(ReportError::EmptyZone(zone as u8));
+        }
+
+        Ok(Timeline { zones })
+    }
+
+    pub fn zones(&self) -> &[Vec<Step>] {
+        &self.zones
+    }
+
+    /// The color of the zone at `time`, zones without steps are black.
+    pub fn color_at(&self, zone: u8, time: Duration) -> ColorRGB {
+        let steps = match self.zones.get(zone as usize) {
+            Some(steps) => steps,
+            None => return BLACK_COLOR,
+        };
+
+        let cycle: Duration = steps.iter().map(|step| step.duration).sum();
+        let mut time = Duration::from_nanos((time.as_nanos() % cycle.as_nanos().max(1)) as u64);
+
+        for (i, step) in steps.iter().enumerate() {
+            if time < step.duration {
+                let next = steps[(i + 1) % steps.len()].color;
+                let t = time.as_secs_f64() / step.duration.as_secs_f64();
+                return mix(step.color, next, step.easing.apply(t));
+            }
+            time -= step.duration;
+        }
+
+        steps[0].color
+    }
+
+    /// Chooses between uploading keyframes and streaming. The keyframes fit
+    /// if the pad has the zones and the mode and they fit into its report.
+    pub fn compile(&self, capabilities: &Capabilities) -> Plan {
+        match self.to_report() {
+            Ok((report, approximated)) => match capabilities.encode_zone_colors(&report) {
+                Ok(_) => Plan::Upload {
+                    report,
+                    approximated,
+                },
+                Err(e) => Plan::Stream {
+                    reason: e.to_string(),
+                },
+            },
+            Err(e) => Plan::Stream {
+                reason: format!("the pad cannot hold the keyframes: {}", e),
+            },
+        }
+    }
+
+    /// The pad fades linearly from one keyframe to the next, steps with
+    /// another easing are split into several keyframes on their curve. So
+    /// are steps that are too long for a single keyframe.
+    fn to_report(&self) -> std::result::Result<(ZoneColorReport, bool), ReportError> {
+        let mut builder = ZoneColorReport::builder();
+        let mut approximated = false;
+
+        for (zone, steps) in self.zones.iter().enumerate() {
+            for (i, step) in steps.iter().enumerate() {
+                let next = steps[(i + 1) % steps.len()].color;
+
+                let mut keyframes = if step.easing == Easing::Linear || next == step.color {
+                    1
+                } else {
+                    approximated = true;
+                    EASED_KEYFRAMES
+                };
+                while step.duration / keyframes > MAX_DURATION {
+                    keyframes += 1;
+                }
+
+                for k in 0..keyframes {
+                    let t = f64::from(k) / f64::from(keyframes);
+                    builder = builder
+                        .duration(step.duration / keyframes)
+                        .entry(zone as u8, mix(step.color, next, step.easing.apply(t)));
+                }
+            }
+        }
+
+        Ok((builder.build()?, approximated))
+    }
+
+    /// Plays the timeline as planned. A streamed timeline is stopped like an
+    /// effect, see [`effects::play_until_interrupted`].
+    pub fn play<P: Pad + ?Sized>(
+        &mut self,
+        pad: &mut P,
+        plan: &Plan,
+        playback: &Playback,
+    ) -> Result<()> {
+        match plan {
+            Plan::Upload { report, .. } => pad.set_zone_colors(report),
+            Plan::Stream { .. } => effects::play_until_interrupted(pad, self, playback).map(|_| ()),
+        }
+    }
+}
+
+impl Effect for Timeline {
+    fn frame(&mut self, time: Duration, zones: u8) -> Vec<ColorRGB> {
+        (0..zones).map(|zone| self.color_at(zone, time)).collect()
+    }
+}
+
+/// The color `t` of the way from `from` to `to`.
+fn mix(from: ColorRGB, to: ColorRGB, t: f64) -> ColorRGB {
+    let component =
+        |from: u8, to: u8| (f64::from(from) + (f64::from(to) - f64::from(from)) * t).round() as u8;
+
+    ColorRGB::new(
+        component(from.red, to.red),
+        component(from.green, to.green),
+        component(from.blue, to.blue),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::ZoneFormat;
+
+    const RED: ColorRGB = ColorRGB {
+        red: 0xff,
+        green: 0,
+        blue: 0,
+    };
+
+    fn timeline(upper: &str) -> Timeline {
+        let steps: Vec<Step> = upper.split(',').map(|s| s.parse().unwrap()).collect();
+        Timeline::new(vec![steps.clone(), steps]).unwrap()
+    }
+
+    #[test]
+    fn parses_steps_with_easing() {
+        assert_eq!(
+            "red:1s:ease-in-out".parse::<Step>(),
+            Ok(Step::new(RED, Duration::from_secs(1), Easing::EaseInOut))
+        );
+        assert_eq!(
+            "#ff0000:500ms".parse::<Step>(),
+            Ok(Step::new(RED, Duration::from_millis(500), Easing::Linear))
+        );
+        assert!(matches!(
+            "red:1s:bounce".parse::<Step>(),
+            Err(ParseStepError::Easing(_))
+        ));
+        assert!(matches!(
+            "red:0s".parse::<Step>(),
+            Err(ParseStepError::ZeroDuration(_))
+        ));
+    }
+
+    #[test]
+    fn linear_timelines_are_uploaded_as_they_are() {
+        let capabilities = Capabilities::qck_prism_cloth();
+
+        let plan = timeline("red:1s,blue:500ms").compile(&capabilities);
+        match &plan {
+            Plan::Upload {
+                report,
+                approximated: false,
+            } => {
+                assert_eq!(report.entries().len(), 4);
+                assert_eq!(report.entries()[1].duration, Duration::from_millis(500));
+            }
+            plan => panic!("{}", plan),
+        }
+        assert_eq!(
+            plan.to_string(),
+            "played by the pad as colorshift with 4 keyframes"
+        );
+
+        let plan = timeline("red:2s,black:2s").compile(&capabilities);
+        assert!(plan.to_string().starts_with("played by the pad as breathe"));
+
+        let plan = timeline("red:1s:ease-in,blue:1s").compile(&capabilities);
+        assert_eq!(
+            plan.to_string(),
+            "played by the pad as colorshift with 10 keyframes, eased steps are approximated by linear fades"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_streaming_with_the_reason() {
+        let steps = ["red:100ms:ease-out,blue:100ms:ease-in"; 6].join(",");
+        let plan = timeline(&steps).compile(&Capabilities::qck_prism_cloth());
+        assert_eq!(
+            plan.to_string(),
+            "streamed from this computer, the pad cannot hold the keyframes: report holds 96 entries, at most 43 are supported"
+        );
+
+        let steady_only = Capabilities {
+            zone_format: ZoneFormat::Offsets(vec![1, 4]),
+            modes: vec![Mode::Steady],
+            ..Capabilities::qck_prism_cloth()
+        };
+        let plan = timeline("red:1s,blue:1s").compile(&steady_only);
+        assert_eq!(
+            plan.to_string(),
+            "streamed from this computer, not supported by the pad: colorshift mode"
+        );
+
+        // the streamed frames follow the easing
+        let mut eased = timeline("black:1s:ease-in,red:1s");
+        assert_eq!(
+            eased.frame(Duration::from_millis(500), 2),
+            vec![ColorRGB::new(0x40, 0, 0); 2]
+        );
+        assert_eq!(eased.color_at(0, Duration::from_millis(3000)), RED);
+    }
+}
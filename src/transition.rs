@@ -0,0 +1,313 @@
+//! Smooth transitions from what the pad shows to a new configuration.
+//!
+//! [`fade`] starts at the current colors and intensity of the pad, read back
+//! or taken from the state cache, and streams frames that move every zone to
+//! its new color in OKLab, so the colors in between look evenly spaced
+//! instead of passing through a dull gray. The new configuration is set once
+//! the transition is over.
+//!
+//! Changing the intensity with every frame would store it every time, so a
+//! transition runs at the higher of both intensities and dims the colors of
+//! the frames instead.
+
+use std::time::Duration;
+
+use crate::color::{from_oklab, to_oklab};
+use crate::effects::{self, scale, Effect, Playback};
+use crate::profile::Profile;
+use crate::report::ZoneColorReport;
+use crate::timeline::Easing;
+use crate::{ColorRGB, Pad, Result, BLACK_COLOR};
+
+/// Frames per second of a transition.
+pub const FPS: u32 = 30;
+
+/// How long a transition takes and how it speeds up and slows down.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fade {
+    pub duration: Duration,
+    pub easing: Easing,
+}
+
+/// The way of every zone from one color and intensity to another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transition {
+    /// OKLab colors of the zones, starting with zone 0
+    from: Vec<[f64; 3]>,
+    to: Vec<[f64; 3]>,
+    /// How much the colors are dimmed at the start and the end, between 0
+    /// and 1
+    levels: (f64, f64),
+    fade: Fade,
+}
+
+impl Transition {
+    /// From the colors `from` dimmed to `levels.0` to the colors `to` dimmed
+    /// to `levels.1`, one color per zone.
+    pub fn new(from: &[ColorRGB], to: &[ColorRGB], levels: (f64, f64), fade: Fade) -> Self {
+        Transition {
+            from: from.iter().copied().map(to_oklab).collect(),
+            to: to.iter().copied().map(to_oklab).collect(),
+            levels,
+            fade,
+        }
+    }
+
+    /// The color of every zone at `time`, the colors `to` from the end of
+    /// the transition on.
+    pub fn colors_at(&self, time: Duration) -> Vec<ColorRGB> {
+        let duration = self.fade.duration.as_secs_f64();
+        let t = if duration > 0.0 {
+            self.fade.easing.apply(time.as_secs_f64() / duration)
+        } else {
+            1.0
+        };
+        let level = self.levels.0 + (self.levels.1 - self.levels.0) * t;
+
+        self.from
+            .iter()
+            .zip(&self.to)
+            .map(|(from, to)| {
+                let lab = [0, 1, 2].map(|i| from[i] + (to[i] - from[i]) * t);
+                scale(from_oklab(lab), level)
+            })
+            .collect()
+    }
+}
+
+impl Effect for Transition {
+    fn frame(&mut self, time: Duration, _zones: u8) -> Vec<ColorRGB> {
+        self.colors_at(time)
+    }
+}
+
+/// Fades from what the pad shows to the zones of `update` and `intensity`,
+/// then sets them like [`Pad::update_zones`] and [`Pad::set_intensity`].
+/// Zones that are not in `update` keep their colors, `None` keeps the
+/// intensity.
+///
+/// If the state of the pad is not known the change is made at once, with a
+/// warning.
+pub fn fade<P: Pad + ?Sized>(
+    pad: &mut P,
+    update: &ZoneColorReport,
+    intensity: Option<u8>,
+    fade: &Fade,
+) -> Result<()> {
+    let zones = pad.capabilities().zones;
+    let current = match pad.current_state() {
+        Some(state) => state,
+        None => {
+            eprintln!("the current colors are unknown, changing them without a fade");
+            return set(pad, update, intensity, None);
+        }
+    };
+
+    let current_intensity = current.intensity;
+    if intensity.is_some() && current_intensity.is_none() {
+        eprintln!("the current intensity is unknown, changing it without a fade");
+        return set(pad, update, intensity, None);
+    }
+    let base = Profile::from(current)
+        .zone_colors()?
+        .unwrap_or_else(|| ZoneColorReport::uniform(zones, BLACK_COLOR));
+    let mut target = base.clone();
+    for zone in update.zones() {
+        let entries = update
+            .entries()
+            .iter()
+            .filter(|e| e.zone == zone)
+            .cloned()
+            .collect();
+        target.replace_zone(zone, entries)?;
+    }
+
+    let levels = match (current_intensity, intensity.or(current_intensity)) {
+        (Some(from), Some(to)) if from.max(to) > 0 => {
+            let peak = f64::from(from.max(to));
+            (f64::from(from) / peak, f64::from(to) / peak)
+        }
+        _ => (1.0, 1.0),
+    };
+    let mut transition = Transition::new(
+        &shown_colors(&base, zones),
+        &shown_colors(&target, zones),
+        levels,
+        *fade,
+    );
+
+    if let Some(intensity) = intensity.filter(|_| levels.0 < 1.0) {
+        // the frames start dimmed before the intensity goes up
+        let colors = transition.colors_at(Duration::ZERO);
+        pad.show_frame(&ZoneColorReport::per_zone(&colors))?;
+        pad.set_intensity(intensity)?;
+    }
+
+    let playback = Playback {
+        fps: FPS,
+        zones,
+        length: Some(fade.duration),
+    };
+    effects::play(pad, &mut transition, &playback)?;
+
+    set(pad, update, intensity, Some(&target))
+}
+
+/// Sets the change. The frames replaced the colors of all zones, so they
+/// are written again even if only the intensity changes.
+fn set<P: Pad + ?Sized>(
+    pad: &mut P,
+    update: &ZoneColorReport,
+    intensity: Option<u8>,
+    target: Option<&ZoneColorReport>,
+) -> Result<()> {
+    if let Some(intensity) = intensity {
+        pad.set_intensity(intensity)?;
+    }
+
+    if !update.entries().is_empty() {
+        pad.update_zones(update)
+    } else if let Some(target) = target {
+        pad.set_zone_colors(target)
+    } else {
+        Ok(())
+    }
+}
+
+/// The first color of every zone at the brightness of its entry, black for
+/// zones without entries.
+fn shown_colors(report: &ZoneColorReport, zones: u8) -> Vec<ColorRGB> {
+    (0..zones)
+        .map(|zone| {
+            report
+                .entries()
+                .iter()
+                .find(|entry| entry.zone == zone)
+                .map_or(BLACK_COLOR, |entry| {
+                    scale(entry.color, f64::from(entry.brightness.raw()) / 255.0)
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::ZoneEntry;
+    use crate::status::DeviceState;
+    use crate::Error;
+
+    const RED: ColorRGB = ColorRGB {
+        red: 0xff,
+        green: 0,
+        blue: 0,
+    };
+    const BLUE: ColorRGB = ColorRGB {
+        red: 0,
+        green: 0,
+        blue: 0xff,
+    };
+
+    #[test]
+    fn transitions_interpolate_in_oklab() {
+        let fade = Fade {
+            duration: Duration::from_secs(1),
+            easing: Easing::Linear,
+        };
+        let transition =
+            Transition::new(&[RED, BLACK_COLOR], &[BLUE, BLACK_COLOR], (1.0, 1.0), fade);
+
+        assert_eq!(transition.colors_at(Duration::ZERO), vec![RED, BLACK_COLOR]);
+        assert_eq!(
+            transition.colors_at(Duration::from_secs(1)),
+            vec![BLUE, BLACK_COLOR]
+        );
+        assert_eq!(
+            transition.colors_at(Duration::from_secs(2)),
+            vec![BLUE, BLACK_COLOR]
+        );
+
+        // halfway is a lighter purple than the mix of the RGB values, 0x800080
+        assert_eq!(
+            transition.colors_at(Duration::from_millis(500))[0],
+            ColorRGB::new(0x8c, 0x53, 0xa2)
+        );
+    }
+
+    #[test]
+    fn intensity_is_faded_by_dimming_the_colors() {
+        let fade = Fade {
+            duration: Duration::from_secs(1),
+            easing: Easing::EaseIn,
+        };
+        let transition = Transition::new(&[RED], &[RED], (1.0, 0.5), fade);
+
+        assert_eq!(transition.colors_at(Duration::ZERO), vec![RED]);
+        // a quarter of the way with ease-in at half of the time
+        assert_eq!(
+            transition.colors_at(Duration::from_millis(500)),
+            vec![ColorRGB::new(0xdf, 0, 0)]
+        );
+        assert_eq!(
+            transition.colors_at(Duration::from_secs(1)),
+            vec![ColorRGB::new(0x80, 0, 0)]
+        );
+    }
+
+    /// A pad that cannot be read back, but may know what it was set to.
+    struct UnreadablePad {
+        state: Option<DeviceState>,
+        written: Vec<ZoneColorReport>,
+    }
+
+    impl Pad for UnreadablePad {
+        fn set_intensity(&mut self, _intensity: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_zone_colors(&mut self, report: &ZoneColorReport) -> Result<()> {
+            self.written.push(report.clone());
+            Ok(())
+        }
+
+        fn update_zones(&mut self, update: &ZoneColorReport) -> Result<()> {
+            self.written.push(update.clone());
+            Ok(())
+        }
+
+        fn status(&mut self) -> Result<DeviceState> {
+            Err(Error::Unsupported("reading back the state".to_string()))
+        }
+
+        fn current_state(&mut self) -> Option<DeviceState> {
+            self.state.clone()
+        }
+    }
+
+    #[test]
+    fn fades_start_at_the_known_state_if_it_cannot_be_read_back() {
+        let short = Fade {
+            duration: Duration::from_millis(50),
+            easing: Easing::Linear,
+        };
+        let mut update = ZoneColorReport::new();
+        update.push(ZoneEntry::new(1, BLUE)).unwrap();
+
+        let mut pad = UnreadablePad {
+            state: Some(DeviceState::new(&ZoneColorReport::uniform(2, RED), None)),
+            written: vec![],
+        };
+        fade(&mut pad, &update, None, &short).unwrap();
+        assert!(pad.written.len() > 2);
+        assert_eq!(pad.written[0], ZoneColorReport::per_zone(&[RED, RED]));
+        assert_eq!(pad.written.last(), Some(&update));
+
+        // without a known state there are no frames
+        let mut pad = UnreadablePad {
+            state: None,
+            written: vec![],
+        };
+        fade(&mut pad, &update, None, &short).unwrap();
+        assert_eq!(pad.written, vec![update]);
+    }
+}
@@ -0,0 +1,141 @@
+//! How reports get to the pad.
+//!
+//! The pad is a HID device, every command is a SET_REPORT control transfer
+//! whose `value` holds the report type in the high byte and the report id in
+//! the low byte, and whose `index` is the number of the HID interface. The
+//! commands in [`crate::commands`] only need a [`Transport`], so they work
+//! with a rusb handle as well as with the [`MockTransport`] used by the tests.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use rusb::{DeviceHandle, UsbContext};
+
+use crate::{Error, Result};
+
+/// `value` of the output report used for the intensity and to apply changes.
+pub const OUTPUT_REPORT: u16 = 0x0200;
+
+/// `value` of the feature report holding the zone colors.
+pub const FEATURE_REPORT: u16 = 0x0300;
+
+pub trait Transport {
+    /// Sends `data` with a SET_REPORT request and returns the number of bytes
+    /// written.
+    fn write_report(
+        &mut self,
+        value: u16,
+        index: u16,
+        data: &[u8],
+        timeout: Duration,
+    ) -> Result<usize>;
+
+    /// Fills `data` with a GET_REPORT request and returns the number of bytes
+    /// read.
+    fn read_report(
+        &mut self,
+        value: u16,
+        index: u16,
+        data: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize>;
+}
+
+impl<T: UsbContext> Transport for DeviceHandle<T> {
+    fn write_report(
+        &mut self,
+        value: u16,
+        index: u16,
+        data: &[u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        Ok(self.write_control(0x21, 0x09, value, index, data, timeout)?)
+    }
+
+    fn read_report(
+        &mut self,
+        value: u16,
+        index: u16,
+        data: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        Ok(self.read_control(0xa1, 0x01, value, index, data, timeout)?)
+    }
+}
+
+/// A report written to a [`MockTransport`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SentReport {
+    pub value: u16,
+    pub index: u16,
+    pub data: Vec<u8>,
+    pub timeout: Duration,
+}
+
+/// In-memory transport that records every written report.
+///
+/// Reads are answered with the responses set with
+/// [`MockTransport::set_response`]. Without a response the read fails with
+/// [`rusb::Error::Pipe`], like a pad that stalls GET_REPORT requests.
+#[derive(Clone, Debug, Default)]
+pub struct MockTransport {
+    sent: Vec<SentReport>,
+    responses: HashMap<u16, Vec<u8>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        MockTransport::default()
+    }
+
+    /// Answers every GET_REPORT request for `value` with `data`.
+    pub fn set_response(&mut self, value: u16, data: &[u8]) {
+        self.responses.insert(value, data.to_vec());
+    }
+
+    /// All reports written so far, oldest first.
+    pub fn sent(&self) -> &[SentReport] {
+        &self.sent
+    }
+
+    /// Returns the reports written so far and forgets them.
+    pub fn take_sent(&mut self) -> Vec<SentReport> {
+        std::mem::take(&mut self.sent)
+    }
+}
+
+impl Transport for MockTransport {
+    fn write_report(
+        &mut self,
+        value: u16,
+        index: u16,
+        data: &[u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        self.sent.push(SentReport {
+            value,
+            index,
+            data: data.to_vec(),
+            timeout,
+        });
+
+        Ok(data.len())
+    }
+
+    fn read_report(
+        &mut self,
+        value: u16,
+        _index: u16,
+        data: &mut [u8],
+        _timeout: Duration,
+    ) -> Result<usize> {
+        let response = self
+            .responses
+            .get(&value)
+            .ok_or(Error::Usb(rusb::Error::Pipe))?;
+        let len = response.len().min(data.len());
+        data[..len].copy_from_slice(&response[..len]);
+
+        Ok(len)
+    }
+}
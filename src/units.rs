@@ -0,0 +1,377 @@
+//! Typed values for the fields of a zone entry that follow the RGB triplet.
+//!
+//! The vendor software sends `ff 32 c8 00` after every color. These bytes are
+//! interpreted as the brightness of the keyframe (`0xff` = 100%), the speed of
+//! the color cycle (`0x32` = 50 of 255) and the duration of the keyframe in
+//! milliseconds as little endian u16 (`0x00c8` = 200ms).
+//!
+//! The global intensity of the pad is an [`Intensity`], given in percent
+//! along a [`Curve`] or as the raw byte.
+
+use clap::arg_enum;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Longest duration that fits into the two duration bytes of an entry.
+pub const MAX_DURATION: Duration = Duration::from_millis(u16::MAX as u64);
+
+/// Brightness of a single entry, independent of the global intensity of the
+/// pad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Brightness(u8);
+
+impl Brightness {
+    pub const FULL: Brightness = Brightness(0xff);
+
+    pub fn from_raw(raw: u8) -> Self {
+        Brightness(raw)
+    }
+
+    /// Returns `None` if `percent` is above 100.
+    pub fn from_percent(percent: u8) -> Option<Self> {
+        if percent > 100 {
+            return None;
+        }
+
+        Some(Brightness(((u16::from(percent) * 255 + 50) / 100) as u8))
+    }
+
+    pub fn raw(self) -> u8 {
+        self.0
+    }
+
+    pub fn percent(self) -> u8 {
+        ((u16::from(self.0) * 100 + 127) / 255) as u8
+    }
+}
+
+impl Default for Brightness {
+    fn default() -> Self {
+        Brightness::FULL
+    }
+}
+
+impl fmt::Display for Brightness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}%", self.percent())
+    }
+}
+
+/// Parses percentages like `80` or `80%`
+impl FromStr for Brightness {
+    type Err = ParseUnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim_end_matches('%')
+            .parse::<u8>()
+            .ok()
+            .and_then(Brightness::from_percent)
+            .ok_or_else(|| ParseUnitError::Brightness(s.to_string()))
+    }
+}
+
+/// Speed of the color cycle, higher values are faster.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Speed(u8);
+
+impl Speed {
+    /// Speed the vendor software sends
+    pub const DEFAULT: Speed = Speed(0x32);
+
+    pub fn new(speed: u8) -> Self {
+        Speed(speed)
+    }
+
+    pub fn raw(self) -> u8 {
+        self.0
+    }
+}
+
+impl Default for Speed {
+    fn default() -> Self {
+        Speed::DEFAULT
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Speed {
+    type Err = ParseUnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<u8>()
+            .map(Speed)
+            .map_err(|_| ParseUnitError::Speed(s.to_string()))
+    }
+}
+
+arg_enum! {
+    /// How a percentage of the intensity is mapped to the raw value. The
+    /// LEDs emit light proportional to the raw value, but the eye tells the
+    /// low values apart much better than the high ones.
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub enum Curve {
+        Linear,
+        Gamma,
+        Cie
+    }
+}
+
+/// Exponent of the [`Curve::Gamma`] curve
+const GAMMA: f64 = 2.2;
+
+/// Global intensity of the LEDs, the raw value (0-255) sent to the pad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Intensity(u8);
+
+impl Intensity {
+    pub fn from_raw(raw: u8) -> Self {
+        Intensity(raw)
+    }
+
+    /// Maps `percent` to the raw value along the curve: [`Curve::Linear`]
+    /// rounds down, [`Curve::Gamma`] raises it to the power of 2.2 and
+    /// [`Curve::Cie`] takes it as the lightness L* of CIE 1976. Returns
+    /// `None` if `percent` is above 100.
+    pub fn from_percent(percent: u8, curve: Curve) -> Option<Self> {
+        if percent > 100 {
+            return None;
+        }
+
+        let to_raw = |luminance: f64| (luminance * 255.0).round() as u8;
+        let raw = match curve {
+            Curve::Linear => (u16::from(percent) * 255 / 100) as u8,
+            Curve::Gamma => to_raw((f64::from(percent) / 100.0).powf(GAMMA)),
+            Curve::Cie => to_raw(cie_luminance(f64::from(percent))),
+        };
+
+        Some(Intensity(raw))
+    }
+
+    /// Parses percentages like `60` or `60%` and maps them along the curve.
+    pub fn parse_percent(s: &str, curve: Curve) -> Result<Self, ParseUnitError> {
+        s.trim_end_matches('%')
+            .parse::<u8>()
+            .ok()
+            .and_then(|percent| Intensity::from_percent(percent, curve))
+            .ok_or_else(|| ParseUnitError::Intensity(s.to_string()))
+    }
+
+    pub fn raw(self) -> u8 {
+        self.0
+    }
+}
+
+/// The relative luminance (0-1) of the CIE 1976 lightness L* (0-100).
+fn cie_luminance(lightness: f64) -> f64 {
+    if lightness > 8.0 {
+        ((lightness + 16.0) / 116.0).powi(3)
+    } else {
+        lightness / 903.3
+    }
+}
+
+/// Parses the raw value between 0 and 255
+impl FromStr for Intensity {
+    type Err = ParseUnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<u8>()
+            .map(Intensity)
+            .map_err(|_| ParseUnitError::RawIntensity(s.to_string()))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseUnitError {
+    Brightness(String),
+    Speed(String),
+    Intensity(String),
+    RawIntensity(String),
+    Duration(String),
+    DurationTooLong(String),
+}
+
+impl fmt::Display for ParseUnitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseUnitError::Brightness(s) => {
+                write!(f, "'{}' is not a brightness between 0% and 100%", s)
+            }
+            ParseUnitError::Speed(s) => write!(f, "'{}' is not a speed between 0 and 255", s),
+            ParseUnitError::Intensity(s) => {
+                write!(f, "'{}' is not an intensity between 0 and 100", s)
+            }
+            ParseUnitError::RawIntensity(s) => {
+                write!(f, "'{}' is not a raw intensity between 0 and 255", s)
+            }
+            ParseUnitError::Duration(s) => {
+                write!(f, "'{}' is not a duration, expected e.g. 200ms or 1.5s", s)
+            }
+            ParseUnitError::DurationTooLong(s) => write!(
+                f,
+                "'{}' is longer than the maximum of {}ms",
+                s,
+                MAX_DURATION.as_millis()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseUnitError {}
+
+/// Parses durations like `200ms`, `1.5s` or `200`. Numbers without a unit are
+/// milliseconds.
+pub fn parse_duration(s: &str) -> Result<Duration, ParseUnitError> {
+    let (value, factor) = if let Some(value) = s.strip_suffix("ms") {
+        (value, 1.0)
+    } else if let Some(value) = s.strip_suffix('s') {
+        (value, 1000.0)
+    } else {
+        (s, 1.0)
+    };
+
+    let millis = match value.trim().parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => value * factor,
+        _ => return Err(ParseUnitError::Duration(s.to_string())),
+    };
+
+    if millis > MAX_DURATION.as_millis() as f64 {
+        return Err(ParseUnitError::DurationTooLong(s.to_string()));
+    }
+
+    Ok(Duration::from_millis(millis.round() as u64))
+}
+
+/// Duration in milliseconds as stored in an entry, longer durations are
+/// clamped to [`MAX_DURATION`].
+pub fn duration_to_millis(duration: Duration) -> u16 {
+    duration.min(MAX_DURATION).as_millis() as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(millis: u64) -> Result<Duration, ParseUnitError> {
+        Ok(Duration::from_millis(millis))
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("200ms"), millis(200));
+        assert_eq!(parse_duration("1.5s"), millis(1500));
+        assert_eq!(parse_duration("200"), millis(200));
+        assert_eq!(parse_duration("0ms"), millis(0));
+        assert_eq!(parse_duration("65535ms"), millis(65535));
+
+        for invalid in ["", "ms", "s", "fast", "-1ms", "1.5m", "NaNs"] {
+            assert_eq!(
+                parse_duration(invalid),
+                Err(ParseUnitError::Duration(invalid.to_string()))
+            );
+        }
+
+        for too_long in ["65536ms", "65536", "66s"] {
+            assert_eq!(
+                parse_duration(too_long),
+                Err(ParseUnitError::DurationTooLong(too_long.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn durations_are_clamped_to_an_entry() {
+        assert_eq!(duration_to_millis(Duration::from_millis(200)), 0x00c8);
+        assert_eq!(duration_to_millis(Duration::from_secs(70)), u16::MAX);
+    }
+
+    #[test]
+    fn brightness_is_between_0_and_100_percent() {
+        assert_eq!("100%".parse(), Ok(Brightness::FULL));
+        assert_eq!("50".parse(), Ok(Brightness::from_raw(0x80)));
+        assert_eq!("0%".parse(), Ok(Brightness::from_raw(0x00)));
+        assert_eq!(Brightness::from_raw(0x80).percent(), 50);
+        assert_eq!(Brightness::from_percent(101), None);
+
+        for invalid in ["101%", "-1", "bright", ""] {
+            assert_eq!(
+                invalid.parse::<Brightness>(),
+                Err(ParseUnitError::Brightness(invalid.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn speed_is_between_0_and_255() {
+        assert_eq!("0".parse(), Ok(Speed::new(0)));
+        assert_eq!("255".parse(), Ok(Speed::new(255)));
+
+        for invalid in ["256", "-1", "50%"] {
+            assert_eq!(
+                invalid.parse::<Speed>(),
+                Err(ParseUnitError::Speed(invalid.to_string()))
+            );
+        }
+    }
+
+    fn raw(percent: u8, curve: Curve) -> Option<u8> {
+        Intensity::from_percent(percent, curve).map(Intensity::raw)
+    }
+
+    #[test]
+    fn intensity_percentages_follow_the_curve() {
+        for curve in [Curve::Linear, Curve::Gamma, Curve::Cie] {
+            assert_eq!(raw(0, curve), Some(0));
+            assert_eq!(raw(100, curve), Some(255));
+            assert_eq!(raw(101, curve), None);
+
+            let raws: Vec<u8> = (0..=100).filter_map(|p| raw(p, curve)).collect();
+            assert!(
+                raws.windows(2).all(|pair| pair[0] <= pair[1]),
+                "{:?}",
+                curve
+            );
+        }
+
+        // linear rounds down
+        assert_eq!(raw(50, Curve::Linear), Some(127));
+        assert_eq!(raw(60, Curve::Linear), Some(153));
+        // 0.5 ^ 2.2 = 0.2176
+        assert_eq!(raw(50, Curve::Gamma), Some(55));
+        assert_eq!(raw(10, Curve::Gamma), Some(2));
+        // L* 50 is a luminance of 0.1842, below L* 8 it is linear
+        assert_eq!(raw(50, Curve::Cie), Some(47));
+        assert_eq!(raw(8, Curve::Cie), Some(2));
+        assert_eq!(raw(9, Curve::Cie), Some(3));
+    }
+
+    #[test]
+    fn parses_intensities() {
+        assert_eq!(
+            Intensity::parse_percent("60%", Curve::Linear),
+            Ok(Intensity::from_raw(153))
+        );
+        assert_eq!(
+            Intensity::parse_percent("50", Curve::Cie),
+            Ok(Intensity::from_raw(47))
+        );
+        assert_eq!("128".parse(), Ok(Intensity::from_raw(128)));
+
+        for invalid in ["101", "-1", "dim", ""] {
+            assert_eq!(
+                Intensity::parse_percent(invalid, Curve::Gamma),
+                Err(ParseUnitError::Intensity(invalid.to_string()))
+            );
+        }
+        assert_eq!(
+            "256".parse::<Intensity>(),
+            Err(ParseUnitError::RawIntensity("256".to_string()))
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::time::Duration;
+
+use rusb::{Device, DeviceDescriptor, DeviceHandle, Error, Result, UsbContext};
+
+use crate::device::PadInfo;
+use crate::transport::Transport;
+
+#[derive(Debug)]
+pub struct Endpoint {
+    pub config: u8,
+    pub iface: u8,
+    pub setting: u8,
+    pub address: u8,
+}
+
+pub type OpenedDevice<T> = (Device<T>, DeviceDescriptor, DeviceHandle<T>);
+
+/// Opens the first device with the ids. Fails if the device is found but
+/// cannot be opened, e.g. because of missing permissions.
+pub fn open_device<T: UsbContext>(
+    context: &mut T,
+    vid: u16,
+    pid: u16,
+) -> Result<Option<OpenedDevice<T>>> {
+    context.set_log_level(rusb::LogLevel::Debug);
+
+    for device in context.devices()?.iter() {
+        let device_desc = match device.device_descriptor() {
+            Ok(d) => d,
+            Err(_) => continue,
+        };
+
+        if device_desc.vendor_id() == vid && device_desc.product_id() == pid {
+            let handle = device.open()?;
+            return Ok(Some((device, device_desc, handle)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Lists all devices with the ids. Devices that cannot be opened are listed
+/// without serial and with the reason in [`PadInfo::error`].
+pub fn list_pads<T: UsbContext>(context: &T, vid: u16, pid: u16) -> Result<Vec<PadInfo>> {
+    let mut pads = vec![];
+
+    for device in context.devices()?.iter() {
+        let device_desc = match device.device_descriptor() {
+            Ok(d) => d,
+            Err(_) => continue,
+        };
+
+        if device_desc.vendor_id() != vid || device_desc.product_id() != pid {
+            continue;
+        }
+
+        let version = device_desc.device_version();
+        let mut pad = PadInfo {
+            vendor_id: vid,
+            product_id: pid,
+            bus: Some(device.bus_number()),
+            address: Some(device.address()),
+            port_path: device.port_numbers().ok().map(|ports| {
+                let ports: Vec<String> = ports.iter().map(|port| port.to_string()).collect();
+                format!("{}-{}", device.bus_number(), ports.join("."))
+            }),
+            firmware: Some(format!(
+                "{}.{}{}",
+                version.major(),
+                version.minor(),
+                version.sub_minor()
+            )),
+            ..PadInfo::default()
+        };
+
+        match device.open() {
+            Ok(handle) => pad.serial = handle.read_serial_number_string_ascii(&device_desc).ok(),
+            Err(e) => pad.error = Some(format!("cannot open: {}", e)),
+        }
+
+        pads.push(pad);
+    }
+
+    Ok(pads)
+}
+
+/// Opens the device at `bus` and `address`.
+pub fn open_at<T: UsbContext>(
+    context: &T,
+    bus: u8,
+    address: u8,
+) -> Result<(Device<T>, DeviceHandle<T>)> {
+    let device = context
+        .devices()?
+        .iter()
+        .find(|device| device.bus_number() == bus && device.address() == address)
+        .ok_or(Error::NoDevice)?;
+    let handle = device.open()?;
+
+    Ok((device, handle))
+}
+
+// returns all readable endpoints for given usb device and descriptor
+pub fn find_readable_endpoints<T: UsbContext>(device: &mut Device<T>) -> Result<Vec<Endpoint>> {
+    let device_desc = device.device_descriptor()?;
+    let mut endpoints = vec![];
+    for n in 0..device_desc.num_configurations() {
+        let config_desc = match device.config_descriptor(n) {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+        // println!("{:#?}", config_desc);
+        for interface in config_desc.interfaces() {
+            for interface_desc in interface.descriptors() {
+                // println!("{:#?}", interface_desc);
+                for endpoint_desc in interface_desc.endpoint_descriptors() {
+                    // println!("{:#?}", endpoint_desc);
+                    endpoints.push(Endpoint {
+                        config: config_desc.number(),
+                        iface: interface_desc.interface_number(),
+                        setting: interface_desc.setting_number(),
+                        address: endpoint_desc.address(),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(endpoints)
+}
+
+pub fn configure_endpoint<T: UsbContext>(
+    handle: &mut DeviceHandle<T>,
+    endpoint: &Endpoint,
+) -> Result<()> {
+    //handle.set_active_configuration(endpoint.config)?;
+    handle.claim_interface(endpoint.iface)?;
+    handle.set_alternate_setting(endpoint.iface, endpoint.setting)
+}
+
+/// A rusb handle with the interface of the pad claimed.
+///
+/// The interface is released and the kernel driver reattached when the
+/// transport is dropped.
+pub struct UsbTransport<T: UsbContext> {
+    handle: DeviceHandle<T>,
+    iface: u8,
+    has_kernel_driver: bool,
+}
+
+impl<T: UsbContext> UsbTransport<T> {
+    /// Detaches the kernel driver if necessary and claims the first interface
+    /// with an endpoint.
+    pub fn new(mut device: Device<T>, mut handle: DeviceHandle<T>) -> Result<Self> {
+        let endpoints = find_readable_endpoints(&mut device)?;
+        let endpoint = endpoints.first().ok_or(Error::NotFound)?;
+
+        let has_kernel_driver = match handle.kernel_driver_active(endpoint.iface) {
+            Ok(true) => {
+                handle.detach_kernel_driver(endpoint.iface)?;
+                true
+            }
+            _ => false,
+        };
+
+        configure_endpoint(&mut handle, endpoint)?;
+
+        Ok(UsbTransport {
+            handle,
+            iface: endpoint.iface,
+            has_kernel_driver,
+        })
+    }
+
+    pub fn handle(&mut self) -> &mut DeviceHandle<T> {
+        &mut self.handle
+    }
+}
+
+impl<T: UsbContext> Transport for UsbTransport<T> {
+    fn write_report(
+        &mut self,
+        value: u16,
+        index: u16,
+        data: &[u8],
+        timeout: Duration,
+    ) -> crate::Result<usize> {
+        self.handle.write_report(value, index, data, timeout)
+    }
+
+    fn read_report(
+        &mut self,
+        value: u16,
+        index: u16,
+        data: &mut [u8],
+        timeout: Duration,
+    ) -> crate::Result<usize> {
+        self.handle.read_report(value, index, data, timeout)
+    }
+}
+
+impl<T: UsbContext> Drop for UsbTransport<T> {
+    fn drop(&mut self) {
+        let _ = self.handle.release_interface(self.iface);
+        if self.has_kernel_driver {
+            let _ = self.handle.attach_kernel_driver(self.iface);
+        }
+    }
+}
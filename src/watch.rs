@@ -0,0 +1,87 @@
+//! Notices pads being plugged in, e.g. after a KVM switch, through libusb
+//! hotplug events.
+
+use std::cell::RefCell;
+use std::mem;
+use std::rc::Rc;
+use std::time::Duration;
+
+use rusb::{Device, Hotplug, Registration, UsbContext};
+
+use crate::registry::Model;
+
+/// A pad that was plugged in or removed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    Arrived { bus: u8, address: u8 },
+    Left { bus: u8, address: u8 },
+}
+
+// libusb does not allow I/O on the device from within the callback, so the
+// events are only queued and handled after `handle_events` returned
+struct Queue(Rc<RefCell<Vec<Event>>>);
+
+impl<T: UsbContext> Hotplug<T> for Queue {
+    fn device_arrived(&mut self, device: Device<T>) {
+        self.0.borrow_mut().push(Event::Arrived {
+            bus: device.bus_number(),
+            address: device.address(),
+        });
+    }
+
+    fn device_left(&mut self, device: Device<T>) {
+        self.0.borrow_mut().push(Event::Left {
+            bus: device.bus_number(),
+            address: device.address(),
+        });
+    }
+}
+
+/// Listens for hotplug events of the pads of some models.
+pub struct Watcher<T: UsbContext> {
+    context: T,
+    events: Rc<RefCell<Vec<Event>>>,
+    registrations: Vec<Registration>,
+}
+
+impl<T: UsbContext> Watcher<T> {
+    /// Fails if libusb does not support hotplug events on this platform.
+    pub fn new(context: &T, models: &[Model]) -> rusb::Result<Self> {
+        if !rusb::has_hotplug() {
+            return Err(rusb::Error::NotSupported);
+        }
+
+        let mut watcher = Watcher {
+            context: context.clone(),
+            events: Rc::new(RefCell::new(vec![])),
+            registrations: vec![],
+        };
+
+        for model in models {
+            let registration = context.register_callback(
+                Some(model.vendor_id),
+                Some(model.product_id),
+                None,
+                Box::new(Queue(watcher.events.clone())),
+            )?;
+            watcher.registrations.push(registration);
+        }
+
+        Ok(watcher)
+    }
+
+    /// Waits up to `timeout` and returns the events that happened meanwhile.
+    pub fn wait(&mut self, timeout: Duration) -> rusb::Result<Vec<Event>> {
+        self.context.handle_events(Some(timeout))?;
+
+        Ok(mem::take(&mut *self.events.borrow_mut()))
+    }
+}
+
+impl<T: UsbContext> Drop for Watcher<T> {
+    fn drop(&mut self) {
+        for registration in self.registrations.drain(..) {
+            self.context.unregister_callback(registration);
+        }
+    }
+}
@@ -0,0 +1,633 @@
+//! Runs the CLI subcommands against a mock transport and checks the exact
+//! reports sent to the pad.
+//!
+//! The expected reports are written out byte by byte instead of being built
+//! with `ZoneColorReport`, so a change to the encoding shows up here.
+
+use std::time::Duration;
+
+use rgame_linux::registry::{Capabilities, ReportLayout};
+use rgame_linux::transport::{MockTransport, SentReport, FEATURE_REPORT, OUTPUT_REPORT};
+use rgame_linux::{cli, Error, QckPad, Result};
+
+const RED: [u8; 3] = [0xff, 0x00, 0x00];
+const GREEN: [u8; 3] = [0x00, 0xff, 0x00];
+const BLUE: [u8; 3] = [0x00, 0x00, 0xff];
+const ORANGE: [u8; 3] = [0xff, 0x52, 0x00];
+const BLACK: [u8; 3] = [0x00, 0x00, 0x00];
+
+const LOWER: u8 = 0;
+const UPPER: u8 = 1;
+
+fn run_with(transport: MockTransport, args: &[&str]) -> (Result<()>, Vec<SentReport>) {
+    run_on(transport, Capabilities::qck_prism_cloth(), args)
+}
+
+fn run_on(
+    transport: MockTransport,
+    capabilities: Capabilities,
+    args: &[&str],
+) -> (Result<()>, Vec<SentReport>) {
+    let args = std::iter::once("rgame_linux").chain(args.iter().cloned());
+    let matches = cli::app().get_matches_from_safe(args).unwrap();
+
+    let mut pad = QckPad::new(transport);
+    pad.use_capabilities(capabilities);
+    let result = cli::run(&mut pad, &matches);
+
+    (result, pad.transport().take_sent())
+}
+
+fn run(args: &[&str]) -> Vec<SentReport> {
+    let (result, sent) = run_with(MockTransport::new(), args);
+    result.unwrap();
+
+    sent
+}
+
+fn output_report(id: u8, value: u8) -> Vec<u8> {
+    let mut data = vec![0u8; 64];
+    data[0] = id;
+    data[2] = value;
+    data
+}
+
+fn apply() -> Vec<u8> {
+    output_report(0x0d, 0x00)
+}
+
+/// An entry with the values the vendor software sends: full brightness,
+/// speed 0x32 and 200ms.
+fn entry(zone: u8, color: [u8; 3]) -> [u8; 12] {
+    timed_entry(zone, color, 0xff, 0x32, 200)
+}
+
+fn timed_entry(zone: u8, color: [u8; 3], brightness: u8, speed: u8, millis: u16) -> [u8; 12] {
+    let millis = millis.to_le_bytes();
+
+    [
+        color[0], color[1], color[2], brightness, speed, millis[0], millis[1], 0x00, zone, 0x01,
+        0x00, 0x00,
+    ]
+}
+
+fn zone_report(entries: &[[u8; 12]]) -> Vec<u8> {
+    let mut data = vec![0x0e, 0x00, entries.len() as u8, 0x00];
+    for (position, entry) in entries.iter().enumerate() {
+        data.extend_from_slice(&entry[..11]);
+        data.push(position as u8);
+    }
+    data.resize(524, 0x00);
+    data
+}
+
+fn writes(sent: &[SentReport]) -> Vec<(u16, Vec<u8>)> {
+    sent.iter()
+        .map(|report| (report.value, report.data.clone()))
+        .collect()
+}
+
+fn pad_showing(entries: &[[u8; 12]]) -> MockTransport {
+    let mut transport = MockTransport::new();
+    transport.set_response(FEATURE_REPORT, &zone_report(entries));
+    transport
+}
+
+#[test]
+fn set_intensity_maps_percent_to_raw_value() {
+    let sent = run(&["set_intensity", "-i", "100"]);
+    assert_eq!(
+        writes(&sent),
+        vec![(OUTPUT_REPORT, output_report(0x0c, 0xff))]
+    );
+
+    let sent = run(&["set_intensity", "-i", "50"]);
+    assert_eq!(
+        writes(&sent),
+        vec![(OUTPUT_REPORT, output_report(0x0c, 0x7f))]
+    );
+    let sent = run(&["set_intensity", "-i", "50%", "--curve", "gamma"]);
+    assert_eq!(
+        writes(&sent),
+        vec![(OUTPUT_REPORT, output_report(0x0c, 0x37))]
+    );
+
+    // half the perceived lightness is less than a fifth of the light
+    let sent = run(&["set_intensity", "-i", "50", "--curve", "cie"]);
+    assert_eq!(
+        writes(&sent),
+        vec![(OUTPUT_REPORT, output_report(0x0c, 0x2f))]
+    );
+
+    let sent = run(&["set_intensity", "-i", "5", "--curve", "cie"]);
+    assert_eq!(
+        writes(&sent),
+        vec![(OUTPUT_REPORT, output_report(0x0c, 0x01))]
+    );
+
+    let sent = run(&["set_intensity", "-i", "200", "--raw"]);
+    assert_eq!(
+        writes(&sent),
+        vec![(OUTPUT_REPORT, output_report(0x0c, 0xc8))]
+    );
+}
+
+#[test]
+fn intensities_out_of_range_are_refused() {
+    for args in [
+        &["set_intensity", "-i", "101"][..],
+        &["set_intensity", "-i", "dim"],
+        &["set_intensity", "-i", "256", "--raw"],
+    ] {
+        let (result, sent) = run_with(MockTransport::new(), args);
+        assert!(matches!(result, Err(Error::Cli(_))), "{:?}", args);
+        assert!(sent.is_empty());
+    }
+}
+
+#[test]
+fn solid_sets_upper_and_lower_zone() {
+    let sent = run(&["solid", "255", "0", "0", "0", "0", "255"]);
+
+    assert_eq!(
+        writes(&sent),
+        vec![
+            (
+                FEATURE_REPORT,
+                zone_report(&[entry(LOWER, BLUE), entry(UPPER, RED)])
+            ),
+            (OUTPUT_REPORT, apply()),
+        ]
+    );
+}
+
+#[test]
+fn solid_accepts_hex_colors() {
+    let sent = run(&["solid", "ff0000", "#0000ff"]);
+
+    assert_eq!(
+        writes(&sent)[0],
+        (
+            FEATURE_REPORT,
+            zone_report(&[entry(LOWER, BLUE), entry(UPPER, RED)])
+        )
+    );
+}
+
+#[test]
+fn solid_encodes_brightness_speed_and_duration() {
+    let sent = run(&[
+        "solid",
+        "ff0000",
+        "0000ff",
+        "--brightness",
+        "50%",
+        "--speed",
+        "10",
+        "--duration",
+        "1s",
+    ]);
+
+    assert_eq!(
+        writes(&sent)[0],
+        (
+            FEATURE_REPORT,
+            zone_report(&[
+                timed_entry(LOWER, BLUE, 0x80, 0x0a, 1000),
+                timed_entry(UPPER, RED, 0x80, 0x0a, 1000),
+            ])
+        )
+    );
+}
+
+#[test]
+fn solid_zone_keeps_the_other_zone() {
+    let transport = pad_showing(&[entry(LOWER, GREEN), entry(UPPER, BLUE)]);
+    let (result, sent) = run_with(transport, &["solid", "ff5200", "--zone", "lower"]);
+    result.unwrap();
+
+    assert_eq!(
+        writes(&sent),
+        vec![
+            (
+                FEATURE_REPORT,
+                zone_report(&[entry(LOWER, ORANGE), entry(UPPER, BLUE)])
+            ),
+            (OUTPUT_REPORT, apply()),
+        ]
+    );
+}
+
+#[test]
+fn solid_zone_takes_a_single_color() {
+    for args in [
+        &["solid", "ff0000", "00ff00", "--zone", "lower"][..],
+        &["solid", "255", "0", "0", "0", "255", "0", "--zone", "upper"],
+    ] {
+        let (result, sent) = run_with(MockTransport::new(), args);
+
+        match result {
+            Err(Error::Cli(e)) => assert!(e.message.contains("--zone"), "{}", e.message),
+            result => panic!("{:?} for {:?}", result, args),
+        }
+        assert!(sent.is_empty());
+    }
+}
+
+#[test]
+fn solid_with_one_color_keeps_the_lower_zone() {
+    for args in [&["solid", "ff0000"][..], &["solid", "255", "0", "0"]] {
+        let transport = pad_showing(&[entry(LOWER, GREEN), entry(UPPER, BLUE)]);
+        let (result, sent) = run_with(transport, args);
+        result.unwrap();
+
+        assert_eq!(
+            writes(&sent),
+            vec![
+                (
+                    FEATURE_REPORT,
+                    zone_report(&[entry(LOWER, GREEN), entry(UPPER, RED)])
+                ),
+                (OUTPUT_REPORT, apply()),
+            ]
+        );
+    }
+}
+
+#[test]
+fn disable_keeps_the_other_zone() {
+    let transport = pad_showing(&[entry(LOWER, GREEN), entry(UPPER, ORANGE)]);
+    let (result, sent) = run_with(transport, &["disable", "lower"]);
+    result.unwrap();
+
+    assert_eq!(
+        writes(&sent),
+        vec![
+            (
+                FEATURE_REPORT,
+                zone_report(&[entry(LOWER, BLACK), entry(UPPER, ORANGE)])
+            ),
+            (OUTPUT_REPORT, apply()),
+        ]
+    );
+}
+
+#[test]
+fn disable_without_read_back_turns_off_both_zones() {
+    let sent = run(&["disable", "upper"]);
+
+    assert_eq!(
+        writes(&sent)[0],
+        (
+            FEATURE_REPORT,
+            zone_report(&[entry(LOWER, BLACK), entry(UPPER, BLACK)])
+        )
+    );
+}
+
+#[test]
+fn solid_accepts_every_color_syntax() {
+    for colors in [
+        ["red", "blue"],
+        ["rgb(255, 0, 0)", "hsl(240, 100%, 50%)"],
+        ["#f00", "hsv(240deg 100% 100%)"],
+    ] {
+        let sent = run(&["solid", colors[0], colors[1]]);
+
+        assert_eq!(
+            writes(&sent)[0],
+            (
+                FEATURE_REPORT,
+                zone_report(&[entry(LOWER, BLUE), entry(UPPER, RED)])
+            )
+        );
+    }
+
+    // commas within rgb() do not separate keyframes
+    assert_eq!(
+        run(&["colorshift", "-u", "rgb(255, 0, 0):500ms, blue:1s"]),
+        run(&["colorshift", "-u", "ff0000:500ms,0000ff:1s"])
+    );
+}
+
+#[test]
+fn invalid_colors_are_reported_instead_of_black() {
+    for args in [
+        &["solid", "255", "0", "25x"][..],
+        &["solid", "ornage"],
+        &["solid", "rgb(255, 3OO, 0)"],
+        &["colorshift", "-u", "rgb(255, 0, 0):500ms,blu:1s"],
+    ] {
+        let (result, sent) = run_with(MockTransport::new(), args);
+
+        match result {
+            Err(Error::Cli(e)) => assert!(
+                e.message.contains("'25x'")
+                    || e.message.contains("'ornage'")
+                    || e.message.contains("'3OO'")
+                    || e.message.contains("'blu'"),
+                "{}",
+                e.message
+            ),
+            result => panic!("{:?} for {:?}", result, args),
+        }
+        assert!(sent.is_empty());
+    }
+}
+
+#[test]
+fn colorshift_sends_keyframes_of_both_zones() {
+    let sent = run(&[
+        "colorshift",
+        "-s",
+        "20",
+        "-u",
+        "rgb(255, 0, 0):500ms, blue:1s",
+    ]);
+
+    assert_eq!(
+        writes(&sent),
+        vec![
+            (
+                FEATURE_REPORT,
+                zone_report(&[
+                    timed_entry(LOWER, RED, 0xff, 0x14, 500),
+                    timed_entry(LOWER, BLUE, 0xff, 0x14, 1000),
+                    timed_entry(UPPER, RED, 0xff, 0x14, 500),
+                    timed_entry(UPPER, BLUE, 0xff, 0x14, 1000),
+                ])
+            ),
+            (OUTPUT_REPORT, apply()),
+        ]
+    );
+}
+
+#[test]
+fn breathe_alternates_colors_with_black() {
+    let sent = run(&["breathe", "-p", "2s", "-u", "ff0000", "-l", "00ff00"]);
+
+    assert_eq!(
+        writes(&sent),
+        vec![
+            (
+                FEATURE_REPORT,
+                zone_report(&[
+                    timed_entry(LOWER, GREEN, 0xff, 0x32, 1000),
+                    timed_entry(LOWER, BLACK, 0xff, 0x32, 1000),
+                    timed_entry(UPPER, RED, 0xff, 0x32, 1000),
+                    timed_entry(UPPER, BLACK, 0xff, 0x32, 1000),
+                ])
+            ),
+            (OUTPUT_REPORT, apply()),
+        ]
+    );
+}
+
+#[test]
+fn profiles_are_applied_and_saved() {
+    let dir = std::env::temp_dir().join(format!("rqck-cli-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("config.toml");
+    std::fs::write(
+        &config,
+        "[profiles.work]\nmode = \"steady\"\nupper = \"ff5200\"\nlower = \"0000ff\"\nintensity = 50\n",
+    )
+    .unwrap();
+    let config = config.to_str().unwrap();
+
+    let sent = run(&["--config", config, "profile", "apply", "work"]);
+    assert_eq!(
+        writes(&sent),
+        vec![
+            (
+                FEATURE_REPORT,
+                zone_report(&[entry(LOWER, BLUE), entry(UPPER, ORANGE)])
+            ),
+            (OUTPUT_REPORT, apply()),
+            (OUTPUT_REPORT, output_report(0x0c, 0x7f)),
+        ]
+    );
+
+    // the intensity cannot be read back, the saved profile keeps it
+    let (result, sent) = run_with(
+        pad_showing(&[entry(LOWER, GREEN), entry(UPPER, RED)]),
+        &["--config", config, "profile", "save", "gaming"],
+    );
+    result.unwrap();
+    assert!(sent.is_empty());
+
+    let sent = run(&["--config", config, "profile", "apply", "gaming"]);
+    assert_eq!(
+        writes(&sent),
+        vec![
+            (
+                FEATURE_REPORT,
+                zone_report(&[entry(LOWER, GREEN), entry(UPPER, RED)])
+            ),
+            (OUTPUT_REPORT, apply()),
+        ]
+    );
+
+    let (result, sent) = run_with(
+        MockTransport::new(),
+        &["--config", config, "profile", "apply", "missing"],
+    );
+    assert!(matches!(result, Err(Error::Config(_))));
+    assert!(sent.is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn effect_streams_frames_until_stopped() {
+    let sent = run(&[
+        "effect",
+        "strobe",
+        "--color",
+        "red",
+        "--period",
+        "200ms",
+        "--fps",
+        "10",
+        "--stop-after",
+        "200ms",
+    ]);
+
+    // frames at 0ms (on) and 100ms (off), unless the second one was late
+    let frames = writes(&sent);
+    assert!(frames.len() == 2 || frames.len() == 4, "{:?}", frames);
+    assert_eq!(
+        frames[..2],
+        [
+            (
+                FEATURE_REPORT,
+                zone_report(&[entry(LOWER, RED), entry(UPPER, RED)])
+            ),
+            (OUTPUT_REPORT, apply()),
+        ]
+    );
+    if frames.len() == 4 {
+        assert_eq!(
+            frames[2].1,
+            zone_report(&[entry(LOWER, BLACK), entry(UPPER, BLACK)])
+        );
+    }
+
+    let (result, sent) = run_with(MockTransport::new(), &["effect", "wave", "-p", "0s"]);
+    assert!(matches!(result, Err(Error::Cli(_))));
+    assert!(sent.is_empty());
+}
+
+#[test]
+fn timeline_is_uploaded_when_it_fits() {
+    let sent = run(&["timeline", "-u", "red:1s,blue:500ms", "-l", "00ff00:2s"]);
+    assert_eq!(
+        writes(&sent),
+        vec![
+            (
+                FEATURE_REPORT,
+                zone_report(&[
+                    timed_entry(LOWER, GREEN, 0xff, 0x32, 2000),
+                    timed_entry(UPPER, RED, 0xff, 0x32, 1000),
+                    timed_entry(UPPER, BLUE, 0xff, 0x32, 500),
+                ])
+            ),
+            (OUTPUT_REPORT, apply()),
+        ]
+    );
+
+    let sent = run(&[
+        "timeline",
+        "-u",
+        "red:1s:ease-in",
+        "--stream",
+        "--fps",
+        "10",
+        "--stop-after",
+        "100ms",
+    ]);
+    assert_eq!(
+        writes(&sent),
+        vec![
+            (
+                FEATURE_REPORT,
+                zone_report(&[entry(LOWER, RED), entry(UPPER, RED)])
+            ),
+            (OUTPUT_REPORT, apply()),
+        ]
+    );
+}
+
+#[test]
+fn fades_start_at_the_current_colors_and_end_with_the_change() {
+    let showing_red = || pad_showing(&[entry(LOWER, RED), entry(UPPER, RED)]);
+
+    let (result, sent) = run_with(
+        showing_red(),
+        &["solid", "blue", "-z", "upper", "--fade", "100ms"],
+    );
+    result.unwrap();
+
+    let frames = writes(&sent);
+    assert!(
+        frames.len() >= 4 && frames.len().is_multiple_of(2),
+        "{:?}",
+        frames
+    );
+    assert_eq!(
+        frames[..2],
+        [
+            (
+                FEATURE_REPORT,
+                zone_report(&[entry(LOWER, RED), entry(UPPER, RED)])
+            ),
+            (OUTPUT_REPORT, apply()),
+        ]
+    );
+    assert_eq!(
+        frames[frames.len() - 2..],
+        [
+            (
+                FEATURE_REPORT,
+                zone_report(&[entry(LOWER, RED), entry(UPPER, BLUE)])
+            ),
+            (OUTPUT_REPORT, apply()),
+        ]
+    );
+
+    let (result, sent) = run_with(
+        showing_red(),
+        &["set_intensity", "-i", "50", "--fade", "100ms"],
+    );
+    result.unwrap();
+
+    // without a state cache the intensity is unknown, so it is set at once
+    assert_eq!(writes(&sent), [(OUTPUT_REPORT, output_report(0x0c, 0x7f))]);
+}
+
+#[test]
+fn status_only_reads() {
+    let transport = pad_showing(&[entry(LOWER, GREEN), entry(UPPER, BLUE)]);
+
+    let (result, sent) = run_with(transport, &["status", "--json"]);
+    result.unwrap();
+
+    assert!(sent.is_empty());
+}
+
+#[test]
+fn invalid_values_send_nothing() {
+    let (result, sent) = run_with(MockTransport::new(), &["solid", "ff0000", "-d", "70s"]);
+
+    assert!(matches!(result, Err(Error::Cli(_))));
+    assert!(sent.is_empty());
+}
+
+#[test]
+fn reports_use_the_command_timeouts() {
+    let sent = run(&["solid", "ff0000"]);
+
+    let timeouts: Vec<_> = sent.iter().map(|report| report.timeout).collect();
+    assert_eq!(
+        timeouts,
+        vec![Duration::from_secs(5), Duration::from_secs(5)]
+    );
+}
+
+#[test]
+fn other_models_use_their_zones_and_reports() {
+    // three zones, a larger zone color report on interface 1 and no intensity
+    let capabilities = Capabilities {
+        zones: 3,
+        zone_report: ReportLayout {
+            index: 1,
+            len: 1024,
+            ..Capabilities::qck_prism_cloth().zone_report
+        },
+        intensity_report: None,
+        ..Capabilities::qck_prism_cloth()
+    };
+
+    let (result, sent) = run_on(
+        MockTransport::new(),
+        capabilities.clone(),
+        &["solid", "ff0000", "0000ff"],
+    );
+    result.unwrap();
+
+    let mut report = zone_report(&[entry(LOWER, BLUE), entry(UPPER, RED), entry(2, BLACK)]);
+    report.resize(1024, 0x00);
+    assert_eq!(sent[0].index, 1);
+    assert_eq!(
+        writes(&sent),
+        vec![(FEATURE_REPORT, report), (OUTPUT_REPORT, apply())]
+    );
+
+    let (result, sent) = run_on(
+        MockTransport::new(),
+        capabilities,
+        &["set_intensity", "-i", "50"],
+    );
+    assert!(matches!(result, Err(Error::Unsupported(_))));
+    assert!(sent.is_empty());
+}
@@ -0,0 +1,201 @@
+//! Serves mock pads on a private `dbus-daemon` and controls them through
+//! the `org.rqck.Pad1` and `org.rqck.Zone1` interfaces.
+//!
+//! The tests are skipped if `dbus-daemon` is not installed.
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rgame_linux::daemon::Daemon;
+use rgame_linux::dbus::{self, BUS_NAME};
+use rgame_linux::device::PadInfo;
+use rgame_linux::report::ZoneColorReport;
+use rgame_linux::transport::{MockTransport, SentReport, Transport, FEATURE_REPORT, OUTPUT_REPORT};
+use rgame_linux::{ColorRGB, QckPad, Result, BLACK_COLOR};
+use zbus::blocking::fdo::PropertiesProxy;
+use zbus::blocking::{Connection, Proxy};
+
+/// A private session bus, killed when dropped.
+struct Bus {
+    process: Child,
+    address: String,
+}
+
+impl Bus {
+    fn start() -> Option<Bus> {
+        let mut process = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+
+        let mut address = String::new();
+        BufReader::new(process.stdout.as_mut()?)
+            .read_line(&mut address)
+            .ok()?;
+
+        Some(Bus {
+            process,
+            address: address.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// A mock transport whose reports can be inspected while the daemon owns it.
+#[derive(Clone, Default)]
+struct SharedTransport(Arc<Mutex<MockTransport>>);
+
+impl SharedTransport {
+    fn take_sent(&self) -> Vec<(u16, u8)> {
+        self.0
+            .lock()
+            .unwrap()
+            .take_sent()
+            .into_iter()
+            .map(|report: SentReport| (report.value, report.data[0]))
+            .collect()
+    }
+}
+
+impl Transport for SharedTransport {
+    fn write_report(
+        &mut self,
+        value: u16,
+        index: u16,
+        data: &[u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        self.0
+            .lock()
+            .unwrap()
+            .write_report(value, index, data, timeout)
+    }
+
+    fn read_report(
+        &mut self,
+        value: u16,
+        index: u16,
+        data: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        self.0
+            .lock()
+            .unwrap()
+            .read_report(value, index, data, timeout)
+    }
+}
+
+fn with_bus<F: FnOnce(&Connection, &SharedTransport)>(test: F) {
+    let bus = match Bus::start() {
+        Some(bus) => bus,
+        None => {
+            eprintln!("skipping, cannot start dbus-daemon");
+            return;
+        }
+    };
+
+    let transport = SharedTransport::default();
+    let showing = ZoneColorReport::steady(BLACK_COLOR, ColorRGB::new(0xff, 0x52, 0x00));
+    transport
+        .0
+        .lock()
+        .unwrap()
+        .set_response(FEATURE_REPORT, &showing.to_bytes());
+
+    let info = PadInfo {
+        serial: Some("AAA".to_string()),
+        ..PadInfo::default()
+    };
+    let daemon = Arc::new(Daemon::new(vec![(info, QckPad::new(transport.clone()))]));
+    let _service = dbus::serve(daemon, Some(&bus.address)).unwrap();
+
+    let client = zbus::blocking::connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .build()
+        .unwrap();
+
+    test(&client, &transport);
+}
+
+fn proxy<'a>(client: &Connection, path: &'a str, interface: &'a str) -> Proxy<'a> {
+    Proxy::new(client, BUS_NAME, path, interface).unwrap()
+}
+
+#[test]
+fn exposes_the_pad_and_its_zones() {
+    with_bus(|client, _| {
+        let pad = proxy(client, "/org/rqck/pad0", "org.rqck.Pad1");
+        assert_eq!(pad.get_property::<String>("Device").unwrap(), "AAA");
+        assert_eq!(pad.get_property::<String>("Mode").unwrap(), "steady");
+        assert_eq!(pad.get_property::<i32>("Intensity").unwrap(), -1);
+
+        let upper = proxy(client, "/org/rqck/pad0/upper", "org.rqck.Zone1");
+        assert_eq!(upper.get_property::<String>("Name").unwrap(), "upper");
+        assert_eq!(upper.get_property::<String>("Color").unwrap(), "#ff5200");
+
+        let lower = proxy(client, "/org/rqck/pad0/lower", "org.rqck.Zone1");
+        assert_eq!(lower.get_property::<String>("Color").unwrap(), "#000000");
+    });
+}
+
+#[test]
+fn methods_write_to_the_pad() {
+    with_bus(|client, transport| {
+        let pad = proxy(client, "/org/rqck/pad0", "org.rqck.Pad1");
+
+        pad.call_method("SetIntensity", &(255u8,)).unwrap();
+        assert_eq!(transport.take_sent(), vec![(OUTPUT_REPORT, 0x0c)]);
+
+        pad.call_method("SetSteady", &("ff0000", "0000ff")).unwrap();
+        assert_eq!(
+            transport.take_sent(),
+            vec![(FEATURE_REPORT, 0x0e), (OUTPUT_REPORT, 0x0d)]
+        );
+
+        let upper = proxy(client, "/org/rqck/pad0/upper", "org.rqck.Zone1");
+        upper.call_method("Disable", &()).unwrap();
+        assert_eq!(
+            transport.take_sent(),
+            vec![(FEATURE_REPORT, 0x0e), (OUTPUT_REPORT, 0x0d)]
+        );
+
+        let error = pad.call_method("SetColor", &("#12345",)).unwrap_err();
+        assert!(
+            matches!(&error, zbus::Error::MethodError(name, _, _) if name.as_str() == "org.freedesktop.DBus.Error.InvalidArgs"),
+            "{}",
+            error
+        );
+        assert!(transport.take_sent().is_empty());
+    });
+}
+
+#[test]
+fn changes_emit_properties_changed() {
+    with_bus(|client, _| {
+        let properties = PropertiesProxy::builder(client)
+            .destination(BUS_NAME)
+            .unwrap()
+            .path("/org/rqck/pad0")
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut signals = properties.receive_properties_changed().unwrap();
+
+        let pad = proxy(client, "/org/rqck/pad0", "org.rqck.Pad1");
+        pad.call_method("SetColor", &("00ff00",)).unwrap();
+
+        let signal = signals.next().unwrap();
+        let args = signal.args().unwrap();
+        assert_eq!(args.interface_name().as_str(), "org.rqck.Pad1");
+        assert!(args.changed_properties().contains_key("Mode"));
+    });
+}
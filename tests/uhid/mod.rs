@@ -0,0 +1,246 @@
+//! A virtual QCK pad created through `/dev/uhid`.
+//!
+//! The kernel exposes the virtual pad like a real one, including the hidraw
+//! node and the sysfs entries used for discovery. The pad records every report
+//! it receives and answers GET_REPORT requests for the feature report with
+//! the last zone colors it was sent.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rgame_linux::hidraw::find_devices;
+use rgame_linux::{PRODUCT_ID, VENDOR_ID};
+
+const UHID_DESTROY: u32 = 1;
+const UHID_OUTPUT: u32 = 6;
+const UHID_GET_REPORT: u32 = 9;
+const UHID_GET_REPORT_REPLY: u32 = 10;
+const UHID_CREATE2: u32 = 11;
+const UHID_SET_REPORT: u32 = 13;
+const UHID_SET_REPORT_REPLY: u32 = 14;
+
+const UHID_FEATURE_REPORT: u8 = 0;
+
+const BUS_USB: u16 = 0x03;
+const EIO: u16 = 5;
+
+/// `sizeof(struct uhid_event)`, the largest member of the union is
+/// `uhid_create2_req`.
+const EVENT_LEN: usize = 4 + 128 + 64 + 64 + 2 + 2 + 4 * 4 + 4096;
+
+/// Vendor defined collection with an unnumbered 64 byte output report and an
+/// unnumbered 524 byte feature report, the sizes of the reports the pad
+/// accepts. The descriptor is not a dump of the real pad.
+const REPORT_DESCRIPTOR: &[u8] = &[
+    0x06, 0x00, 0xff, // Usage Page (Vendor Defined 0xFF00)
+    0x09, 0x01, // Usage (0x01)
+    0xa1, 0x01, // Collection (Application)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xff, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x40, //   Report Count (64)
+    0x09, 0x02, //   Usage (0x02)
+    0x91, 0x02, //   Output (Data, Var, Abs)
+    0x96, 0x0c, 0x02, //   Report Count (524)
+    0x09, 0x03, //   Usage (0x03)
+    0xb1, 0x02, //   Feature (Data, Var, Abs)
+    0xc0, // End Collection
+];
+
+/// A report the virtual pad received, without the report id hidraw puts in
+/// front of it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Received {
+    Output(Vec<u8>),
+    Feature(Vec<u8>),
+}
+
+#[derive(Default)]
+struct PadState {
+    received: Vec<Received>,
+    feature: Option<Vec<u8>>,
+}
+
+pub struct VirtualPad {
+    file: File,
+    state: Arc<Mutex<PadState>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl VirtualPad {
+    /// Creates the pad and waits for its hidraw node. Fails if `/dev/uhid`
+    /// cannot be opened.
+    pub fn create() -> io::Result<VirtualPad> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/uhid")?;
+
+        let mut event = event(UHID_CREATE2);
+        let name = b"SteelSeries QCK Prism Cloth (virtual)";
+        event[4..4 + name.len()].copy_from_slice(name);
+        event[260..262].copy_from_slice(&(REPORT_DESCRIPTOR.len() as u16).to_le_bytes());
+        event[262..264].copy_from_slice(&BUS_USB.to_le_bytes());
+        event[264..268].copy_from_slice(&u32::from(VENDOR_ID).to_le_bytes());
+        event[268..272].copy_from_slice(&u32::from(PRODUCT_ID).to_le_bytes());
+        event[280..280 + REPORT_DESCRIPTOR.len()].copy_from_slice(REPORT_DESCRIPTOR);
+        file.write_all(&event)?;
+
+        let state = Arc::new(Mutex::new(PadState::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let file = file.try_clone()?;
+            let state = state.clone();
+            let stop = stop.clone();
+            thread::spawn(move || handle_events(file, &state, &stop))
+        };
+
+        let pad = VirtualPad {
+            file,
+            state,
+            stop,
+            thread: Some(thread),
+        };
+        pad.wait_for_hidraw()?;
+
+        Ok(pad)
+    }
+
+    /// Everything the pad received so far, oldest first.
+    pub fn received(&self) -> Vec<Received> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// Sets the zone color report returned by GET_REPORT.
+    pub fn set_feature_report(&self, data: &[u8]) {
+        self.state.lock().unwrap().feature = Some(data.to_vec());
+    }
+
+    // virtual devices have no usb interface, this tells them apart from a
+    // real pad
+    fn wait_for_hidraw(&self) -> io::Result<()> {
+        let start = Instant::now();
+
+        while start.elapsed() < Duration::from_secs(5) {
+            let found = find_devices(VENDOR_ID, PRODUCT_ID)
+                .iter()
+                .any(|device| device.interface.is_none() && device.path.exists());
+
+            if found {
+                return Ok(());
+            }
+
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "hidraw node of the virtual pad did not show up",
+        ))
+    }
+}
+
+impl Drop for VirtualPad {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        let _ = self.file.write_all(&event(UHID_DESTROY));
+    }
+}
+
+fn event(event_type: u32) -> Vec<u8> {
+    let mut event = vec![0u8; EVENT_LEN];
+    event[0..4].copy_from_slice(&event_type.to_le_bytes());
+    event
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn handle_events(mut file: File, state: &Mutex<PadState>, stop: &AtomicBool) {
+    let mut event = vec![0u8; EVENT_LEN];
+
+    while !stop.load(Ordering::SeqCst) {
+        let mut poll = libc::pollfd {
+            fd: file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut poll, 1, 50) } <= 0 {
+            continue;
+        }
+
+        if file.read(&mut event).is_err() {
+            return;
+        }
+
+        let payload = &event[4..];
+        let reply = match u32::from_le_bytes([event[0], event[1], event[2], event[3]]) {
+            UHID_OUTPUT => {
+                let size = usize::from(u16_at(payload, 4096));
+                let data = payload[..size].iter().skip(1).cloned().collect::<Vec<_>>();
+
+                state.lock().unwrap().received.push(Received::Output(data));
+                None
+            }
+            UHID_SET_REPORT => {
+                let size = usize::from(u16_at(payload, 6));
+                let data = payload[8..8 + size]
+                    .iter()
+                    .skip(1)
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                let mut state = state.lock().unwrap();
+                state.feature = Some(data.clone());
+                state.received.push(Received::Feature(data));
+
+                let mut reply = event_reply(UHID_SET_REPORT_REPLY, payload);
+                reply[8..10].copy_from_slice(&0u16.to_le_bytes());
+                Some(reply)
+            }
+            UHID_GET_REPORT => {
+                let state = state.lock().unwrap();
+                let report = match payload[5] {
+                    UHID_FEATURE_REPORT => state.feature.as_ref(),
+                    _ => None,
+                };
+
+                let mut reply = event_reply(UHID_GET_REPORT_REPLY, payload);
+                match report {
+                    Some(report) => {
+                        // the report id goes in front of the data
+                        reply[10..12].copy_from_slice(&(report.len() as u16 + 1).to_le_bytes());
+                        reply[13..13 + report.len()].copy_from_slice(report);
+                    }
+                    None => reply[8..10].copy_from_slice(&EIO.to_le_bytes()),
+                }
+                Some(reply)
+            }
+            _ => None,
+        };
+
+        if let Some(reply) = reply {
+            if file.write_all(&reply).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// A reply event carrying the id of the request in `payload`.
+fn event_reply(event_type: u32, payload: &[u8]) -> Vec<u8> {
+    let mut reply = event(event_type);
+    reply[4..8].copy_from_slice(&payload[0..4]);
+    reply
+}
@@ -0,0 +1,196 @@
+//! Runs the `rgame_linux` binary against a virtual pad created through
+//! `/dev/uhid`, exercising device discovery and the hidraw backend without
+//! hardware.
+//!
+//! Creating uhid devices needs write access to `/dev/uhid`, usually root, so
+//! the tests are ignored by default. Run them with `cargo test --test
+//! uhid_cli -- --ignored`, they fail if no virtual pad can be created or a
+//! real pad is plugged in.
+
+mod uhid;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use rgame_linux::hidraw::find_devices;
+use rgame_linux::report::ZoneColorReport;
+use rgame_linux::{ColorRGB, BLACK_COLOR, PRODUCT_ID, VENDOR_ID};
+
+use uhid::{Received, VirtualPad};
+
+// the cli picks the first pad it finds, so only one virtual pad may exist
+static PAD_LOCK: Mutex<()> = Mutex::new(());
+
+const GREEN: ColorRGB = ColorRGB {
+    red: 0x00,
+    green: 0xff,
+    blue: 0x00,
+};
+const BLUE: ColorRGB = ColorRGB {
+    red: 0x00,
+    green: 0x00,
+    blue: 0xff,
+};
+const ORANGE: ColorRGB = ColorRGB {
+    red: 0xff,
+    green: 0x52,
+    blue: 0x00,
+};
+
+/// Removes the state cache of a test, even if it fails.
+struct CacheDir;
+
+impl Drop for CacheDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(cache_dir());
+    }
+}
+
+fn with_virtual_pad<F: FnOnce(&VirtualPad)>(test: F) {
+    let _lock = PAD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    assert!(
+        !find_devices(VENDOR_ID, PRODUCT_ID)
+            .iter()
+            .any(|device| device.interface.is_some()),
+        "a real pad is connected, unplug it to run the tests"
+    );
+
+    let pad = VirtualPad::create().unwrap_or_else(|e| panic!("cannot create a virtual pad: {}", e));
+    let _cache = CacheDir;
+    test(&pad);
+}
+
+fn cache_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("rqck-uhid-{}", std::process::id()))
+}
+
+/// The binary on the hidraw backend, without a running `rqckd` taking over.
+fn command(args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rgame_linux"));
+    command
+        .args(["--backend", "hidraw", "--no-daemon"])
+        .args(args)
+        .env("XDG_CACHE_HOME", cache_dir());
+    command
+}
+
+fn rgame_linux(args: &[&str]) -> Output {
+    let output = command(args).output().unwrap();
+
+    assert!(
+        output.status.success(),
+        "rgame_linux {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+fn apply() -> Received {
+    let mut data = vec![0u8; 64];
+    data[0] = 0x0d;
+    Received::Output(data)
+}
+
+#[test]
+#[ignore = "needs write access to /dev/uhid"]
+fn solid_reaches_the_pad() {
+    with_virtual_pad(|pad| {
+        rgame_linux(&["solid", "0000ff", "00ff00"]);
+
+        let report = ZoneColorReport::steady(GREEN, BLUE);
+        assert_eq!(
+            pad.received(),
+            vec![Received::Feature(report.to_bytes().to_vec()), apply()]
+        );
+    });
+}
+
+#[test]
+#[ignore = "needs write access to /dev/uhid"]
+fn disable_keeps_the_zone_read_back_from_the_pad() {
+    with_virtual_pad(|pad| {
+        pad.set_feature_report(&ZoneColorReport::steady(GREEN, ORANGE).to_bytes());
+
+        rgame_linux(&["disable", "lower"]);
+
+        let report = ZoneColorReport::steady(BLACK_COLOR, ORANGE);
+        assert_eq!(
+            pad.received(),
+            vec![Received::Feature(report.to_bytes().to_vec()), apply()]
+        );
+    });
+}
+
+#[test]
+#[ignore = "needs write access to /dev/uhid"]
+fn status_reads_colors_but_not_the_intensity() {
+    with_virtual_pad(|pad| {
+        pad.set_feature_report(&ZoneColorReport::steady(GREEN, BLUE).to_bytes());
+
+        rgame_linux(&["set_intensity", "-i", "100"]);
+        let output = rgame_linux(&["status", "--json"]);
+
+        let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(status["mode"], "steady");
+        assert_eq!(status["intensity"], serde_json::Value::Null);
+        assert_eq!(status["zones"][0]["zone"], "lower");
+        assert_eq!(status["zones"][0]["key_frames"][0]["color"], "#00ff00");
+        assert_eq!(status["zones"][1]["key_frames"][0]["color"], "#0000ff");
+
+        let mut intensity = vec![0u8; 64];
+        intensity[0] = 0x0c;
+        intensity[2] = 0xff;
+        assert_eq!(pad.received(), vec![Received::Output(intensity)]);
+    });
+}
+
+#[test]
+#[ignore = "needs write access to /dev/uhid"]
+fn effects_stopped_with_a_signal_restore_the_configuration() {
+    with_virtual_pad(|pad| {
+        let configuration = ZoneColorReport::steady(GREEN, ORANGE).to_bytes().to_vec();
+        pad.set_feature_report(&configuration);
+
+        let child = command(&["effect", "rainbow"]).spawn().unwrap();
+        thread::sleep(Duration::from_millis(300));
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+
+        let received = pad.received();
+        assert!(received.len() > 2);
+        assert_eq!(
+            received[received.len() - 2..],
+            [Received::Feature(configuration), apply()]
+        );
+    });
+}
+
+#[test]
+#[ignore = "needs write access to /dev/uhid"]
+fn list_shows_the_pad_and_device_selects_it() {
+    with_virtual_pad(|pad| {
+        let device = find_devices(VENDOR_ID, PRODUCT_ID)
+            .into_iter()
+            .find(|device| device.interface.is_none())
+            .unwrap();
+        let path = device.path.to_str().unwrap();
+
+        let output = rgame_linux(&["list"]);
+        assert!(String::from_utf8_lossy(&output.stdout).contains(path));
+
+        rgame_linux(&["--device", path, "disable", "upper", "lower"]);
+
+        let report = ZoneColorReport::steady(BLACK_COLOR, BLACK_COLOR);
+        assert_eq!(
+            pad.received(),
+            vec![Received::Feature(report.to_bytes().to_vec()), apply()]
+        );
+    });
+}